@@ -95,6 +95,161 @@ fn generate_consts() {
     write!(file, "{}", array_string(rook_offsets.to_vec(), "usize", "ROOK_OFFSETS")).expect("Couldnt write rook_offsets!");
     write!(file, "{}", array_string(bishop_offsets.to_vec(), "usize", "BISHOP_OFFSETS")).expect("Couldnt write bishop_offsets!");
     write!(file, "{}", array_string(attacks.to_vec(), "u64", "SLIDING_ATTACKS")).expect("Couldnt write sliding_attacks!");
+
+    //Endgames
+    let kpk = generate_kpk_bitbase(&generate_king_attacks(), &generate_pawn_attacks(true));
+    write!(file, "{}", array_string(kpk, "u64", "KPK_BITBASE")).expect("Couldnt write kpk_bitbase!");
+}
+
+//KPK positions with white as the side with the pawn, which is on the files a-d and the ranks 2-7.
+//Squares go from a8 = 0 to h1 = 63 like in the engine, so the pawn moves towards the lower squares.
+//Indexed as in attack_tables::probe_kpk
+const KPK_SIZE: usize = 24 * 64 * 64 * 2;
+
+fn kpk_index(white_to_move: bool, white_king: usize, black_king: usize, pawn: usize) -> usize {
+    let pawn_index = (pawn / 8 - 1) * 4 + pawn % 8;
+    ((pawn_index * 64 + white_king) * 64 + black_king) * 2 + white_to_move as usize
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum KpkResult {
+    Invalid,
+    Unknown,
+    Draw,
+    Win
+}
+
+//Win/draw for every KPK position by retrograde analysis, one bit per position which is set for wins.
+//Positions are first classified by the immediate outcome, and then from their successors until nothing changes.
+//What is still unknown after that is a draw
+fn generate_kpk_bitbase(king_attacks: &[u64; 64], pawn_attacks: &[u64; 64]) -> Vec<u64> {
+    let mut positions = Vec::with_capacity(KPK_SIZE);
+    for pawn in 8..56 {
+        if pawn % 8 > 3 {
+            continue;
+        }
+        for white_king in 0..64 {
+            for black_king in 0..64 {
+                for white_to_move in [false, true] {
+                    positions.push((white_to_move, white_king, black_king, pawn));
+                }
+            }
+        }
+    }
+
+    let mut results = vec![KpkResult::Unknown; KPK_SIZE];
+    for &(white_to_move, white_king, black_king, pawn) in &positions {
+        results[kpk_index(white_to_move, white_king, black_king, pawn)] =
+            initial_kpk_result(white_to_move, white_king, black_king, pawn, king_attacks, pawn_attacks);
+    }
+
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for &(white_to_move, white_king, black_king, pawn) in &positions {
+            let index = kpk_index(white_to_move, white_king, black_king, pawn);
+            if results[index] != KpkResult::Unknown {
+                continue;
+            }
+
+            let result = if white_to_move {
+                white_kpk_result(white_king, black_king, pawn, &results, king_attacks)
+            } else {
+                black_kpk_result(white_king, black_king, pawn, &results, king_attacks, pawn_attacks)
+            };
+
+            if result != KpkResult::Unknown {
+                results[index] = result;
+                changed = true;
+            }
+        }
+    }
+
+    let mut bits = vec![0; KPK_SIZE / 64];
+    for (index, result) in results.iter().enumerate() {
+        if *result == KpkResult::Win {
+            bits[index / 64] |= 1 << (index % 64);
+        }
+    }
+    bits
+}
+
+fn initial_kpk_result(white_to_move: bool, white_king: usize, black_king: usize, pawn: usize, king_attacks: &[u64; 64], pawn_attacks: &[u64; 64]) -> KpkResult {
+    //Kings next to each other, pieces on the same square, or black in check with white to move
+    if white_king == black_king || white_king == pawn || black_king == pawn
+        || king_attacks[white_king] & 1 << black_king != 0
+        || (white_to_move && pawn_attacks[pawn] & 1 << black_king != 0) {
+        return KpkResult::Invalid;
+    }
+
+    //The pawn queens and can't be taken right away
+    if white_to_move && pawn / 8 == 1 {
+        let queening_square = pawn - 8;
+        if queening_square != white_king && queening_square != black_king
+            && (king_attacks[black_king] & 1 << queening_square == 0 || king_attacks[white_king] & 1 << queening_square != 0) {
+            return KpkResult::Win;
+        }
+    }
+
+    //Stalemate, or the black king takes the undefended pawn
+    if !white_to_move {
+        let black_moves = king_attacks[black_king] & !(king_attacks[white_king] | pawn_attacks[pawn]);
+        if black_moves == 0 || black_moves & 1 << pawn != 0 {
+            return KpkResult::Draw;
+        }
+    }
+
+    KpkResult::Unknown
+}
+
+//Won if any move wins, drawn if every move draws. Promotions are covered by the initial classification
+fn white_kpk_result(white_king: usize, black_king: usize, pawn: usize, results: &[KpkResult], king_attacks: &[u64; 64]) -> KpkResult {
+    let mut successors = Vec::new();
+
+    let mut king_moves = king_attacks[white_king] & !king_attacks[black_king] & !(1 << pawn);
+    while king_moves != 0 {
+        let to = king_moves.trailing_zeros() as usize;
+        king_moves &= king_moves - 1;
+        successors.push(kpk_index(false, to, black_king, pawn));
+    }
+
+    let push = pawn - 8;
+    if pawn / 8 > 1 && push != white_king && push != black_king {
+        successors.push(kpk_index(false, white_king, black_king, push));
+
+        let double_push = pawn - 16;
+        if pawn / 8 == 6 && double_push != white_king && double_push != black_king {
+            successors.push(kpk_index(false, white_king, black_king, double_push));
+        }
+    }
+
+    if successors.iter().any(|s| results[*s] == KpkResult::Win) {
+        KpkResult::Win
+    } else if successors.iter().all(|s| results[*s] == KpkResult::Draw) {
+        KpkResult::Draw
+    } else {
+        KpkResult::Unknown
+    }
+}
+
+//Drawn if any move draws, won if every move loses. Taking the pawn is covered by the initial classification
+fn black_kpk_result(white_king: usize, black_king: usize, pawn: usize, results: &[KpkResult], king_attacks: &[u64; 64], pawn_attacks: &[u64; 64]) -> KpkResult {
+    let mut successors = Vec::new();
+
+    let mut king_moves = king_attacks[black_king] & !(king_attacks[white_king] | pawn_attacks[pawn]);
+    while king_moves != 0 {
+        let to = king_moves.trailing_zeros() as usize;
+        king_moves &= king_moves - 1;
+        successors.push(kpk_index(true, white_king, to, pawn));
+    }
+
+    if successors.iter().any(|s| results[*s] == KpkResult::Draw) {
+        KpkResult::Draw
+    } else if successors.iter().all(|s| results[*s] == KpkResult::Win) {
+        KpkResult::Win
+    } else {
+        KpkResult::Unknown
+    }
 }
 
 
@@ -49,6 +49,13 @@ pub fn get_queen_attack_table(square: u8, occ: Bitboard) -> Bitboard {
     )
 }
 
+///Whether white wins the KPK position. White has the pawn, which has to be on the files a-d and ranks 2-7
+pub fn probe_kpk(white_to_move: bool, white_king: u8, black_king: u8, pawn: u8) -> bool {
+    let pawn_index = (pawn as usize / 8 - 1) * 4 + pawn as usize % 8;
+    let index = ((pawn_index * 64 + white_king as usize) * 64 + black_king as usize) * 2 + white_to_move as usize;
+    KPK_BITBASE[index / 64] & 1 << (index % 64) != 0
+}
+
 fn pext(bits: u64, mask: u64) -> u64 {
     unsafe { core::arch::x86_64::_pext_u64(bits, mask) }
 }
\ No newline at end of file
@@ -1,6 +1,6 @@
 use super::utilities::*;
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Default)]
 pub struct Bitboard {
     bits: u64,
 }
@@ -0,0 +1,278 @@
+use std::{fs::File, io::{BufWriter, Write}, sync::{Mutex, atomic::{AtomicUsize, Ordering}}, thread, time::SystemTime};
+
+use rand::{Rng, SeedableRng, rngs::StdRng};
+
+use super::*;
+
+const MAX_GAME_PLIES: usize = 400;
+//Games are adjudicated as wins once a side has been this far ahead for WIN_ADJUDICATION_PLIES plies in a row
+const WIN_ADJUDICATION_SCORE: i32 = 2000;
+const WIN_ADJUDICATION_PLIES: usize = 8;
+
+#[derive(Debug, PartialEq)]
+pub struct DatagenConfig {
+    pub games: usize,
+    pub threads: usize,
+    ///Search depth per move, -1 for no limit
+    pub depth: i8,
+    ///Nodes per move, 0 for no limit
+    pub nodes: u64,
+    ///Number of random moves played from the start position
+    pub random_plies: usize,
+    pub seed: u64,
+    ///File the games are archived to as PGN
+    pub pgn: Option<String>,
+}
+
+impl Default for DatagenConfig {
+    fn default() -> Self {
+        Self { games: 100, threads: 1, depth: 8, nodes: 0, random_plies: 8, seed: 0, pgn: None }
+    }
+}
+
+///A position recorded during self-play, with the search score from white's point of view
+pub struct DataPoint {
+    pub fen: String,
+    pub score: i32,
+}
+
+///The recorded positions of a game and its result from white's point of view: 1.0, 0.5 or 0.0
+pub struct GameRecord {
+    pub positions: Vec<DataPoint>,
+    pub result: f64,
+    ///Every move from the start position, including the random opening
+    pub moves: Vec<Move>
+}
+
+impl GameRecord {
+    ///One line per position: "fen | score | result"
+    pub fn write(&self, writer: &mut impl Write) -> std::io::Result<()> {
+        for position in &self.positions {
+            writeln!(writer, "{} | {} | {:.1}", position.fen, position.score, self.result)?;
+        }
+        Ok(())
+    }
+
+    pub fn to_pgn(&self, round: usize) -> PgnGame {
+        let result = if self.result == 1.0 { "1-0" } else if self.result == 0.0 { "0-1" } else { "1/2-1/2" };
+        let mut pgn = PgnGame::new(&Game::new_from_start_pos(), &self.moves, result);
+        pgn.set_tag("Event", "JENCE self-play");
+        pgn.set_tag("Round", &round.to_string());
+        pgn.set_tag("White", "JENCE");
+        pgn.set_tag("Black", "JENCE");
+        pgn
+    }
+}
+
+///Plays games on all threads and writes them to the output in game order.
+///Each game is seeded from the seed and its index, so the output doesn't depend on the number of threads
+pub fn run_datagen(config: &DatagenConfig, evaluator: &Evaluator, output: &str) -> Result<usize, String> {
+    let file = File::create(output).map_err(|e| format!("Could not create '{}': {}", output, e))?;
+    let pgn_file = match &config.pgn {
+        Some(path) => Some(File::create(path).map_err(|e| format!("Could not create '{}': {}", path, e))?),
+        None => None
+    };
+
+    let next_game = AtomicUsize::new(0);
+    let output_state = Mutex::new(DatagenOutput {
+        writer: BufWriter::new(file),
+        pgn_writer: pgn_file.map(BufWriter::new),
+        pending: (0..config.games).map(|_| None).collect(),
+        games_written: 0,
+        positions_written: 0
+    });
+    let start = SystemTime::now();
+
+    thread::scope(|scope| {
+        let handles: Vec<_> = (0..config.threads.max(1)).map(|_| {
+            scope.spawn(|| -> Result<(), String> {
+                let mut tt = TranspositionTable::new();
+
+                loop {
+                    let index = next_game.fetch_add(1, Ordering::Relaxed);
+                    if index >= config.games {
+                        return Ok(());
+                    }
+
+                    let record = play_game(config, evaluator, index, &mut tt);
+
+                    let mut state = output_state.lock().unwrap();
+                    state.pending[index] = Some(record);
+                    state.write_finished(start).map_err(|e| format!("Could not write the output: {}", e))?;
+                }
+            })
+        }).collect();
+
+        handles.into_iter().try_for_each(|h| h.join().unwrap())
+    })?;
+
+    let mut state = output_state.lock().unwrap();
+    state.writer.flush().map_err(|e| format!("Could not write '{}': {}", output, e))?;
+    if let Some(pgn_writer) = &mut state.pgn_writer {
+        pgn_writer.flush().map_err(|e| format!("Could not write the PGN: {}", e))?;
+    }
+
+    Ok(state.positions_written)
+}
+
+struct DatagenOutput {
+    writer: BufWriter<File>,
+    pgn_writer: Option<BufWriter<File>>,
+    //Finished games waiting for the games before them
+    pending: Vec<Option<GameRecord>>,
+    games_written: usize,
+    positions_written: usize
+}
+
+impl DatagenOutput {
+    ///Writes every finished game that is next in line
+    fn write_finished(&mut self, start: SystemTime) -> std::io::Result<()> {
+        while self.games_written < self.pending.len() {
+            let record = match self.pending[self.games_written].take() {
+                Some(record) => record,
+                None => break
+            };
+            record.write(&mut self.writer)?;
+            if let Some(pgn_writer) = &mut self.pgn_writer {
+                pgn_writer.write_all(record.to_pgn(self.games_written + 1).to_pgn().as_bytes())?;
+            }
+            self.positions_written += record.positions.len();
+            self.games_written += 1;
+
+            if self.games_written.is_multiple_of(10) || self.games_written == self.pending.len() {
+                println!(" Games: {}\t Positions: {}\t Time: {}s", self.games_written, self.positions_written, start.elapsed().unwrap().as_secs());
+            }
+        }
+        Ok(())
+    }
+}
+
+fn legal_moves(game: &mut Game) -> Vec<Move> {
+    let moves = generate_moves(game, MoveTypes::All);
+    (0..moves.len()).map(|i| moves.get(i)).filter(|m| make_move(&mut game.clone(), m)).collect()
+}
+
+///Plays a game from a random opening. Only quiet positions, where the side to move isn't in check and the best move
+///isn't a capture or promotion, are recorded
+pub fn play_game(config: &DatagenConfig, evaluator: &Evaluator, index: usize, tt: &mut TranspositionTable) -> GameRecord {
+    let mut rng = StdRng::seed_from_u64(config.seed ^ (index as u64).wrapping_mul(0x9E3779B97F4A7C15));
+
+    //Random opening, retried if it ends the game
+    let mut game;
+    let mut played = Vec::new();
+    loop {
+        game = Game::new_from_start_pos();
+        played.clear();
+        for _ in 0..config.random_plies {
+            let moves = legal_moves(&mut game);
+            if moves.is_empty() { break; }
+            let mov = moves[rng.gen_range(0..moves.len())];
+            make_move(&mut game, &mov);
+            played.push(mov);
+        }
+        if !legal_moves(&mut game).is_empty() { break; }
+    }
+
+    tt.clear();
+    let mut rep_table = RepetitionTable::new();
+    rep_table.push(game.zobrist_hash, game.half_moves);
+    //Positions since the last irreversible move, for detecting threefold repetitions
+    let mut history = vec![game.zobrist_hash];
+
+    let mut positions = Vec::new();
+    let mut plies = 0;
+    let mut winning_plies = 0;
+    let mut losing_plies = 0;
+
+    let result = loop {
+        let moves = legal_moves(&mut game);
+        if moves.is_empty() {
+            if game.is_in_check(game.active_player) {
+                break if game.active_player == Color::White { 0.0 } else { 1.0 };
+            }
+            break 0.5;
+        }
+
+        if game.half_moves >= 100 || game.is_insufficient_material() || plies >= MAX_GAME_PLIES
+            || history.iter().filter(|h| **h == game.zobrist_hash).count() >= 3 {
+            break 0.5;
+        }
+
+        let search_result = search_silently(&mut game, config.depth, -1, config.nodes, tt, &mut rep_table, evaluator);
+        let best_move = if moves.contains(&search_result.best_move) { search_result.best_move } else { moves[0] };
+        let white_score = if game.active_player == Color::White { search_result.score } else { -search_result.score };
+
+        //Decisive scores end the game early
+        if search_result.score.abs() > MATE_BOUND {
+            break if white_score > 0 { 1.0 } else { 0.0 };
+        }
+        if white_score > WIN_ADJUDICATION_SCORE { winning_plies += 1 } else { winning_plies = 0 }
+        if white_score < -WIN_ADJUDICATION_SCORE { losing_plies += 1 } else { losing_plies = 0 }
+        if winning_plies >= WIN_ADJUDICATION_PLIES { break 1.0 }
+        if losing_plies >= WIN_ADJUDICATION_PLIES { break 0.0 }
+
+        if !game.is_in_check(game.active_player) && !best_move.is_capture() && best_move.promotion() == Piece::None as u8 {
+            positions.push(DataPoint { fen: game.to_fen(), score: white_score });
+        }
+
+        make_search_move(&mut game, &best_move, &mut rep_table, None);
+        played.push(best_move);
+        plies += 1;
+        if game.half_moves == 0 {
+            history.clear();
+        }
+        history.push(game.zobrist_hash);
+    };
+
+    GameRecord { positions, result, moves: played }
+}
+
+#[cfg(test)]
+mod datagen_tests {
+    use crate::*;
+
+    fn config() -> DatagenConfig {
+        DatagenConfig { games: 2, threads: 2, depth: 2, nodes: 0, random_plies: 8, seed: 7, pgn: None }
+    }
+
+    #[test]
+    pub fn games_are_deterministic() {
+        let evaluator = Evaluator::new();
+        let mut tt = TranspositionTable::new();
+        let first = play_game(&config(), &evaluator, 1, &mut tt);
+        let second = play_game(&config(), &evaluator, 1, &mut tt);
+
+        assert!(!first.positions.is_empty());
+        assert_eq!(first.result, second.result);
+        assert_eq!(first.positions.iter().map(|p| &p.fen).collect::<Vec<_>>(), second.positions.iter().map(|p| &p.fen).collect::<Vec<_>>());
+    }
+
+    #[test]
+    pub fn records_are_tuner_input() {
+        let evaluator = Evaluator::new();
+        let mut tt = TranspositionTable::new();
+        let record = play_game(&config(), &evaluator, 0, &mut tt);
+
+        let mut output = Vec::new();
+        record.write(&mut output).unwrap();
+        let text = String::from_utf8(output).unwrap();
+
+        for line in text.lines() {
+            let position = parse_tuning_position(line).unwrap();
+            assert_eq!(position.result, record.result);
+        }
+    }
+
+    #[test]
+    pub fn games_are_archived_as_pgn() {
+        let evaluator = Evaluator::new();
+        let mut tt = TranspositionTable::new();
+        let record = play_game(&config(), &evaluator, 0, &mut tt);
+
+        let pgn = record.to_pgn(1).to_pgn();
+        let read = PgnReader::new(&pgn, false).next().unwrap().unwrap();
+        assert_eq!(read.tag("Event"), Some("JENCE self-play"));
+        assert_eq!(read.moves.len(), record.moves.len());
+        assert_eq!(read.result, if record.result == 1.0 { "1-0" } else if record.result == 0.0 { "0-1" } else { "1/2-1/2" });
+    }
+}
@@ -0,0 +1,398 @@
+use super::*;
+
+///Score for endgames that are won with correct play, on top of which the evaluators add their own terms
+pub const KNOWN_WIN: i32 = 1000;
+///Scale factors are out of this
+pub const SCALE_NORMAL: i32 = 64;
+
+//Piece values used to compare the material of the sides, [pawn, knight, bishop, rook, queen]
+const SIMPLE_VALUES: [i32; 5] = [100, 300, 300, 500, 900];
+
+///Evaluates an endgame from the strong side's point of view
+type EndgameEvaluator = fn(&Game, Color, &EvalParams) -> i32;
+
+///A specialized evaluator and the material it is used for
+pub struct Endgame {
+    pub name: &'static str,
+    key: u64,
+    evaluate: EndgameEvaluator
+}
+
+///Specialized evaluators, keyed by the material of the strong side followed by the weak side
+pub const ENDGAMES: [Endgame; 3] = [
+    Endgame { name: "KPK", key: signature_key("KPK"), evaluate: evaluate_kpk },
+    Endgame { name: "KBNK", key: signature_key("KBNK"), evaluate: evaluate_kbnk },
+    Endgame { name: "KRKP", key: signature_key("KRKP"), evaluate: evaluate_krkp },
+];
+
+///Number of pawns, knights, bishops, rooks and queens of each side, [white, black]
+pub struct Material {
+    pub counts: [[u32; 5]; 2]
+}
+
+impl Material {
+    pub fn new(game: &Game) -> Self {
+        let side_counts = |offset: usize| std::array::from_fn(|piece_type| game.bitboards[piece_type + offset].to_u64().count_ones());
+        Self { counts: [side_counts(0), side_counts(6)] }
+    }
+
+    ///Material of the strong side followed by the weak side, 4 bits per piece type
+    pub fn key(&self, strong: Color) -> u64 {
+        let side_key = |counts: &[u32; 5]| counts.iter().enumerate().fold(0, |key, (i, count)| key | (*count as u64) << (4 * i));
+        let (strong, weak) = side_indices(strong);
+        side_key(&self.counts[strong]) << 20 | side_key(&self.counts[weak])
+    }
+
+    fn non_pawn_material(&self, side: usize) -> i32 {
+        (1..5).map(|piece_type| self.counts[side][piece_type] as i32 * SIMPLE_VALUES[piece_type]).sum()
+    }
+
+    fn only_king(&self, side: usize) -> bool {
+        self.counts[side].iter().all(|count| *count == 0)
+    }
+}
+
+///The key of a signature like "KBNK", built the same way as Material::key
+const fn signature_key(signature: &str) -> u64 {
+    let bytes = signature.as_bytes();
+    let mut keys = [0u64; 2];
+    let mut side = 0;
+
+    //The first character is the strong king
+    let mut i = 1;
+    while i < bytes.len() {
+        let piece_type = match bytes[i] {
+            b'K' => { side = 1; i += 1; continue; },
+            b'P' => 0,
+            b'N' => 1,
+            b'B' => 2,
+            b'R' => 3,
+            b'Q' => 4,
+            _ => std::panic!("Invalid endgame signature")
+        };
+        keys[side] += 1 << (4 * piece_type);
+        i += 1;
+    }
+
+    keys[0] << 20 | keys[1]
+}
+
+///Applies endgame knowledge to a tapered score from white's point of view
+pub fn evaluate_endgame(game: &Game, score: i32, params: &EvalParams) -> i32 {
+    match probe_endgame(game, score, params) {
+        Some((_, score)) => score,
+        None => score
+    }
+}
+
+///The name of the endgame knowledge that applies to the position and the score it gives, from white's point of view.
+///Specialized evaluators come first, then lone kings and at last the scale factors of drawish endings
+pub fn probe_endgame(game: &Game, score: i32, params: &EvalParams) -> Option<(&'static str, i32)> {
+    let material = Material::new(game);
+
+    for strong in [Color::White, Color::Black] {
+        let key = material.key(strong);
+        if let Some(endgame) = ENDGAMES.iter().find(|e| e.key == key) {
+            return Some((endgame.name, from_white(strong, (endgame.evaluate)(game, strong, params))));
+        }
+    }
+
+    for strong in [Color::White, Color::Black] {
+        let (strong_side, weak_side) = side_indices(strong);
+        if !material.only_king(weak_side) || material.counts[strong_side][0] > 0 {
+            continue;
+        }
+
+        if !has_mating_material(game, &material, strong) {
+            return Some(("insufficient material", 0));
+        }
+        return Some(("mop-up", score + from_white(strong, mop_up(game, strong))));
+    }
+
+    let strong = if score > 0 { Color::White } else if score < 0 { Color::Black } else { return None };
+    let (name, scale) = scale_factor(game, &material, strong)?;
+    Some((name, score * scale / SCALE_NORMAL))
+}
+
+///Scale factor for the side that is ahead, when the material makes the position drawish
+fn scale_factor(game: &Game, material: &Material, strong: Color) -> Option<(&'static str, i32)> {
+    let (strong_side, weak_side) = side_indices(strong);
+    let strong_counts = material.counts[strong_side];
+    let weak_counts = material.counts[weak_side];
+
+    //Without pawns, being up a minor piece or less is rarely enough
+    if strong_counts[0] == 0 && material.non_pawn_material(strong_side) - material.non_pawn_material(weak_side) <= SIMPLE_VALUES[2] {
+        return Some(("no pawns", if material.non_pawn_material(strong_side) < SIMPLE_VALUES[3] { 0 } else { 16 }));
+    }
+
+    //Bishop and rook pawns against a bare king, where the bishop can't control the queening square and the defending king gets there first
+    let strong_pawns = pieces(game, Piece::WhitePawn, strong);
+    if strong_counts[1..5] == [0, 1, 0, 0] && strong_counts[0] > 0 && material.only_king(weak_side) {
+        for file in [0, 7] {
+            if strong_pawns & !FILE_MASKS[file] != 0 {
+                continue;
+            }
+
+            let queening_square = if strong == Color::White { file as u8 } else { 56 + file as u8 };
+            let bishop_is_light = pieces(game, Piece::WhiteBishop, strong) & LIGHT_SQUARES != 0;
+            let queening_is_light = 1 << queening_square & LIGHT_SQUARES != 0;
+            let weak_king = pieces(game, Piece::WhiteKing, opposite_color(strong)).trailing_zeros() as u8;
+
+            if bishop_is_light != queening_is_light && distance(weak_king, queening_square) <= 1 {
+                return Some(("wrong colored bishop", 0));
+            }
+        }
+    }
+
+    //Opposite colored bishops, most drawish when nothing else is left
+    if strong_counts[2] == 1 && weak_counts[2] == 1 {
+        let strong_light = pieces(game, Piece::WhiteBishop, strong) & LIGHT_SQUARES != 0;
+        let weak_light = pieces(game, Piece::WhiteBishop, opposite_color(strong)) & LIGHT_SQUARES != 0;
+
+        if strong_light != weak_light {
+            let only_bishops = strong_counts[1..5] == [0, 1, 0, 0] && weak_counts[1..5] == [0, 1, 0, 0];
+            return Some(("opposite colored bishops", if only_bishops { 24 } else { 48 }));
+        }
+    }
+
+    None
+}
+
+///A queen, a rook, bishops on both colors, a bishop and a knight or three knights
+fn has_mating_material(game: &Game, material: &Material, strong: Color) -> bool {
+    let (side, _) = side_indices(strong);
+    let counts = material.counts[side];
+    let bishops = pieces(game, Piece::WhiteBishop, strong);
+
+    counts[4] > 0 || counts[3] > 0
+        || (counts[2] > 0 && counts[1] > 0)
+        || (bishops & LIGHT_SQUARES != 0 && bishops & !LIGHT_SQUARES != 0)
+        || counts[1] >= 3
+}
+
+///Drives the lone king to the edge and brings the strong king closer, from the strong side's point of view
+fn mop_up(game: &Game, strong: Color) -> i32 {
+    let strong_king = pieces(game, Piece::WhiteKing, strong).trailing_zeros() as u8;
+    let weak_king = pieces(game, Piece::WhiteKing, opposite_color(strong)).trailing_zeros() as u8;
+
+    KNOWN_WIN / 2 + 10 * center_distance(weak_king) + 5 * (14 - manhattan_distance(strong_king, weak_king))
+}
+
+///King and pawn against king, looked up in the bitbase built by build.rs
+fn evaluate_kpk(game: &Game, strong: Color, params: &EvalParams) -> i32 {
+    //The bitbase has the pawn as white's and on the files a-d
+    let flip = |square: u8| if strong == Color::White { square } else { MIRRORED[square as usize] as u8 };
+    let pawn = flip(pieces(game, Piece::WhitePawn, strong).trailing_zeros() as u8);
+    let mirror = |square: u8| if pawn % 8 > 3 { square ^ 7 } else { square };
+
+    let strong_king = mirror(flip(pieces(game, Piece::WhiteKing, strong).trailing_zeros() as u8));
+    let weak_king = mirror(flip(pieces(game, Piece::WhiteKing, opposite_color(strong)).trailing_zeros() as u8));
+
+    if probe_kpk(game.active_player == strong, strong_king, weak_king, mirror(pawn)) {
+        KNOWN_WIN + params.material_eg[0] + 10 * LOOKUP_RANK[pawn as usize] as i32
+    } else {
+        0
+    }
+}
+
+///Bishop and knight mate: the lone king has to be driven to a corner of the bishop's color
+fn evaluate_kbnk(game: &Game, strong: Color, _params: &EvalParams) -> i32 {
+    let strong_king = pieces(game, Piece::WhiteKing, strong).trailing_zeros() as u8;
+    let weak_king = pieces(game, Piece::WhiteKing, opposite_color(strong)).trailing_zeros() as u8;
+
+    //a8 and h1 are light, a1 and h8 are dark
+    let corners = if pieces(game, Piece::WhiteBishop, strong) & LIGHT_SQUARES != 0 { [0, 63] } else { [56, 7] };
+    let corner_distance = corners.iter().map(|corner| distance(weak_king, *corner)).min().unwrap();
+
+    KNOWN_WIN + 20 * (7 - corner_distance) + 5 * (7 - distance(strong_king, weak_king))
+}
+
+///Rook against pawn. Won when the strong king stops the pawn or the weak king is too far away, otherwise
+///drawish depending on how far the pawn is from queening compared to the kings
+fn evaluate_krkp(game: &Game, strong: Color, params: &EvalParams) -> i32 {
+    //Squares are flipped so the strong side plays up the board, with the pawn moving towards rank 1
+    let flip = |square: u8| if strong == Color::White { square } else { MIRRORED[square as usize] as u8 };
+    let strong_king = flip(pieces(game, Piece::WhiteKing, strong).trailing_zeros() as u8);
+    let weak_king = flip(pieces(game, Piece::WhiteKing, opposite_color(strong)).trailing_zeros() as u8);
+    let rook = flip(pieces(game, Piece::WhiteRook, strong).trailing_zeros() as u8);
+    let pawn = flip(pieces(game, Piece::WhitePawn, opposite_color(strong)).trailing_zeros() as u8);
+
+    let queening_square = 56 + pawn % 8;
+    let pawn_push = pawn + 8;
+    let weak_to_move = (game.active_player != strong) as i32;
+    let rook_value = params.material_eg[3];
+
+    //The strong king is in front of the pawn
+    if strong_king % 8 == pawn % 8 && LOOKUP_RANK[strong_king as usize] < LOOKUP_RANK[pawn as usize] {
+        return rook_value - distance(strong_king, pawn);
+    }
+
+    //The weak king is too far away to support the pawn
+    if distance(weak_king, pawn) >= 3 + weak_to_move && distance(weak_king, rook) >= 3 {
+        return rook_value - distance(strong_king, pawn);
+    }
+
+    //The pawn is far advanced and supported, while the strong king is too far away
+    if LOOKUP_RANK[weak_king as usize] <= 2 && distance(weak_king, pawn) == 1
+        && LOOKUP_RANK[strong_king as usize] >= 3 && distance(strong_king, pawn) > 3 - weak_to_move {
+        return 80 - 8 * distance(strong_king, pawn);
+    }
+
+    200 - 8 * (distance(strong_king, pawn_push) - distance(weak_king, pawn_push) - distance(pawn, queening_square))
+}
+
+///The bitboard of the given piece type for a side, where the piece is given as white's
+fn pieces(game: &Game, white_piece: Piece, side: Color) -> u64 {
+    let offset = if side == Color::White { 0 } else { 6 };
+    game.bitboards[white_piece as usize + offset].to_u64()
+}
+
+fn side_indices(strong: Color) -> (usize, usize) {
+    if strong == Color::White { (0, 1) } else { (1, 0) }
+}
+
+fn from_white(strong: Color, score: i32) -> i32 {
+    if strong == Color::White { score } else { -score }
+}
+
+///King moves between the squares
+fn distance(a: u8, b: u8) -> i32 {
+    let file_distance = (a as i32 % 8 - b as i32 % 8).abs();
+    let rank_distance = (a as i32 / 8 - b as i32 / 8).abs();
+    file_distance.max(rank_distance)
+}
+
+fn manhattan_distance(a: u8, b: u8) -> i32 {
+    (a as i32 % 8 - b as i32 % 8).abs() + (a as i32 / 8 - b as i32 / 8).abs()
+}
+
+///0 on the four center squares, 6 in the corners
+fn center_distance(square: u8) -> i32 {
+    let file = square as i32 % 8;
+    let rank = square as i32 / 8;
+    (if file < 4 { 3 - file } else { file - 4 }) + (if rank < 4 { 3 - rank } else { rank - 4 })
+}
+
+#[cfg(test)]
+mod endgame_tests {
+    use crate::*;
+
+    fn white_eval(fen: &str) -> i32 {
+        let game = Game::new_from_fen(fen).unwrap();
+        let eval = evaluate(&game, &EvalParams::default());
+        if game.active_player == Color::White { eval } else { -eval }
+    }
+
+    fn endgame_name(fen: &str) -> Option<&'static str> {
+        let game = Game::new_from_fen(fen).unwrap();
+        probe_endgame(&game, evaluate_score(&game, &EvalParams::default()).taper(game_phase(&game)), &EvalParams::default()).map(|(name, _)| name)
+    }
+
+    #[test]
+    pub fn signatures_match_material() {
+        let game = Game::new_from_fen("8/8/8/3k4/8/8/8/1NB1K3 w - - 0 1").unwrap();
+        let kbnk = ENDGAMES.iter().find(|e| e.name == "KBNK").unwrap();
+        assert_eq!(Material::new(&game).key(Color::White), kbnk.key);
+        assert_ne!(Material::new(&game).key(Color::Black), kbnk.key);
+
+        assert_eq!(endgame_name("8/8/8/3K4/8/8/8/1nb1k3 b - - 0 1"), Some("KBNK"));
+        assert_eq!(endgame_name("8/8/8/2k5/8/8/8/1NBRK3 w - - 0 1"), Some("mop-up"));
+        assert_eq!(endgame_name("r1bqkbnr/pppppppp/2n5/8/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 1 2"), None);
+    }
+
+    #[test]
+    pub fn kpk_matches_known_positions() {
+        //The defending king has the opposition in front of the pawn only when white is to move
+        assert_eq!(white_eval("8/4k3/8/4K3/4P3/8/8/8 w - - 0 1"), 0);
+        assert!(white_eval("8/4k3/8/4K3/4P3/8/8/8 b - - 0 1") > KNOWN_WIN);
+        //A king on the 6th rank in front of the pawn wins either way
+        assert!(white_eval("4k3/8/4K3/4P3/8/8/8/8 w - - 0 1") > KNOWN_WIN);
+        assert!(white_eval("4k3/8/3K4/4P3/8/8/8/8 w - - 0 1") > KNOWN_WIN);
+        assert!(white_eval("4k3/8/3K4/4P3/8/8/8/8 b - - 0 1") > KNOWN_WIN);
+        //The defending king reaches the corner in front of the rook pawn
+        assert_eq!(white_eval("k7/8/8/8/8/8/P7/7K w - - 0 1"), 0);
+        assert_eq!(white_eval("7k/8/8/8/8/8/7P/K7 w - - 0 1"), 0);
+        //The king can't catch the pawn
+        assert!(white_eval("7k/8/8/8/P7/8/8/K7 w - - 0 1") > KNOWN_WIN);
+        assert!(white_eval("7k/8/8/8/P7/8/8/K7 b - - 0 1") > KNOWN_WIN);
+        assert!(white_eval("6k1/8/8/8/P7/8/8/K7 b - - 0 1") > KNOWN_WIN);
+        assert!(white_eval("5k2/8/8/8/P7/8/8/K7 w - - 0 1") > KNOWN_WIN);
+        assert_eq!(white_eval("5k2/8/8/8/P7/8/8/K7 b - - 0 1"), 0);
+        //The pawn can't be taken on the 7th rank
+        assert!(white_eval("3k4/4P3/4K3/8/8/8/8/8 b - - 0 1") > KNOWN_WIN);
+
+        //Mirrored to the black side
+        assert_eq!(white_eval("8/8/8/4p3/4k3/8/4K3/8 b - - 0 1"), 0);
+        assert!(white_eval("8/8/8/4p3/4k3/8/4K3/8 w - - 0 1") < -KNOWN_WIN);
+        assert!(white_eval("k7/8/8/8/8/p7/8/7K w - - 0 1") < -KNOWN_WIN);
+
+        //Wins are scored with the tuned pawn value
+        let mut params = EvalParams::default();
+        params.material_eg[0] += 50;
+        let game = Game::new_from_fen("4k3/8/4K3/4P3/8/8/8/8 w - - 0 1").unwrap();
+        assert_eq!(evaluate(&game, &params), evaluate(&game, &EvalParams::default()) + 50);
+    }
+
+    #[test]
+    pub fn kbnk_drives_the_king_to_the_bishops_corner() {
+        //Light squared bishop, so a8 is the right corner and a1 the wrong one
+        let right_corner = white_eval("k7/8/1K6/8/8/8/8/3BN3 w - - 0 1");
+        let wrong_corner = white_eval("8/8/8/8/8/1K6/8/k2BN3 w - - 0 1");
+        assert!(right_corner > KNOWN_WIN);
+        assert!(right_corner > wrong_corner);
+
+        //The same from black's side
+        assert_eq!(white_eval("3bn3/8/8/8/8/1k6/8/K7 b - - 0 1"), -right_corner);
+    }
+
+    #[test]
+    pub fn krkp_knows_won_and_drawish_positions() {
+        //The white king stops the pawn
+        let won = white_eval("8/8/8/8/1k6/1p6/1K6/7R w - - 0 1");
+        //The pawn is about to queen with its king next to it, and the white king is far away
+        let drawish = white_eval("K7/8/8/8/8/8/1pk5/7R w - - 0 1");
+
+        assert!(won > 500);
+        assert!(drawish < 100);
+    }
+
+    #[test]
+    pub fn lone_king_is_driven_to_the_edge() {
+        assert!(white_eval("7k/8/5K2/8/8/8/8/R7 w - - 0 1") > white_eval("8/8/8/4k3/8/8/8/R3K3 w - - 0 1"));
+        assert!(white_eval("8/8/8/4k3/8/8/8/R3K3 w - - 0 1") > KNOWN_WIN / 2);
+
+        //Not enough to mate
+        assert_eq!(white_eval("8/8/8/4k3/8/8/8/1N2K3 w - - 0 1"), 0);
+        assert_eq!(white_eval("8/8/8/4k3/8/8/8/NN2K3 w - - 0 1"), 0);
+    }
+
+    #[test]
+    pub fn drawish_endings_are_scaled() {
+        let params = EvalParams::default();
+        let unscaled = |fen: &str| {
+            let game = Game::new_from_fen(fen).unwrap();
+            evaluate_score(&game, &params).taper(game_phase(&game))
+        };
+
+        //Opposite and same colored bishops with the same pawns
+        let opposite = "4k3/8/3b4/8/2PP4/8/4B3/4K3 w - - 0 1";
+        let same = "4k3/8/4b3/8/2PP4/8/4B3/4K3 w - - 0 1";
+        assert_eq!(endgame_name(opposite), Some("opposite colored bishops"));
+        assert_eq!(endgame_name(same), None);
+        assert_eq!(white_eval(opposite), unscaled(opposite) * 24 / SCALE_NORMAL);
+
+        //The light squared bishop can't help the h-pawn queen on the dark h8, with the black king in the corner
+        assert_eq!(endgame_name("7k/8/6K1/7P/8/8/4B3/8 w - - 0 1"), Some("wrong colored bishop"));
+        assert_eq!(white_eval("7k/8/6K1/7P/8/8/4B3/8 w - - 0 1"), 0);
+        assert_eq!(endgame_name("7k/8/6K1/7P/8/8/3B4/8 w - - 0 1"), None);
+        //Not when the defending side has more than its king
+        for fen in ["7k/p7/6K1/7P/8/8/4B3/8 w - - 0 1", "7k/8/6K1/7P/8/8/4B3/n7 w - - 0 1"] {
+            assert_eq!(endgame_name(fen), None);
+            assert_eq!(white_eval(fen), unscaled(fen));
+        }
+
+        //A rook against a minor piece
+        assert_eq!(endgame_name("4k3/8/8/3n4/8/8/8/3RK3 w - - 0 1"), Some("no pawns"));
+        assert_eq!(white_eval("4k3/8/8/3n4/8/8/8/3RK3 w - - 0 1"), unscaled("4k3/8/8/3n4/8/8/8/3RK3 w - - 0 1") * 16 / SCALE_NORMAL);
+    }
+}
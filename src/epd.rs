@@ -0,0 +1,210 @@
+use std::{fs, time::SystemTime};
+
+use super::*;
+
+///A position from an EPD line and its operations, like "bm Nf3 Qd1" or "id "WAC.001""
+pub struct EpdPosition {
+    pub game: Game,
+    ///Opcodes and their operands, with the quotes of string operands removed
+    pub operations: Vec<(String, Vec<String>)>,
+}
+
+impl EpdPosition {
+    pub fn operation(&self, opcode: &str) -> Option<&[String]> {
+        self.operations.iter().find(|(op, _)| op == opcode).map(|(_, operands)| operands.as_slice())
+    }
+
+    pub fn id(&self) -> Option<&str> {
+        self.operation("id").and_then(|operands| operands.first()).map(String::as_str)
+    }
+
+    ///The moves of a move operation like "bm" or "am", given in SAN or UCI
+    pub fn moves(&self, opcode: &str) -> Result<Vec<Move>, String> {
+        let mut game = self.game;
+        self.operation(opcode).unwrap_or_default().iter()
+            .map(|mov| game.parse_san(mov).or_else(|| game.parse_move(mov.to_string())).ok_or(format!("Illegal move '{}' in '{}'", mov, opcode)))
+            .collect()
+    }
+}
+
+///Parses "[pieces] [side] [castling] [enpassant] [operations]", where the operations are separated by ';'.
+///The move clocks are taken from the "hmvc" and "fmvn" operations, or from two numbers after the fields like in a fen
+pub fn parse_epd(line: &str) -> Result<EpdPosition, String> {
+    let mut rest = line.trim();
+    let mut fields = Vec::new();
+    while fields.len() < 6 {
+        let (field, remaining) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+        //The move clocks are optional
+        if fields.len() >= 4 && field.parse::<u16>().is_err() {
+            break;
+        }
+        if field.is_empty() {
+            return Err(format!("Missing fields in '{}'", line));
+        }
+        fields.push(field);
+        rest = remaining.trim_start();
+    }
+
+    let operations = parse_operations(rest)?;
+    let operand = |opcode: &str| operations.iter().find(|(op, _)| op == opcode).and_then(|(_, operands)| operands.first());
+    let half_moves = operand("hmvc").map(String::as_str).or(fields.get(4).copied()).unwrap_or("0");
+    let full_moves = operand("fmvn").map(String::as_str).or(fields.get(5).copied()).unwrap_or("1");
+
+    let fen = format!("{} {} {}", fields[..4].join(" "), half_moves, full_moves);
+    let game = Game::new_from_fen(&fen).map_err(|e| format!("Illegal position in '{}': {}", line, e))?;
+
+    Ok(EpdPosition { game, operations })
+}
+
+fn parse_operations(text: &str) -> Result<Vec<(String, Vec<String>)>, String> {
+    let mut operations = Vec::new();
+    let mut tokens: Vec<String> = Vec::new();
+    let mut chars = text.chars().peekable();
+
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+
+        match chars.next() {
+            None => break,
+            Some(';') => {
+                if !tokens.is_empty() {
+                    let opcode = tokens.remove(0);
+                    operations.push((opcode, std::mem::take(&mut tokens)));
+                }
+            },
+            Some('"') => {
+                let mut string = String::new();
+                loop {
+                    match chars.next() {
+                        None => return Err(format!("Unterminated string in '{}'", text)),
+                        Some('"') => break,
+                        Some(c) => string.push(c)
+                    }
+                }
+                tokens.push(string);
+            },
+            Some(c) => {
+                let mut token = c.to_string();
+                while let Some(c) = chars.next_if(|c| !c.is_whitespace() && *c != ';') {
+                    token.push(c);
+                }
+                tokens.push(token);
+            }
+        }
+    }
+
+    if !tokens.is_empty() {
+        return Err(format!("Missing ';' after '{}'", tokens.join(" ")));
+    }
+
+    Ok(operations)
+}
+
+#[derive(Debug, PartialEq)]
+pub enum SuiteLimit {
+    MoveTime(i64),
+    Depth(i8),
+}
+
+///Searches every position of an EPD file and checks the move against its "bm" and "am" operations.
+///Returns the number of solved positions and the number of positions with a solution
+pub fn run_test_suite(path: &str, limit: &SuiteLimit, evaluator: &Evaluator) -> Result<(usize, usize), String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("Could not read '{}': {}", path, e))?;
+
+    let (depth, max_time) = match limit {
+        SuiteLimit::MoveTime(time) => (-1, *time),
+        SuiteLimit::Depth(depth) => (*depth, -1)
+    };
+
+    let mut tt = TranspositionTable::new();
+    let mut solved = 0;
+    let mut total = 0;
+    let mut nodes = 0;
+    let start = SystemTime::now();
+
+    for (line_number, line) in text.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let name = format!("Line {}", line_number + 1);
+
+        let position = match parse_epd(line) {
+            Ok(position) => position,
+            Err(e) => { println!(" {}: {}", name, e); continue; }
+        };
+        let name = position.id().unwrap_or(&name).to_string();
+        let (best_moves, avoid_moves) = match (position.moves("bm"), position.moves("am")) {
+            (Ok(best), Ok(avoid)) if !best.is_empty() || !avoid.is_empty() => (best, avoid),
+            (Err(e), _) | (_, Err(e)) => { println!(" {}: {}", name, e); continue; },
+            _ => { println!(" {}: No 'bm' or 'am' operation", name); continue; }
+        };
+
+        tt.clear();
+        let mut game = position.game;
+        let position_start = SystemTime::now();
+        let result = search_silently(&mut game, depth, max_time, 0, &mut tt, &mut RepetitionTable::new(), evaluator);
+        nodes += result.nodes_visited;
+
+        let found = result.best_move;
+        let correct = (best_moves.is_empty() || best_moves.contains(&found)) && !avoid_moves.contains(&found);
+        total += 1;
+        if correct {
+            solved += 1;
+        }
+
+        let expected = if best_moves.is_empty() {
+            format!("avoid {}", avoid_moves.iter().map(|m| position.game.move_to_san(m)).collect::<Vec<_>>().join(" "))
+        }
+        else {
+            best_moves.iter().map(|m| position.game.move_to_san(m)).collect::<Vec<_>>().join(" ")
+        };
+        let found = if found == NULL_MOVE { "none".to_string() } else { position.game.move_to_san(&found) };
+        println!(" {}: {}\t Found: {}\t Expected: {}\t Depth: {}\t Time: {}ms", name, if correct { "solved" } else { "FAILED" },
+            found, expected, result.depth, position_start.elapsed().unwrap().as_millis());
+    }
+
+    println!(" Solved {}/{}\t Nodes: {}\t Time: {}ms", solved, total, nodes, start.elapsed().unwrap().as_millis());
+    Ok((solved, total))
+}
+
+#[cfg(test)]
+mod epd_tests {
+    use crate::*;
+
+    #[test]
+    pub fn operations_are_parsed() {
+        let position = parse_epd("r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - bm Bb5 Bc4; id \"Test; 1\"; c0 \"a comment\"; hmvc 2; fmvn 3;").unwrap();
+        assert_eq!(position.id(), Some("Test; 1"));
+        assert_eq!(position.operation("c0").unwrap(), ["a comment"]);
+        assert_eq!(position.game.half_moves, 2);
+        assert_eq!(position.game.full_moves, 3);
+        assert_eq!(position.moves("bm").unwrap().iter().map(|m| m.to_uci()).collect::<Vec<_>>(), ["f1b5", "f1c4"]);
+        assert!(position.moves("am").unwrap().is_empty());
+
+        //Full fens and UCI moves are read too
+        let position = parse_epd("6k1/8/8/8/8/8/8/K6R w - - 5 40 am h1h7;").unwrap();
+        assert_eq!((position.game.half_moves, position.game.full_moves), (5, 40));
+        assert_eq!(position.moves("am").unwrap().iter().map(|m| m.to_uci()).collect::<Vec<_>>(), ["h1h7"]);
+    }
+
+    #[test]
+    pub fn malformed_lines_are_rejected() {
+        assert!(parse_epd("").is_err());
+        assert!(parse_epd("6k1/8/8/8/8/8/8/K6R w -").is_err());
+        assert!(parse_epd("6k1/8/8/8/8/8/8/K6R w - - bm Rh7").is_err());
+        assert!(parse_epd("6k1/8/8/8/8/8/8/K6R w - - id \"unterminated;").is_err());
+        assert!(parse_epd("6k1/8/8/8/8/8/8/K6X w - - bm Rh7;").is_err());
+        assert!(parse_epd("6k1/8/8/8/8/8/8/K6R w - - bm Rh9;").unwrap().moves("bm").is_err());
+    }
+
+    #[test]
+    pub fn suites_count_solved_positions() {
+        let path = std::env::temp_dir().join("jence_suite_test.epd");
+        std::fs::write(&path, "6k1/5ppp/8/8/8/8/8/K3R3 w - - bm Re8#; id \"mate\";\n\
+                               6k1/5ppp/8/8/8/8/8/K3R3 w - - am Re8; id \"avoid mate\";\n\
+                               6k1/5ppp/8/8/8/8/8/K3R3 w - - id \"no solution\";\n").unwrap();
+
+        let result = run_test_suite(path.to_str().unwrap(), &SuiteLimit::Depth(2), &Evaluator::new()).unwrap();
+        assert_eq!(result, (1, 2));
+    }
+}
@@ -0,0 +1,269 @@
+use std::fs;
+
+use super::*;
+
+const PIECE_NAMES: [&str; 6] = ["pawn", "knight", "bishop", "rook", "queen", "king"];
+
+///Every weight used by evaluate(), so parameter sets can be swapped without recompiling.
+///Scores are stored as middlegame/endgame pairs
+#[derive(Clone, PartialEq, Debug)]
+pub struct EvalParams {
+    pub material_mg: [i32; 6],
+    pub material_eg: [i32; 6],
+    pub pst_mg: [[i32; 64]; 6],
+    pub pst_eg: [[i32; 64]; 6],
+    pub stacked_pawn_penalty: Score,
+    pub isolated_pawn_penalty: Score,
+    pub passed_pawn_bonus: [Score; 8],
+    pub semi_open_file_score: Score,
+    pub open_file_score: Score,
+    pub king_semi_open_file_penalty: Score,
+    pub king_open_file_penalty: Score,
+    pub pawn_shield: [Score; 3],
+    pub pawn_storm: [Score; 4],
+    pub king_attack_weights: [i32; 4],
+    pub safe_check_weights: [i32; 4],
+    pub king_danger: [i32; 100],
+    pub knight_mobility: [Score; 9],
+    pub bishop_mobility: [Score; 14],
+    pub rook_mobility: [Score; 15],
+    pub queen_mobility: [Score; 28],
+    pub bishop_pair: Score,
+    pub knight_outpost: Score,
+    pub rook_on_seventh: Score,
+    pub rook_behind_passer: Score,
+    pub trapped_rook: Score,
+    pub trapped_bishop: Score,
+    pub hanging_piece: Score,
+    pub pawn_threat: Score,
+}
+
+impl Default for EvalParams {
+    ///The compiled-in weights
+    fn default() -> Self {
+        Self {
+            material_mg: MATERIAL_MG,
+            material_eg: MATERIAL_EG,
+            pst_mg: PST_MG,
+            pst_eg: PST_EG,
+            stacked_pawn_penalty: STACKED_PAWN_PENALTY,
+            isolated_pawn_penalty: ISOLATED_PAWN_PENALTY,
+            passed_pawn_bonus: PASSED_PAWN_BONUS,
+            semi_open_file_score: SEMI_OPEN_FILE_SCORE,
+            open_file_score: OPEN_FILE_SCORE,
+            king_semi_open_file_penalty: KING_SEMI_OPEN_FILE_PENALTY,
+            king_open_file_penalty: KING_OPEN_FILE_PENALTY,
+            pawn_shield: PAWN_SHIELD,
+            pawn_storm: PAWN_STORM,
+            king_attack_weights: KING_ATTACK_WEIGHTS,
+            safe_check_weights: SAFE_CHECK_WEIGHTS,
+            king_danger: KING_DANGER,
+            knight_mobility: KNIGHT_MOBILITY,
+            bishop_mobility: BISHOP_MOBILITY,
+            rook_mobility: ROOK_MOBILITY,
+            queen_mobility: QUEEN_MOBILITY,
+            bishop_pair: BISHOP_PAIR,
+            knight_outpost: KNIGHT_OUTPOST,
+            rook_on_seventh: ROOK_ON_SEVENTH,
+            rook_behind_passer: ROOK_BEHIND_PASSER,
+            trapped_rook: TRAPPED_ROOK,
+            trapped_bishop: TRAPPED_BISHOP,
+            hanging_piece: HANGING_PIECE,
+            pawn_threat: PAWN_THREAT,
+        }
+    }
+}
+
+impl EvalParams {
+    ///All weights as named lists of values, in the order they are written to file
+    pub fn to_values(&self) -> Vec<(String, Vec<i32>)> {
+        let mut values = vec![
+            ("material_mg".to_string(), self.material_mg.to_vec()),
+            ("material_eg".to_string(), self.material_eg.to_vec()),
+        ];
+
+        for (piece_type, name) in PIECE_NAMES.iter().enumerate() {
+            values.push((format!("pst_mg_{}", name), self.pst_mg[piece_type].to_vec()));
+            values.push((format!("pst_eg_{}", name), self.pst_eg[piece_type].to_vec()));
+        }
+
+        values.push(("stacked_pawn_penalty".to_string(),        scores_to_values(&[self.stacked_pawn_penalty])));
+        values.push(("isolated_pawn_penalty".to_string(),       scores_to_values(&[self.isolated_pawn_penalty])));
+        values.push(("passed_pawn_bonus".to_string(),           scores_to_values(&self.passed_pawn_bonus)));
+        values.push(("semi_open_file_score".to_string(),        scores_to_values(&[self.semi_open_file_score])));
+        values.push(("open_file_score".to_string(),             scores_to_values(&[self.open_file_score])));
+        values.push(("king_semi_open_file_penalty".to_string(), scores_to_values(&[self.king_semi_open_file_penalty])));
+        values.push(("king_open_file_penalty".to_string(),      scores_to_values(&[self.king_open_file_penalty])));
+        values.push(("pawn_shield".to_string(),                 scores_to_values(&self.pawn_shield)));
+        values.push(("pawn_storm".to_string(),                  scores_to_values(&self.pawn_storm)));
+        values.push(("king_attack_weights".to_string(),         self.king_attack_weights.to_vec()));
+        values.push(("safe_check_weights".to_string(),          self.safe_check_weights.to_vec()));
+        values.push(("king_danger".to_string(),                 self.king_danger.to_vec()));
+        values.push(("knight_mobility".to_string(),             scores_to_values(&self.knight_mobility)));
+        values.push(("bishop_mobility".to_string(),             scores_to_values(&self.bishop_mobility)));
+        values.push(("rook_mobility".to_string(),               scores_to_values(&self.rook_mobility)));
+        values.push(("queen_mobility".to_string(),              scores_to_values(&self.queen_mobility)));
+        values.push(("bishop_pair".to_string(),                 scores_to_values(&[self.bishop_pair])));
+        values.push(("knight_outpost".to_string(),              scores_to_values(&[self.knight_outpost])));
+        values.push(("rook_on_seventh".to_string(),             scores_to_values(&[self.rook_on_seventh])));
+        values.push(("rook_behind_passer".to_string(),          scores_to_values(&[self.rook_behind_passer])));
+        values.push(("trapped_rook".to_string(),                scores_to_values(&[self.trapped_rook])));
+        values.push(("trapped_bishop".to_string(),              scores_to_values(&[self.trapped_bishop])));
+        values.push(("hanging_piece".to_string(),               scores_to_values(&[self.hanging_piece])));
+        values.push(("pawn_threat".to_string(),                 scores_to_values(&[self.pawn_threat])));
+
+        values
+    }
+
+    ///Sets the weight with the given name. Scores take a middlegame and an endgame value each
+    pub fn set_values(&mut self, name: &str, values: &[i32]) -> Result<(), String> {
+        if let Some(piece) = name.strip_prefix("pst_mg_") {
+            let piece_type = piece_type_from_name(piece)?;
+            return set_ints(&mut self.pst_mg[piece_type], name, values);
+        }
+        if let Some(piece) = name.strip_prefix("pst_eg_") {
+            let piece_type = piece_type_from_name(piece)?;
+            return set_ints(&mut self.pst_eg[piece_type], name, values);
+        }
+
+        match name {
+            "material_mg" =>                 set_ints(&mut self.material_mg, name, values),
+            "material_eg" =>                 set_ints(&mut self.material_eg, name, values),
+            "stacked_pawn_penalty" =>        set_scores(std::slice::from_mut(&mut self.stacked_pawn_penalty), name, values),
+            "isolated_pawn_penalty" =>       set_scores(std::slice::from_mut(&mut self.isolated_pawn_penalty), name, values),
+            "passed_pawn_bonus" =>           set_scores(&mut self.passed_pawn_bonus, name, values),
+            "semi_open_file_score" =>        set_scores(std::slice::from_mut(&mut self.semi_open_file_score), name, values),
+            "open_file_score" =>             set_scores(std::slice::from_mut(&mut self.open_file_score), name, values),
+            "king_semi_open_file_penalty" => set_scores(std::slice::from_mut(&mut self.king_semi_open_file_penalty), name, values),
+            "king_open_file_penalty" =>      set_scores(std::slice::from_mut(&mut self.king_open_file_penalty), name, values),
+            "pawn_shield" =>                 set_scores(&mut self.pawn_shield, name, values),
+            "pawn_storm" =>                  set_scores(&mut self.pawn_storm, name, values),
+            "king_attack_weights" =>         set_ints(&mut self.king_attack_weights, name, values),
+            "safe_check_weights" =>          set_ints(&mut self.safe_check_weights, name, values),
+            "king_danger" =>                 set_ints(&mut self.king_danger, name, values),
+            "knight_mobility" =>             set_scores(&mut self.knight_mobility, name, values),
+            "bishop_mobility" =>             set_scores(&mut self.bishop_mobility, name, values),
+            "rook_mobility" =>               set_scores(&mut self.rook_mobility, name, values),
+            "queen_mobility" =>              set_scores(&mut self.queen_mobility, name, values),
+            "bishop_pair" =>                 set_scores(std::slice::from_mut(&mut self.bishop_pair), name, values),
+            "knight_outpost" =>              set_scores(std::slice::from_mut(&mut self.knight_outpost), name, values),
+            "rook_on_seventh" =>             set_scores(std::slice::from_mut(&mut self.rook_on_seventh), name, values),
+            "rook_behind_passer" =>          set_scores(std::slice::from_mut(&mut self.rook_behind_passer), name, values),
+            "trapped_rook" =>                set_scores(std::slice::from_mut(&mut self.trapped_rook), name, values),
+            "trapped_bishop" =>              set_scores(std::slice::from_mut(&mut self.trapped_bishop), name, values),
+            "hanging_piece" =>               set_scores(std::slice::from_mut(&mut self.hanging_piece), name, values),
+            "pawn_threat" =>                 set_scores(std::slice::from_mut(&mut self.pawn_threat), name, values),
+            _ => Err(format!("Unknown parameter '{}'", name))
+        }
+    }
+
+    ///Parses the text format: one weight per line as "name value value ...". Lines starting with # are comments.
+    ///Weights that are left out keep their default value
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut params = Self::default();
+
+        for (line_number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let mut split = line.split_whitespace();
+            let name = split.next().unwrap();
+            let values = split.map(|v| v.parse::<i32>()).collect::<Result<Vec<i32>, _>>()
+                .map_err(|_| format!("Line {}: '{}' has a value that is not an integer", line_number + 1, name))?;
+
+            params.set_values(name, &values).map_err(|e| format!("Line {}: {}", line_number + 1, e))?;
+        }
+
+        Ok(params)
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut text = "# JENCE evaluation parameters. Scores are middlegame/endgame pairs, tables are a8 to h1 from white's point of view\n".to_string();
+        for (name, values) in self.to_values() {
+            text += &name;
+            for value in values {
+                text += &format!(" {}", value);
+            }
+            text += "\n";
+        }
+        text
+    }
+
+    pub fn load(path: &str) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("Could not read '{}': {}", path, e))?;
+        Self::parse(&text)
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        fs::write(path, self.to_text()).map_err(|e| format!("Could not write '{}': {}", path, e))
+    }
+}
+
+fn piece_type_from_name(name: &str) -> Result<usize, String> {
+    PIECE_NAMES.iter().position(|p| *p == name).ok_or(format!("Unknown piece '{}'", name))
+}
+
+fn scores_to_values(scores: &[Score]) -> Vec<i32> {
+    scores.iter().flat_map(|s| [s.mg, s.eg]).collect()
+}
+
+fn set_ints(target: &mut [i32], name: &str, values: &[i32]) -> Result<(), String> {
+    if values.len() != target.len() {
+        return Err(format!("'{}' takes {} values, found {}", name, target.len(), values.len()));
+    }
+    target.copy_from_slice(values);
+    Ok(())
+}
+
+fn set_scores(target: &mut [Score], name: &str, values: &[i32]) -> Result<(), String> {
+    if values.len() != target.len() * 2 {
+        return Err(format!("'{}' takes {} values, found {}", name, target.len() * 2, values.len()));
+    }
+    for (score, pair) in target.iter_mut().zip(values.chunks(2)) {
+        *score = Score::new(pair[0], pair[1]);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod eval_params_tests {
+    use crate::*;
+
+    #[test]
+    pub fn text_round_trips() {
+        let mut params = EvalParams::default();
+        params.rook_mobility[2] = Score::new(3, 7);
+        params.pst_eg[5][10] = -42;
+
+        assert_eq!(EvalParams::parse(&params.to_text()).unwrap(), params);
+    }
+
+    #[test]
+    pub fn missing_weights_keep_defaults() {
+        let params = EvalParams::parse("# only material\nmaterial_mg 100 320 330 500 900 0\n").unwrap();
+        assert_eq!(params.material_mg, [100, 320, 330, 500, 900, 0]);
+        assert_eq!(params.material_eg, EvalParams::default().material_eg);
+    }
+
+    #[test]
+    pub fn rejects_bad_input() {
+        assert!(EvalParams::parse("material_mg 1 2 3").is_err());
+        assert!(EvalParams::parse("bishop_pair 1 x").is_err());
+        assert!(EvalParams::parse("pst_mg_dragon 1").is_err());
+        assert!(EvalParams::parse("no_such_weight 1").is_err());
+    }
+
+    #[test]
+    pub fn weights_change_the_evaluation() {
+        //Two pawns, as a single one is evaluated by the KPK bitbase
+        let game = Game::new_from_fen("4k3/8/8/8/8/8/3PP3/4K3 w - - 0 1").unwrap();
+        let mut params = EvalParams::default();
+        let before = evaluate(&game, &params);
+
+        params.material_eg[0] += 100;
+        params.material_mg[0] += 100;
+        assert_eq!(evaluate(&game, &params), before + 200);
+    }
+}
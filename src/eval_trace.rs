@@ -0,0 +1,173 @@
+use super::*;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum EvalTerm {
+    Material,
+    PieceSquare,
+    StackedPawns,
+    IsolatedPawns,
+    PassedPawns,
+    RookFiles,
+    Mobility,
+    KingSafety,
+    BishopPair,
+    Outposts,
+    RookOnSeventh,
+    TrappedPieces,
+    Threats,
+}
+
+pub const EVAL_TERMS: [EvalTerm; 13] = [
+    EvalTerm::Material, EvalTerm::PieceSquare, EvalTerm::StackedPawns, EvalTerm::IsolatedPawns,
+    EvalTerm::PassedPawns, EvalTerm::RookFiles, EvalTerm::Mobility, EvalTerm::KingSafety,
+    EvalTerm::BishopPair, EvalTerm::Outposts, EvalTerm::RookOnSeventh, EvalTerm::TrappedPieces, EvalTerm::Threats
+];
+
+impl EvalTerm {
+    pub fn name(&self) -> &'static str {
+        match self {
+            EvalTerm::Material =>      "material",
+            EvalTerm::PieceSquare =>   "piece_square",
+            EvalTerm::StackedPawns =>  "stacked_pawns",
+            EvalTerm::IsolatedPawns => "isolated_pawns",
+            EvalTerm::PassedPawns =>   "passed_pawns",
+            EvalTerm::RookFiles =>     "rook_files",
+            EvalTerm::Mobility =>      "mobility",
+            EvalTerm::KingSafety =>    "king_safety",
+            EvalTerm::BishopPair =>    "bishop_pair",
+            EvalTerm::Outposts =>      "outposts",
+            EvalTerm::RookOnSeventh => "rook_on_seventh",
+            EvalTerm::TrappedPieces => "trapped_pieces",
+            EvalTerm::Threats =>       "threats",
+        }
+    }
+}
+
+///Receives every term added by the evaluation, with the side it was added for
+pub trait EvalTracer {
+    fn add(&mut self, term: EvalTerm, white: bool, score: Score);
+}
+
+///Used by the search, where nothing is traced
+impl EvalTracer for () {
+    #[inline(always)]
+    fn add(&mut self, _term: EvalTerm, _white: bool, _score: Score) {}
+}
+
+///Every evaluation term summed per side, each from that side's own point of view
+#[derive(Default, Debug)]
+pub struct EvalTrace {
+    ///[term][white, black]
+    pub terms: [[Score; 2]; 13],
+    pub phase: i32,
+}
+
+impl EvalTracer for EvalTrace {
+    fn add(&mut self, term: EvalTerm, white: bool, score: Score) {
+        self.terms[term as usize][if white { 0 } else { 1 }] += score;
+    }
+}
+
+impl EvalTrace {
+    ///White's score minus black's for the term
+    pub fn term_total(&self, term: EvalTerm) -> Score {
+        let [white, black] = self.terms[term as usize];
+        white - black
+    }
+
+    ///Untapered total from white's point of view, equal to evaluate_score
+    pub fn total(&self) -> Score {
+        EVAL_TERMS.iter().fold(Score::ZERO, |sum, term| sum + self.term_total(*term))
+    }
+
+    ///Tapered total from white's point of view
+    pub fn tapered_total(&self) -> i32 {
+        self.total().taper(self.phase)
+    }
+
+    pub fn to_table(&self) -> String {
+        let mut table = format!(" {:<16}|{:>14} |{:>14} |{:>14} |{:>8}\n", "Term", "White", "Black", "Total", "Tapered");
+        table += &format!(" {:<16}|{:>7}{:>7} |{:>7}{:>7} |{:>7}{:>7} |\n", "", "MG", "EG", "MG", "EG", "MG", "EG");
+        table += &format!(" {:-<16}+{:-<15}+{:-<15}+{:-<15}+{:-<8}\n", "", "", "", "", "");
+
+        for term in EVAL_TERMS {
+            let [white, black] = self.terms[term as usize];
+            let total = self.term_total(term);
+            table += &format!(" {:<16}|{:>7}{:>7} |{:>7}{:>7} |{:>7}{:>7} |{:>8}\n",
+                term.name(), white.mg, white.eg, black.mg, black.eg, total.mg, total.eg, total.taper(self.phase));
+        }
+
+        let total = self.total();
+        table += &format!(" {:-<16}+{:-<15}+{:-<15}+{:-<15}+{:-<8}\n", "", "", "", "", "");
+        table += &format!(" {:<16}|{:>14} |{:>14} |{:>7}{:>7} |{:>8}\n", "Total", "", "", total.mg, total.eg, self.tapered_total());
+        table += &format!(" Phase: {}/{}, scores are from white's point of view", self.phase, TOTAL_PHASE);
+        table
+    }
+
+    pub fn to_json(&self) -> String {
+        let score_json = |score: Score| format!("{{\"mg\":{},\"eg\":{}}}", score.mg, score.eg);
+
+        let terms: Vec<String> = EVAL_TERMS.iter().map(|term| {
+            let [white, black] = self.terms[*term as usize];
+            let total = self.term_total(*term);
+            format!("\"{}\":{{\"white\":{},\"black\":{},\"total\":{},\"tapered\":{}}}",
+                term.name(), score_json(white), score_json(black), score_json(total), total.taper(self.phase))
+        }).collect();
+
+        format!("{{\"phase\":{},\"terms\":{{{}}},\"total\":{},\"tapered\":{}}}",
+            self.phase, terms.join(","), score_json(self.total()), self.tapered_total())
+    }
+}
+
+///Per term breakdown of the evaluation of a position
+pub fn trace_evaluation(game: &Game, params: &EvalParams) -> EvalTrace {
+    let mut trace = EvalTrace { phase: game_phase(game), ..Default::default() };
+    evaluate_terms(game, params, &mut trace, None);
+    trace
+}
+
+#[cfg(test)]
+mod eval_trace_tests {
+    use crate::*;
+
+    #[test]
+    pub fn trace_adds_up_to_the_evaluation() {
+        let params = EvalParams::default();
+        for fen in [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 10",
+            "6k1/3q1pp1/pp5p/1r5n/8/1P3PP1/PQ4BP/2R3K1 b - - 0 1",
+        ] {
+            let game = Game::new_from_fen(fen).unwrap();
+            let trace = trace_evaluation(&game, &params);
+
+            assert_eq!(trace.total(), evaluate_score(&game, &params));
+            let eval = evaluate(&game, &params);
+            assert_eq!(trace.tapered_total(), if game.active_player == Color::White { eval } else { -eval });
+        }
+    }
+
+    #[test]
+    pub fn terms_are_split_by_side() {
+        //Only white has a passed pawn and only black has a rook
+        let game = Game::new_from_fen("r3k3/8/8/8/8/8/4P3/4K3 w - - 0 1").unwrap();
+        let trace = trace_evaluation(&game, &EvalParams::default());
+
+        assert_eq!(trace.terms[EvalTerm::PassedPawns as usize][1], Score::ZERO);
+        assert!(trace.terms[EvalTerm::PassedPawns as usize][0].eg > 0);
+        assert_eq!(trace.terms[EvalTerm::RookFiles as usize][0], Score::ZERO);
+        assert!(trace.terms[EvalTerm::RookFiles as usize][1].mg > 0);
+    }
+
+    #[test]
+    pub fn json_lists_every_term() {
+        let trace = trace_evaluation(&Game::new_from_start_pos(), &EvalParams::default());
+        let json = trace.to_json();
+
+        for term in EVAL_TERMS {
+            assert!(json.contains(&format!("\"{}\":", term.name())));
+        }
+        assert!(json.starts_with("{\"phase\":24,"));
+        assert_eq!(json.matches('{').count(), json.matches('}').count());
+    }
+}
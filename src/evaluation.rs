@@ -1,9 +1,65 @@
+use std::ops::{Add, AddAssign, Sub, SubAssign, Neg, Mul};
+
 use super::*;
 
-pub const MATERIAL_WEIGHTS: [i32; 12] = [100, 300, 350, 500, 1000, 10000, -100, -300, -350, -500, -1000, -10000];
+///A pair of middlegame and endgame values, interpolated by the game phase
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct Score {
+    pub mg: i32,
+    pub eg: i32
+}
+
+impl Score {
+    pub const ZERO: Score = Score { mg: 0, eg: 0 };
+
+    pub const fn new(mg: i32, eg: i32) -> Self {
+        Self { mg, eg }
+    }
+
+    ///Interpolates between the middlegame and endgame value. Phase runs from 0 (endgame) to TOTAL_PHASE (opening)
+    pub fn taper(&self, phase: i32) -> i32 {
+        (self.mg * phase + self.eg * (TOTAL_PHASE - phase)) / TOTAL_PHASE
+    }
+}
+
+impl Add for Score {
+    type Output = Score;
+    fn add(self, other: Score) -> Score { Score::new(self.mg + other.mg, self.eg + other.eg) }
+}
+
+impl AddAssign for Score {
+    fn add_assign(&mut self, other: Score) { self.mg += other.mg; self.eg += other.eg; }
+}
+
+impl Sub for Score {
+    type Output = Score;
+    fn sub(self, other: Score) -> Score { Score::new(self.mg - other.mg, self.eg - other.eg) }
+}
+
+impl SubAssign for Score {
+    fn sub_assign(&mut self, other: Score) { self.mg -= other.mg; self.eg -= other.eg; }
+}
+
+impl Neg for Score {
+    type Output = Score;
+    fn neg(self) -> Score { Score::new(-self.mg, -self.eg) }
+}
+
+impl Mul<i32> for Score {
+    type Output = Score;
+    fn mul(self, factor: i32) -> Score { Score::new(self.mg * factor, self.eg * factor) }
+}
+
+///Phase contribution of each piece type. Pawns and kings don't count
+pub const PHASE_WEIGHTS: [i32; 6] = [0, 1, 1, 2, 4, 0];
+pub const TOTAL_PHASE: i32 = 24;
+
+///[pawn, knight, bishop, rook, queen, king]
+pub const MATERIAL_MG: [i32; 6] = [100, 300, 350, 500, 1000, 0];
+pub const MATERIAL_EG: [i32; 6] = [120, 290, 330, 550, 1000, 0];
 
 // pawn positional score
-pub const PAWN_SCORES: [i32; 64] = 
+pub const PAWN_SCORES_MG: [i32; 64] = 
 [
     90,  90,  90,  90,  90,  90,  90,  90,
     30,  30,  30,  40,  40,  30,  30,  30,
@@ -15,8 +71,20 @@ pub const PAWN_SCORES: [i32; 64] =
      0,   0,   0,   0,   0,   0,   0,   0
 ];
 
+pub const PAWN_SCORES_EG: [i32; 64] = 
+[
+     0,   0,   0,   0,   0,   0,   0,   0,
+    80,  80,  80,  80,  80,  80,  80,  80,
+    50,  50,  50,  50,  50,  50,  50,  50,
+    30,  30,  30,  30,  30,  30,  30,  30,
+    15,  15,  15,  15,  15,  15,  15,  15,
+     5,   5,   5,   5,   5,   5,   5,   5,
+     0,   0,   0,   0,   0,   0,   0,   0,
+     0,   0,   0,   0,   0,   0,   0,   0
+];
+
 // knight positional score
-pub const KNIGHT_SCORES: [i32; 64] = 
+pub const KNIGHT_SCORES_MG: [i32; 64] = 
 [
     -5,   0,   0,   0,   0,   0,   0,  -5,
     -5,   0,   0,  10,  10,   0,   0,  -5,
@@ -28,8 +96,20 @@ pub const KNIGHT_SCORES: [i32; 64] =
     -5, -10,   0,   0,   0,   0, -10,  -5
 ];
 
+pub const KNIGHT_SCORES_EG: [i32; 64] = 
+[
+   -20, -10, -10, -10, -10, -10, -10, -20,
+   -10,   0,   0,   5,   5,   0,   0, -10,
+   -10,   0,  10,  15,  15,  10,   0, -10,
+   -10,   5,  15,  20,  20,  15,   5, -10,
+   -10,   5,  15,  20,  20,  15,   5, -10,
+   -10,   0,  10,  15,  15,  10,   0, -10,
+   -10,   0,   0,   5,   5,   0,   0, -10,
+   -20, -10, -10, -10, -10, -10, -10, -20
+];
+
 // bishop positional score
-pub const BISHOP_SCORES: [i32; 64] = 
+pub const BISHOP_SCORES_MG: [i32; 64] = 
 [
      0,   0,   0,   0,   0,   0,   0,   0,
      0,   0,   0,   0,   0,   0,   0,   0,
@@ -39,11 +119,22 @@ pub const BISHOP_SCORES: [i32; 64] =
      0,  10,   0,   0,   0,   0,  10,   0,
      0,  30,   0,   0,   0,   0,  30,   0,
      0,   0, -10,   0,   0, -10,   0,   0
+];
 
+pub const BISHOP_SCORES_EG: [i32; 64] = 
+[
+   -10,  -5,  -5,  -5,  -5,  -5,  -5, -10,
+    -5,   0,   0,   0,   0,   0,   0,  -5,
+    -5,   0,   5,  10,  10,   5,   0,  -5,
+    -5,   0,  10,  15,  15,  10,   0,  -5,
+    -5,   0,  10,  15,  15,  10,   0,  -5,
+    -5,   0,   5,  10,  10,   5,   0,  -5,
+    -5,   0,   0,   0,   0,   0,   0,  -5,
+   -10,  -5,  -5,  -5,  -5,  -5,  -5, -10
 ];
 
 // rook positional score
-pub const ROOK_SCORES: [i32; 64] = 
+pub const ROOK_SCORES_MG: [i32; 64] = 
 [
     50,  50,  50,  50,  50,  50,  50,  50,
     50,  50,  50,  50,  50,  50,  50,  50,
@@ -53,22 +144,64 @@ pub const ROOK_SCORES: [i32; 64] =
      0,   0,  10,  20,  20,  10,   0,   0,
      0,   0,  10,  20,  20,  10,   0,   0,
      0,   0,   0,  20,  20,   0,   0,   0
+];
 
+pub const ROOK_SCORES_EG: [i32; 64] = 
+[
+    10,  10,  10,  10,  10,  10,  10,  10,
+    20,  20,  20,  20,  20,  20,  20,  20,
+     0,   0,   0,   0,   0,   0,   0,   0,
+     0,   0,   0,   0,   0,   0,   0,   0,
+     0,   0,   0,   0,   0,   0,   0,   0,
+     0,   0,   0,   0,   0,   0,   0,   0,
+     0,   0,   0,   0,   0,   0,   0,   0,
+     0,   0,   0,   0,   0,   0,   0,   0
+];
+
+// queen positional score
+pub const QUEEN_SCORES_MG: [i32; 64] = [0; 64];
+
+pub const QUEEN_SCORES_EG: [i32; 64] = 
+[
+   -20, -10, -10,  -5,  -5, -10, -10, -20,
+   -10,   0,   0,   0,   0,   0,   0, -10,
+   -10,   0,   5,   5,   5,   5,   0, -10,
+    -5,   0,   5,  10,  10,   5,   0,  -5,
+    -5,   0,   5,  10,  10,   5,   0,  -5,
+   -10,   0,   5,   5,   5,   5,   0, -10,
+   -10,   0,   0,   0,   0,   0,   0, -10,
+   -20, -10, -10,  -5,  -5, -10, -10, -20
 ];
 
 // king positional score
-pub const KING_SCORES: [i32; 64] = 
+pub const KING_SCORES_MG: [i32; 64] = 
 [
-     0,   0,   0,   0,   0,   0,   0,   0,
-     0,   0,   5,   5,   5,   5,   0,   0,
-     0,   5,   5,  10,  10,   5,   5,   0,
-     0,   5,  10,  20,  20,  10,   5,   0,
-     0,   5,  10,  20,  20,  10,   5,   0,
-     0,   0,   5,  10,  10,   5,   0,   0,
-     0,   5,   5,  -5,  -5,   0,   5,   0,
-     0,   0,   5,   0, -15,   0,  10,   0
+   -30, -40, -40, -50, -50, -40, -40, -30,
+   -30, -40, -40, -50, -50, -40, -40, -30,
+   -30, -40, -40, -50, -50, -40, -40, -30,
+   -30, -40, -40, -50, -50, -40, -40, -30,
+   -20, -30, -30, -40, -40, -30, -30, -20,
+   -10, -20, -20, -20, -20, -20, -20, -10,
+     5,   5,  -5, -10, -10,  -5,   5,   5,
+    10,  20,   5, -10,   0,  -5,  25,  10
+];
+
+pub const KING_SCORES_EG: [i32; 64] = 
+[
+   -30, -20, -15, -10, -10, -15, -20, -30,
+   -20,  -5,   5,   5,   5,   5,  -5, -20,
+   -15,   5,  15,  20,  20,  15,   5, -15,
+   -10,   5,  20,  30,  30,  20,   5, -10,
+   -10,   5,  20,  30,  30,  20,   5, -10,
+   -15,   5,  15,  20,  20,  15,   5, -15,
+   -20,  -5,   5,   5,   5,   5,  -5, -20,
+   -30, -20, -15, -10, -10, -15, -20, -30
 ];
 
+///[piece type][square], from white's point of view
+pub const PST_MG: [[i32; 64]; 6] = [PAWN_SCORES_MG, KNIGHT_SCORES_MG, BISHOP_SCORES_MG, ROOK_SCORES_MG, QUEEN_SCORES_MG, KING_SCORES_MG];
+pub const PST_EG: [[i32; 64]; 6] = [PAWN_SCORES_EG, KNIGHT_SCORES_EG, BISHOP_SCORES_EG, ROOK_SCORES_EG, QUEEN_SCORES_EG, KING_SCORES_EG];
+
 // mirror positional score tables for opposite side
 pub const MIRRORED: [usize; 64] = 
 [
@@ -100,232 +233,497 @@ pub const MVV_LVA: [[i32; 12]; 12] = [
 ];
 
 
-const FILE_MASKS: [u64; 64] = generate_file_masks();
+///The file of each square
+pub const FILE_MASKS: [u64; 64] = generate_file_masks();
 const RANK_MASKS: [u64; 64] = generate_rank_masks();
 const ISOLATED_MASKS: [u64; 64] = generate_isolated_pawn_masks();
 
-const WHITE_PASSED_PAWN_MASKS: [u64; 64] = generate_white_passed_pawn_masks();
-const BLACK_PASSED_PAWN_MASKS: [u64; 64] = generate_black_passed_pawn_masks();
+pub const WHITE_PASSED_PAWN_MASKS: [u64; 64] = generate_white_passed_pawn_masks();
+pub const BLACK_PASSED_PAWN_MASKS: [u64; 64] = generate_black_passed_pawn_masks();
 
-const STACKED_PAWN_PENALTY: i32 = -10;
-const ISOLATED_PAWN_PENALTY: i32 = -10;
-const PASSED_WHITE_PAWN_BONUS: [i32; 8] = [ 0, 10, 30, 50, 75, 100, 150, 200 ];
-const PASSED_BLACK_PAWN_BONUS: [i32; 8] = [ 200, 150, 100, 75, 50, 30, 10, 0 ]; 
-const SEMI_OPEN_FILE_SCORE: i32 = 10;
-const OPEN_FILE_SCORE: i32 = 15;
-const PROTECTED_KING_BONUS: i32 = 5;
+pub const STACKED_PAWN_PENALTY: Score = Score::new(-10, -20);
+pub const ISOLATED_PAWN_PENALTY: Score = Score::new(-10, -15);
+///Indexed by the rank relative to the pawn's side
+pub const PASSED_PAWN_BONUS: [Score; 8] = [
+    Score::new(0, 0), Score::new(10, 15), Score::new(30, 35), Score::new(50, 60),
+    Score::new(75, 100), Score::new(100, 150), Score::new(150, 220), Score::new(200, 200)
+];
+pub const SEMI_OPEN_FILE_SCORE: Score = Score::new(10, 5);
+pub const OPEN_FILE_SCORE: Score = Score::new(15, 10);
+pub const KING_SEMI_OPEN_FILE_PENALTY: Score = Score::new(-10, 0);
+pub const KING_OPEN_FILE_PENALTY: Score = Score::new(-15, 0);
+///Own pawn in front of the king on its file or a neighbouring one, by distance: one rank, two ranks, further or missing
+pub const PAWN_SHIELD: [Score; 3] = [Score::new(12, 0), Score::new(6, 0), Score::new(-12, 0)];
+///Enemy pawn advancing on the king on its file or a neighbouring one, by distance in ranks from 1 to 4
+pub const PAWN_STORM: [Score; 4] = [Score::new(-10, 0), Score::new(-25, 0), Score::new(-12, 0), Score::new(-4, 0)];
+///Attack units per attacked king zone square, [knight, bishop, rook, queen]
+pub const KING_ATTACK_WEIGHTS: [i32; 4] = [2, 2, 3, 5];
+///Attack units for a piece that can give a safe check, [knight, bishop, rook, queen]
+pub const SAFE_CHECK_WEIGHTS: [i32; 4] = [3, 2, 4, 6];
+///Middlegame penalty by attack units. Grows slowly for a few units and steeply after that
+pub const KING_DANGER: [i32; 100] = [
+      0,   0,   1,   2,   3,   5,   7,   9,  12,  15,
+     18,  22,  26,  30,  35,  39,  44,  50,  56,  62,
+     68,  75,  82,  85,  89,  97, 105, 113, 122, 131,
+    140, 150, 169, 180, 191, 202, 213, 225, 237, 248,
+    260, 272, 283, 295, 307, 319, 330, 342, 354, 366,
+    377, 389, 401, 412, 424, 436, 448, 459, 471, 483,
+    494, 500, 500, 500, 500, 500, 500, 500, 500, 500,
+    500, 500, 500, 500, 500, 500, 500, 500, 500, 500,
+    500, 500, 500, 500, 500, 500, 500, 500, 500, 500,
+    500, 500, 500, 500, 500, 500, 500, 500, 500, 500
+];
 
-/*const KNIGHT_UNIT: u32 = 4;
-const BISHOP_UNIT: u32 = 6;
-const ROOK_UNIT: u32 = 7;
-const QUEEN_UNIT: u32 = 13;
+///Indexed by the number of safe squares attacked: those not occupied by own pieces or attacked by enemy pawns
+pub const KNIGHT_MOBILITY: [Score; 9] = [
+    Score::new(-25, -30), Score::new(-15, -18), Score::new(-6, -8), Score::new(-1, -2), Score::new(3, 3), Score::new(7, 7), Score::new(11, 10),
+    Score::new(14, 12), Score::new(16, 13)
+];
+pub const BISHOP_MOBILITY: [Score; 14] = [
+    Score::new(-20, -25), Score::new(-12, -15), Score::new(-5, -7), Score::new(0, -1), Score::new(4, 4), Score::new(8, 8), Score::new(11, 12),
+    Score::new(14, 15), Score::new(16, 17), Score::new(18, 19), Score::new(20, 21), Score::new(21, 22), Score::new(22, 23), Score::new(23, 24)
+];
+pub const ROOK_MOBILITY: [Score; 15] = [
+    Score::new(-15, -25), Score::new(-9, -15), Score::new(-5, -8), Score::new(-2, -3), Score::new(0, 1), Score::new(2, 5), Score::new(4, 9),
+    Score::new(6, 12), Score::new(8, 15), Score::new(10, 18), Score::new(11, 20), Score::new(12, 22), Score::new(13, 24), Score::new(14, 25),
+    Score::new(15, 26)
+];
+pub const QUEEN_MOBILITY: [Score; 28] = [
+    Score::new(-10, -15), Score::new(-7, -11), Score::new(-5, -8), Score::new(-3, -5), Score::new(-1, -3), Score::new(0, -1), Score::new(1, 1),
+    Score::new(2, 3), Score::new(3, 5), Score::new(4, 7), Score::new(5, 9), Score::new(6, 11), Score::new(7, 12), Score::new(8, 13),
+    Score::new(9, 14), Score::new(10, 15), Score::new(11, 16), Score::new(11, 17), Score::new(12, 18), Score::new(12, 19), Score::new(13, 20),
+    Score::new(13, 21), Score::new(14, 22), Score::new(14, 22), Score::new(15, 23), Score::new(15, 23), Score::new(16, 24), Score::new(16, 24)
+];
+
+pub const BISHOP_PAIR: Score = Score::new(30, 50);
+///Knight on the 4th to 6th rank, defended by a pawn and out of reach of enemy pawns
+pub const KNIGHT_OUTPOST: Score = Score::new(20, 10);
+///Rook on the 7th rank, when enemy pawns are there or the enemy king is on the 8th
+pub const ROOK_ON_SEVENTH: Score = Score::new(20, 30);
+///Rook on the file of a passed pawn of either side, behind it with nothing in between
+pub const ROOK_BEHIND_PASSER: Score = Score::new(5, 20);
+///Rook on the back rank with little mobility, shut in by its own king
+pub const TRAPPED_ROOK: Score = Score::new(-40, -10);
+///Bishop on a7/h7 cut off by a pawn on b6/g6
+pub const TRAPPED_BISHOP: Score = Score::new(-70, -70);
+///Bonus per enemy piece that is attacked and not defended. Pawns and kings don't count
+pub const HANGING_PIECE: Score = Score::new(30, 20);
+///Bonus per enemy piece attacked by a pawn. Pawns and kings don't count
+pub const PAWN_THREAT: Score = Score::new(40, 30);
+
+///The evaluation used by the search. NNUE is used when a network is loaded and enabled
+pub struct Evaluator {
+    pub params: EvalParams,
+    pub network: Option<Network>,
+    pub use_nnue: bool,
+    ///Syzygy tables probed by the search
+    pub tablebases: Option<Tablebases>
+}
 
-const KNIGHT_MOB: i32 = 4;
-const BISHOP_MOB: i32 = 5;
-const ROOK_MOB: i32 = 3;
-const QUEEN_MOB: f32 = 1.5;*/
+impl Evaluator {
+    pub fn new() -> Self {
+        Self { params: EvalParams::default(), network: None, use_nnue: cfg!(feature = "nnue"), tablebases: None }
+    }
 
+    pub fn active_network(&self) -> Option<&Network> {
+        if self.use_nnue { self.network.as_ref() } else { None }
+    }
 
-pub fn evaluate(game: &Game) -> i32 {
-    let mut score: i32 = 0;
+    ///Evaluates from scratch, from the side to move's point of view
+    pub fn evaluate(&self, game: &Game) -> i32 {
+        match self.active_network() {
+            Some(network) => evaluate_nnue(game, network),
+            None => evaluate(game, &self.params)
+        }
+    }
+}
 
-    let mut stacked_pawns;
+pub fn evaluate(game: &Game, params: &EvalParams) -> i32 {
+    let score = evaluate_endgame(game, evaluate_score(game, params).taper(game_phase(game)), params);
+
+    if game.active_player == Color::White { score } else { -score }
+}
+
+///0 when only kings and pawns are left, TOTAL_PHASE with all pieces on the board
+pub fn game_phase(game: &Game) -> i32 {
+    let mut phase = 0;
+    for (piece_type, weight) in PHASE_WEIGHTS.iter().enumerate() {
+        let count = game.bitboards[piece_type].to_u64().count_ones() + game.bitboards[piece_type + 6].to_u64().count_ones();
+        phase += count as i32 * weight;
+    }
+
+    phase.min(TOTAL_PHASE)
+}
+
+///Untapered evaluation from white's point of view
+pub fn evaluate_score(game: &Game, params: &EvalParams) -> Score {
+    evaluate_terms(game, params, &mut (), None)
+}
+
+///Same as evaluate, with the pawn structure looked up in the pawn table
+pub fn evaluate_with_pawn_table(game: &Game, params: &EvalParams, pawn_table: &mut PawnTable) -> i32 {
+    let score = evaluate_endgame(game, evaluate_terms(game, params, &mut (), Some(pawn_table)).taper(game_phase(game)), params);
+
+    if game.active_player == Color::White { score } else { -score }
+}
+
+///The evaluation, reporting every term to the tracer. The empty tracer compiles away.
+///Cached pawn structure scores are not traced, so tracing is done without a pawn table
+pub fn evaluate_terms(game: &Game, params: &EvalParams, tracer: &mut impl EvalTracer, pawn_table: Option<&mut PawnTable>) -> Score {
+    let pawn_entry = match pawn_table {
+        Some(pawn_table) => pawn_table.probe(game, params),
+        None => evaluate_pawns(game, params, tracer)
+    };
+    let mut score = pawn_entry.score;
+    let passed_pawns = pawn_entry.passed_pawns[0].or(pawn_entry.passed_pawns[1]);
+
+    let all_pawns = game.get_piece_bitboard(Piece::WhitePawn).or(game.get_piece_bitboard(Piece::BlackPawn));
+    //[white, black]
+    let pawn_attacks = [pawn_attacks(game, true), pawn_attacks(game, false)];
+    let attacks = [attacked_squares(game, true), attacked_squares(game, false)];
 
     for bb in 0..12 {
+        let white = bb < 6;
+        let piece_type = bb % 6;
+        let (side, enemy) = if white { (0, 1) } else { (1, 0) };
+
+        let (own_pawns, enemy_pawns, own_occupancies) = if white {
+            (game.get_piece_bitboard(Piece::WhitePawn), game.get_piece_bitboard(Piece::BlackPawn), game.white_occupancies)
+        } else {
+            (game.get_piece_bitboard(Piece::BlackPawn), game.get_piece_bitboard(Piece::WhitePawn), game.black_occupancies)
+        };
+        let mobility_area = not(own_occupancies.or(pawn_attacks[enemy]));
+
+        let mut side_score = Score::ZERO;
+
         let mut board = game.bitboards[bb];
         while !board.is_empty() {
             let square = board.extract_bit();
-            score += MATERIAL_WEIGHTS[bb];
-            match bb {
-                //White pawns
-                0  => {
-                    score += PAWN_SCORES[square as usize];
-
-                    //Stacked pawn penalty
-                    stacked_pawns = game.get_piece_bitboard(Piece::WhitePawn)
-                                        .and(Bitboard::from_u64(FILE_MASKS[square as usize]))
-                                        .pop_count();
-                    if stacked_pawns > 1 {
-                        score += stacked_pawns as i32 * STACKED_PAWN_PENALTY;
-                    }
 
-                    //Isolated pawn penalty
-                    if game.get_piece_bitboard(Piece::WhitePawn)
-                            .and(Bitboard::from_u64(ISOLATED_MASKS[square as usize]))
-                            .is_empty() {
-                        score += ISOLATED_PAWN_PENALTY;
-                    }
+            //Tables are from white's point of view
+            let table_square = if white { square as usize } else { MIRRORED[square as usize] };
 
-                    //Passed pawn penalty
-                    if game.get_piece_bitboard(Piece::BlackPawn)
-                           .and(Bitboard::from_u64(WHITE_PASSED_PAWN_MASKS[square as usize]))
-                           .is_empty() {
-                        score += PASSED_WHITE_PAWN_BONUS[LOOKUP_RANK[square as usize]];
-                    }
-                },
-                //White knight
-                1  => {
-                    score += KNIGHT_SCORES[square as usize];
+            let material = Score::new(params.material_mg[piece_type], params.material_eg[piece_type]);
+            side_score += material;
+            tracer.add(EvalTerm::Material, white, material);
 
-                    //Mobility
-                    //score += (get_knight_attack_table(square).pop_count() - KNIGHT_UNIT) as i32 * KNIGHT_MOB;
-                    score += get_knight_attack_table(square).pop_count() as i32;
-                },
-                //White bishops
-                2  => {
-                    score += BISHOP_SCORES[square as usize];
+            let pst = Score::new(params.pst_mg[piece_type][table_square], params.pst_eg[piece_type][table_square]);
+            side_score += pst;
+            tracer.add(EvalTerm::PieceSquare, white, pst);
 
-                    //Mobility
-                    //score += (get_bishop_attack_table(square, game.all_occupancies).pop_count() - BISHOP_UNIT) as i32 * BISHOP_MOB;
-                    score += (get_bishop_attack_table(square, game.all_occupancies).pop_count()) as i32;
+            //Pawns are evaluated by evaluate_pawns
+            if piece_type == 0 {
+                continue;
+            }
 
-                },
-                //White Rooks
-                3  => {
-                    score += ROOK_SCORES[square as usize];
+            //Kings
+            if piece_type == 5 {
+                //Semi open file penalty
+                if own_pawns.and(Bitboard::from_u64(FILE_MASKS[square as usize])).is_empty() {
+                    let value = params.king_semi_open_file_penalty;
+                    side_score += value;
+                    tracer.add(EvalTerm::KingSafety, white, value);
+                }
 
-                    //Semi open file bonus
-                    if game.get_piece_bitboard(Piece::WhitePawn)
-                           .and(Bitboard::from_u64(FILE_MASKS[square as usize]))
-                           .is_empty() {
-                        score += SEMI_OPEN_FILE_SCORE;
-                    }
+                //Open file penalty
+                if all_pawns.and(Bitboard::from_u64(FILE_MASKS[square as usize])).is_empty() {
+                    let value = params.king_open_file_penalty;
+                    side_score += value;
+                    tracer.add(EvalTerm::KingSafety, white, value);
+                }
 
-                    //Open file bonus
-                    if (game.get_piece_bitboard(Piece::WhitePawn)
-                            .or(game.get_piece_bitboard(Piece::BlackPawn)))
-                                .and(Bitboard::from_u64(FILE_MASKS[square as usize])).is_empty() {
-                        score += OPEN_FILE_SCORE;
-                    }
+                side_score += evaluate_king_safety(game, params, white, square, attacks[side], tracer);
+                continue;
+            }
 
-                    //Mobility
-                    //score += (get_rook_attack_table(square, game.all_occupancies).pop_count() - ROOK_UNIT) as i32 * ROOK_MOB;
-                    score += get_rook_attack_table(square, game.all_occupancies).pop_count() as i32;
+            let piece_attack = piece_attacks(piece_type, square, game.all_occupancies);
+            let mobility = piece_attack.and(mobility_area).pop_count() as usize;
+            let value = match piece_type {
+                1 => params.knight_mobility[mobility],
+                2 => params.bishop_mobility[mobility],
+                3 => params.rook_mobility[mobility],
+                _ => params.queen_mobility[mobility]
+            };
+            side_score += value;
+            tracer.add(EvalTerm::Mobility, white, value);
+
+            let rank = relative_rank(square, white);
+            match piece_type {
+                //Knights
+                1 => {
+                    //Outposts, where no enemy pawn on the neighbouring files can chase the knight away
+                    let chasing_pawns = if white { WHITE_PASSED_PAWN_MASKS[square as usize] } else { BLACK_PASSED_PAWN_MASKS[square as usize] }
+                        & ISOLATED_MASKS[square as usize];
+                    if (3..=5).contains(&rank) && pawn_attacks[side].get_bit(square) && enemy_pawns.and(Bitboard::from_u64(chasing_pawns)).is_empty() {
+                        let value = params.knight_outpost;
+                        side_score += value;
+                        tracer.add(EvalTerm::Outposts, white, value);
+                    }
                 },
-                //White queen
-                4 => {
-                    //Mobility
-                    //score += ((get_queen_attack_table(square, game.all_occupancies).pop_count() - QUEEN_UNIT) as f32 * QUEEN_MOB) as i32;
-                    score += get_queen_attack_table(square, game.all_occupancies).pop_count() as i32;
+                //Bishops
+                2 => {
+                    //Bishop on a7/h7 shut in by a pawn on b6/g6, for black mirrored to a2/h2 and b3/g3
+                    for (bishop_square, pawn_square) in [(8, 17), (15, 22)] {
+                        let (bishop_square, pawn_square) = if white { (bishop_square, pawn_square) } else { (MIRRORED[bishop_square], MIRRORED[pawn_square]) };
+                        if square as usize == bishop_square && enemy_pawns.get_bit(pawn_square as u8) {
+                            let value = params.trapped_bishop;
+                            side_score += value;
+                            tracer.add(EvalTerm::TrappedPieces, white, value);
+                        }
+                    }
                 },
-                //White king
-                5  => {
-                    score += KING_SCORES[square as usize];
-
-                    //Semi open file penalty
-                    if game.get_piece_bitboard(Piece::WhitePawn)
-                           .and(Bitboard::from_u64(FILE_MASKS[square as usize]))
-                           .is_empty() {
-                        score -= SEMI_OPEN_FILE_SCORE;
+                //Rooks
+                3 => {
+                    //Semi open file bonus
+                    if own_pawns.and(Bitboard::from_u64(FILE_MASKS[square as usize])).is_empty() {
+                        let value = params.semi_open_file_score;
+                        side_score += value;
+                        tracer.add(EvalTerm::RookFiles, white, value);
                     }
 
-                    //Open file penalty
-                    if (game.get_piece_bitboard(Piece::WhitePawn)
-                            .or(game.get_piece_bitboard(Piece::BlackPawn)))
-                                .and(Bitboard::from_u64(FILE_MASKS[square as usize])).is_empty() {
-                        score -= OPEN_FILE_SCORE;
+                    //Open file bonus
+                    if all_pawns.and(Bitboard::from_u64(FILE_MASKS[square as usize])).is_empty() {
+                        let value = params.open_file_score;
+                        side_score += value;
+                        tracer.add(EvalTerm::RookFiles, white, value);
                     }
 
-                    //King safety
-                    score += get_king_attack_table(square).and(game.white_occupancies).pop_count() as i32 * PROTECTED_KING_BONUS;
-                },
-                //Black pawns
-                6  => {
-                    score -= PAWN_SCORES[MIRRORED[square as usize]];
-                    
-                    //Stacked pawn penalty
-                    stacked_pawns = game.get_piece_bitboard(Piece::BlackPawn)
-                                        .and(Bitboard::from_u64(FILE_MASKS[square as usize]))
-                                        .pop_count();
-                    if stacked_pawns > 1 {
-                        score -= stacked_pawns as i32 * STACKED_PAWN_PENALTY;
+                    //Rook behind a passed pawn, which it defends or holds back as it advances
+                    let mut passers = piece_attack.and(passed_pawns).and(Bitboard::from_u64(FILE_MASKS[square as usize]));
+                    while !passers.is_empty() {
+                        let pawn_square = passers.extract_bit();
+                        let white_pawn = game.get_piece_bitboard(Piece::WhitePawn).get_bit(pawn_square);
+                        if (white_pawn && square > pawn_square) || (!white_pawn && square < pawn_square) {
+                            let value = params.rook_behind_passer;
+                            side_score += value;
+                            tracer.add(EvalTerm::RookFiles, white, value);
+                        }
                     }
 
-                    //Isolated pawn penalty
-                    if game.get_piece_bitboard(Piece::BlackPawn)
-                           .and(Bitboard::from_u64(ISOLATED_MASKS[square as usize]))
-                           .is_empty() {
-                        score -= ISOLATED_PAWN_PENALTY;
+                    //Rook on the 7th, attacking pawns there or cutting off the king on the 8th
+                    let seventh_rank = Bitboard::from_u64(0xFF << (square / 8 * 8));
+                    let enemy_king = game.bitboards[if white { 11 } else { 5 }].least_significant();
+                    if rank == 6 && (!enemy_pawns.and(seventh_rank).is_empty() || relative_rank(enemy_king, white) == 7) {
+                        let value = params.rook_on_seventh;
+                        side_score += value;
+                        tracer.add(EvalTerm::RookOnSeventh, white, value);
                     }
 
-                    //Passed pawn penalty
-                    if game.get_piece_bitboard(Piece::WhitePawn)
-                           .and(Bitboard::from_u64(BLACK_PASSED_PAWN_MASKS[square as usize]))
-                           .is_empty() {
-                        score -= PASSED_BLACK_PAWN_BONUS[LOOKUP_RANK[square as usize]];
+                    //Rook in the corner, behind its uncastled king
+                    let king_square = game.bitboards[if white { 5 } else { 11 }].least_significant();
+                    let (file, king_file) = (square % 8, king_square % 8);
+                    if rank == 0 && relative_rank(king_square, white) == 0 && mobility <= 3
+                        && ((king_file < 4 && file < king_file) || (king_file >= 4 && file > king_file)) {
+                        let value = params.trapped_rook;
+                        side_score += value;
+                        tracer.add(EvalTerm::TrappedPieces, white, value);
                     }
                 },
-                //Black knight
-                7  => {
-                    score -= KNIGHT_SCORES[MIRRORED[square as usize]];
+                _ => {}
+            };
+        }
 
-                    //Mobility
-                    //score -= (get_knight_attack_table(square).pop_count() - KNIGHT_UNIT) as i32 * KNIGHT_MOB;
-                    score -= get_knight_attack_table(square).pop_count() as i32;
-                },
-                //Black bishop
-                8  => {
-                    score -= BISHOP_SCORES[MIRRORED[square as usize]];
+        if piece_type == 2 && game.bitboards[bb].to_u64().count_ones() >= 2 {
+            let value = params.bishop_pair;
+            side_score += value;
+            tracer.add(EvalTerm::BishopPair, white, value);
+        }
 
-                    //Mobility
-                    //score -= (get_bishop_attack_table(square, game.all_occupancies).pop_count() - BISHOP_UNIT) as i32 * BISHOP_MOB;
-                    score -= get_bishop_attack_table(square, game.all_occupancies).pop_count() as i32;
-                },
-                //Black rooks
-                9  => {
-                    score -= ROOK_SCORES[MIRRORED[square as usize]];
+        //Threats against the enemy pieces of this type, counted for the attacking side
+        if (1..5).contains(&piece_type) {
+            let enemy_pieces = game.bitboards[if white { bb + 6 } else { bb - 6 }];
 
-                    //Semi open file bonus
-                    if game.get_piece_bitboard(Piece::BlackPawn)
-                           .and(Bitboard::from_u64(FILE_MASKS[square as usize]))
-                           .is_empty() {
-                        score -= SEMI_OPEN_FILE_SCORE;
-                    }
+            let value = params.pawn_threat * enemy_pieces.and(pawn_attacks[side]).pop_count() as i32;
+            side_score += value;
+            tracer.add(EvalTerm::Threats, white, value);
 
-                    //Open file bonus
-                    if (game.get_piece_bitboard(Piece::BlackPawn)
-                            .or(game.get_piece_bitboard(Piece::WhitePawn)))
-                                .and(Bitboard::from_u64(FILE_MASKS[square as usize])).is_empty() {
-                        score -= OPEN_FILE_SCORE;
-                    }
+            let value = params.hanging_piece * enemy_pieces.and(attacks[side]).and(not(attacks[enemy])).pop_count() as i32;
+            side_score += value;
+            tracer.add(EvalTerm::Threats, white, value);
+        }
 
-                    //Mobility
-                    //score -= (get_rook_attack_table(square, game.all_occupancies).pop_count() - ROOK_UNIT) as i32 * ROOK_MOB;
-                    score -= get_rook_attack_table(square, game.all_occupancies).pop_count() as i32;
-                },
-                //Black queen
-                10 => {
-                    //Mobility
-                    //score -= ((get_queen_attack_table(square, game.all_occupancies).pop_count() - QUEEN_UNIT) as f32 * QUEEN_MOB) as i32;
-                    score -= get_queen_attack_table(square, game.all_occupancies).pop_count() as i32;
-                }
-                //Black king
-                11 => {
-                    score -= KING_SCORES[MIRRORED[square as usize]];
-
-                    //Semi open file penalty
-                    if game.get_piece_bitboard(Piece::BlackPawn)
-                           .and(Bitboard::from_u64(FILE_MASKS[square as usize]))
-                           .is_empty() {
-                        score += SEMI_OPEN_FILE_SCORE;
-                    }
+        if white { score += side_score } else { score -= side_score }
+    }
 
-                    //Open file penalty
-                    if (game.get_piece_bitboard(Piece::BlackPawn)
-                            .or(game.get_piece_bitboard(Piece::WhitePawn)))
-                                .and(Bitboard::from_u64(FILE_MASKS[square as usize])).is_empty() {
-                        score += OPEN_FILE_SCORE;
-                    }
+    score
+}
 
-                    //King safety
-                    score -= get_king_attack_table(square).and(game.black_occupancies).pop_count() as i32 * PROTECTED_KING_BONUS;
-                },
-                _ => unreachable!()
-            };
+///Pawn shield, pawn storm and attack units against the king of the given side, from that side's point of view.
+///The attack units of all pieces attacking the king zone, and of those able to give a safe check, are looked up
+///in the king danger table once at least two pieces take part in the attack
+fn evaluate_king_safety(game: &Game, params: &EvalParams, white: bool, king_square: u8, defended: Bitboard, tracer: &mut impl EvalTracer) -> Score {
+    let (own_pawns, enemy_pawns, enemy_offset, enemy_occupancies) = if white {
+        (game.get_piece_bitboard(Piece::WhitePawn), game.get_piece_bitboard(Piece::BlackPawn), 6, game.black_occupancies)
+    } else {
+        (game.get_piece_bitboard(Piece::BlackPawn), game.get_piece_bitboard(Piece::WhitePawn), 0, game.white_occupancies)
+    };
+
+    let mut score = Score::ZERO;
+
+    //Pawn shield and storm on the king file and its neighbours
+    let king_rank = relative_rank(king_square, white);
+    let king_file = king_square % 8;
+    for file in king_file.saturating_sub(1)..=(king_file + 1).min(7) {
+        let file_mask = Bitboard::from_u64(FILE_MASKS[file as usize]);
+
+        let shield_distance = closest_pawn_distance(own_pawns.and(file_mask), king_rank, white).unwrap_or(3).min(3);
+        let value = params.pawn_shield[shield_distance - 1];
+        score += value;
+        tracer.add(EvalTerm::KingSafety, white, value);
+
+        if let Some(storm_distance) = closest_pawn_distance(enemy_pawns.and(file_mask), king_rank, white) {
+            if storm_distance <= 4 {
+                let value = params.pawn_storm[storm_distance - 1];
+                score += value;
+                tracer.add(EvalTerm::KingSafety, white, value);
+            }
         }
     }
 
-    if game.active_player == Color::White { score } else { -score }
+    //The squares around the king and the rank in front of them
+    let around_king = get_king_attack_table(king_square).to_u64() | 1 << king_square;
+    let zone = Bitboard::from_u64(if white { around_king | around_king >> 8 } else { around_king | around_king << 8 });
+
+    //Checking squares that aren't defended, and not occupied by the attacker's own pieces
+    let safe = not(defended.or(enemy_occupancies));
+    let knight_checks = get_knight_attack_table(king_square).and(safe);
+    let bishop_checks = get_bishop_attack_table(king_square, game.all_occupancies).and(safe);
+    let rook_checks = get_rook_attack_table(king_square, game.all_occupancies).and(safe);
+
+    let mut attackers = 0;
+    let mut attack_units = 0;
+    for piece_type in 1..5 {
+        let checks = match piece_type {
+            1 => knight_checks,
+            2 => bishop_checks,
+            3 => rook_checks,
+            _ => bishop_checks.or(rook_checks)
+        };
+
+        let mut board = game.bitboards[piece_type + enemy_offset];
+        while !board.is_empty() {
+            let attacks = piece_attacks(piece_type, board.extract_bit(), game.all_occupancies);
+
+            let zone_attacks = attacks.and(zone).pop_count() as i32;
+            if zone_attacks > 0 {
+                attackers += 1;
+                attack_units += params.king_attack_weights[piece_type - 1] * zone_attacks;
+            }
+
+            if !attacks.and(checks).is_empty() {
+                attack_units += params.safe_check_weights[piece_type - 1];
+            }
+        }
+    }
+
+    if attackers >= 2 {
+        let value = Score::new(-params.king_danger[attack_units.clamp(0, 99) as usize], 0);
+        score += value;
+        tracer.add(EvalTerm::KingSafety, white, value);
+    }
+
+    score
+}
+
+///Rank counted from the given side's back rank
+fn relative_rank(square: u8, white: bool) -> usize {
+    if white { LOOKUP_RANK[square as usize] } else { 7 - LOOKUP_RANK[square as usize] }
+}
+
+///Number of ranks to the closest of the pawns in front of the king, from the king's side
+fn closest_pawn_distance(mut pawns: Bitboard, king_rank: usize, white: bool) -> Option<usize> {
+    let mut closest = None;
+    while !pawns.is_empty() {
+        let rank = relative_rank(pawns.extract_bit(), white);
+        if rank > king_rank {
+            closest = Some(closest.map_or(rank - king_rank, |c: usize| c.min(rank - king_rank)));
+        }
+    }
+    closest
+}
+
+///Attacks of a knight, bishop, rook or queen
+fn piece_attacks(piece_type: usize, square: u8, occupancies: Bitboard) -> Bitboard {
+    match piece_type {
+        1 => get_knight_attack_table(square),
+        2 => get_bishop_attack_table(square, occupancies),
+        3 => get_rook_attack_table(square, occupancies),
+        4 => get_queen_attack_table(square, occupancies),
+        _ => unreachable!()
+    }
+}
+
+///Every square attacked by the pawns of the given side
+fn pawn_attacks(game: &Game, white: bool) -> Bitboard {
+    let (mut pawns, color) = if white { (game.bitboards[0], Color::White) } else { (game.bitboards[6], Color::Black) };
+    let mut attacked = Bitboard::new();
+    while !pawns.is_empty() {
+        attacked = attacked.or(get_pawn_attack_table(pawns.extract_bit(), color));
+    }
+    attacked
+}
+
+///Every square attacked by the given side
+fn attacked_squares(game: &Game, white: bool) -> Bitboard {
+    let offset = if white { 0 } else { 6 };
+    let mut attacked = pawn_attacks(game, white);
+
+    for piece_type in 1..5 {
+        let mut board = game.bitboards[piece_type + offset];
+        while !board.is_empty() {
+            attacked = attacked.or(piece_attacks(piece_type, board.extract_bit(), game.all_occupancies));
+        }
+    }
+
+    attacked.or(get_king_attack_table(game.bitboards[offset + 5].least_significant()))
+}
+
+///Stacked, isolated and passed pawn terms, which only depend on the pawns
+pub fn evaluate_pawns(game: &Game, params: &EvalParams, tracer: &mut impl EvalTracer) -> PawnEntry {
+    let mut entry = PawnEntry { hash: game.pawn_hash, ..Default::default() };
+
+    for white in [true, false] {
+        let (own_pawns, enemy_pawns, passed_masks) = if white {
+            (game.get_piece_bitboard(Piece::WhitePawn), game.get_piece_bitboard(Piece::BlackPawn), &WHITE_PASSED_PAWN_MASKS)
+        } else {
+            (game.get_piece_bitboard(Piece::BlackPawn), game.get_piece_bitboard(Piece::WhitePawn), &BLACK_PASSED_PAWN_MASKS)
+        };
+
+        let mut side_score = Score::ZERO;
+
+        let mut board = own_pawns;
+        while !board.is_empty() {
+            let square = board.extract_bit();
+            let table_square = if white { square as usize } else { MIRRORED[square as usize] };
+
+            //Stacked pawn penalty
+            let stacked_pawns = own_pawns.and(Bitboard::from_u64(FILE_MASKS[square as usize])).pop_count();
+            if stacked_pawns > 1 {
+                let value = params.stacked_pawn_penalty * stacked_pawns as i32;
+                side_score += value;
+                tracer.add(EvalTerm::StackedPawns, white, value);
+            }
+
+            //Isolated pawn penalty
+            if own_pawns.and(Bitboard::from_u64(ISOLATED_MASKS[square as usize])).is_empty() {
+                let value = params.isolated_pawn_penalty;
+                side_score += value;
+                tracer.add(EvalTerm::IsolatedPawns, white, value);
+            }
+
+            //Passed pawn bonus
+            if enemy_pawns.and(Bitboard::from_u64(passed_masks[square as usize])).is_empty() {
+                let value = params.passed_pawn_bonus[LOOKUP_RANK[table_square]];
+                side_score += value;
+                tracer.add(EvalTerm::PassedPawns, white, value);
+                entry.passed_pawns[if white { 0 } else { 1 }].set_bit(square);
+            }
+        }
+
+        if white { entry.score += side_score } else { entry.score -= side_score }
+    }
+
+    entry
 }
 
 const fn generate_file_masks() -> [u64; 64] {
@@ -479,6 +877,149 @@ mod eval_tests {
     pub fn eval () {
         let game = Game::new_from_fen("6k1/ppppprbp/8/8/8/8/PPPPPRBP/6K1 w - - 0 1 ").unwrap();
         game.pretty_print();
-        println!("{}", evaluate(&game));
+        println!("{}", evaluate(&game, &EvalParams::default()));
+    }
+
+    #[test]
+    pub fn phase_runs_from_opening_to_pawn_ending() {
+        assert_eq!(game_phase(&Game::new_from_start_pos()), TOTAL_PHASE);
+        assert_eq!(game_phase(&Game::new_from_fen("4k3/pppp4/8/8/8/8/PPPP4/4K3 w - - 0 1").unwrap()), 0);
+        assert_eq!(game_phase(&Game::new_from_fen("3qk3/8/8/8/8/8/8/3RK3 w - - 0 1").unwrap()), 6);
+    }
+
+    #[test]
+    pub fn taper_interpolates_by_phase() {
+        let score = Score::new(100, 200);
+        assert_eq!(score.taper(TOTAL_PHASE), 100);
+        assert_eq!(score.taper(0), 200);
+        assert_eq!(score.taper(TOTAL_PHASE / 2), 150);
+    }
+
+    #[test]
+    pub fn king_centralizes_in_pawn_endings() {
+        let central = Game::new_from_fen("4k3/p7/8/8/3K4/8/P7/8 w - - 0 1").unwrap();
+        let corner = Game::new_from_fen("4k3/p7/8/8/8/8/P7/7K w - - 0 1").unwrap();
+        assert!(evaluate(&central, &EvalParams::default()) > evaluate(&corner, &EvalParams::default()));
+    }
+
+    #[test]
+    pub fn king_hides_in_the_middlegame() {
+        let castled = Game::new_from_fen("r1bq1rk1/pppp1ppp/2n2n2/2b1p3/2B1P3/2N2N2/PPPP1PPP/R1BQ1RK1 w - - 0 1").unwrap();
+        let central = Game::new_from_fen("r1bq1rk1/pppp1ppp/2n2n2/2b1p3/2B1P3/2NK1N2/PPPP1PPP/R1BQ1R2 w - - 0 1").unwrap();
+        assert!(evaluate(&castled, &EvalParams::default()) > evaluate(&central, &EvalParams::default()));
+    }
+
+    ///Swaps the colors and flips the board vertically. Castling rights and en passant are left out
+    fn flip_colors(fen: &str) -> String {
+        let fields: Vec<&str> = fen.split_whitespace().collect();
+        let board: Vec<String> = fields[0].split('/').rev().map(|rank| rank.chars().map(|c|
+            if c.is_ascii_uppercase() { c.to_ascii_lowercase() } else { c.to_ascii_uppercase() }).collect()).collect();
+        format!("{} {} - - 0 1", board.join("/"), if fields[1] == "w" { "b" } else { "w" })
+    }
+
+    ///Flips the board horizontally, a-file to h-file
+    fn flip_files(fen: &str) -> String {
+        let fields: Vec<&str> = fen.split_whitespace().collect();
+        let board: Vec<String> = fields[0].split('/').map(|rank| rank.chars().rev().collect()).collect();
+        format!("{} {} - - 0 1", board.join("/"), fields[1])
+    }
+
+    const KING_ATTACK_FENS: [&str; 4] = [
+        "r1b2rk1/ppq2ppp/2n1p3/3pP1N1/3P2Q1/2PB4/P4PPP/R1B2RK1 w - - 0 1",
+        "6k1/5p1p/4pBpQ/8/8/8/5PPP/6K1 b - - 0 1",
+        "2kr3r/ppp2ppp/2n5/4q3/1b6/2N1B3/PPP1QPPP/R3K2R w - - 0 1",
+        "r4rk1/1p3pp1/p1n1b2p/4N3/2B1Q3/8/PP3PPP/3R1RK1 b - - 0 1",
+    ];
+
+    #[test]
+    pub fn king_safety_is_symmetric() {
+        let params = EvalParams::default();
+        for fen in KING_ATTACK_FENS {
+            let game = Game::new_from_fen(fen).unwrap();
+            let flipped = Game::new_from_fen(&flip_colors(fen)).unwrap();
+            let mirrored = Game::new_from_fen(&flip_files(fen)).unwrap();
+
+            assert_eq!(evaluate(&game, &params), evaluate(&flipped, &params));
+
+            let [white, black] = trace_evaluation(&game, &params).terms[EvalTerm::KingSafety as usize];
+            let [flipped_white, flipped_black] = trace_evaluation(&flipped, &params).terms[EvalTerm::KingSafety as usize];
+            assert_eq!((white, black), (flipped_black, flipped_white));
+
+            //The tables aren't symmetric between the wings, but king safety is
+            assert_eq!(trace_evaluation(&mirrored, &params).terms[EvalTerm::KingSafety as usize], [white, black]);
+        }
+    }
+
+    #[test]
+    pub fn attacked_king_is_penalized() {
+        let params = EvalParams::default();
+        //The same queen and knight, attacking the king and far away from it
+        let attacked = Game::new_from_fen("6k1/5ppp/8/6N1/7Q/8/5PPP/6K1 w - - 0 1").unwrap();
+        let quiet = Game::new_from_fen("6k1/5ppp/8/8/8/8/N4PPP/Q5K1 w - - 0 1").unwrap();
+
+        let attacked_black = trace_evaluation(&attacked, &params).terms[EvalTerm::KingSafety as usize][1];
+        let quiet_black = trace_evaluation(&quiet, &params).terms[EvalTerm::KingSafety as usize][1];
+        assert!(attacked_black.mg < quiet_black.mg);
+    }
+
+    #[test]
+    pub fn pawn_shield_beats_pawn_storm() {
+        let params = EvalParams::default();
+        let sheltered = Game::new_from_fen("6k1/5ppp/8/8/8/8/5PPP/6K1 w - - 0 1").unwrap();
+        let stormed = Game::new_from_fen("6k1/5p1p/8/8/8/6p1/5P1P/6K1 w - - 0 1").unwrap();
+
+        let sheltered_white = trace_evaluation(&sheltered, &params).terms[EvalTerm::KingSafety as usize][0];
+        let stormed_white = trace_evaluation(&stormed, &params).terms[EvalTerm::KingSafety as usize][0];
+        assert!(sheltered_white.mg > stormed_white.mg);
+    }
+
+    fn traced_term(fen: &str, term: EvalTerm) -> [Score; 2] {
+        trace_evaluation(&Game::new_from_fen(fen).unwrap(), &EvalParams::default()).terms[term as usize]
+    }
+
+    #[test]
+    pub fn mobility_only_counts_safe_squares() {
+        //Of the knight's squares, three are taken by own pieces and two are attacked by the black pawns
+        let boxed_in = traced_term("4k3/8/8/2p1p3/8/3N4/1P3P2/4K3 w - - 0 1", EvalTerm::Mobility)[0];
+        let free = traced_term("4k3/8/8/8/8/3N4/8/7K w - - 0 1", EvalTerm::Mobility)[0];
+
+        assert_eq!(boxed_in, EvalParams::default().knight_mobility[3]);
+        assert_eq!(free, EvalParams::default().knight_mobility[8]);
+    }
+
+    #[test]
+    pub fn piece_terms_are_found() {
+        let params = EvalParams::default();
+
+        assert_eq!(traced_term("2b1kb2/8/8/8/8/8/8/2B1K3 w - - 0 1", EvalTerm::BishopPair), [Score::ZERO, params.bishop_pair]);
+        //Defended by the e4 pawn, and no black pawn on the d or f file can chase it
+        assert_eq!(traced_term("4k3/p6p/8/3N4/4P3/8/8/4K3 w - - 0 1", EvalTerm::Outposts)[0], params.knight_outpost);
+        assert_eq!(traced_term("4k3/p1p4p/8/3N4/4P3/8/8/4K3 w - - 0 1", EvalTerm::Outposts)[0], Score::ZERO);
+        assert_eq!(traced_term("6k1/pR3ppp/8/8/8/8/5PPP/6K1 w - - 0 1", EvalTerm::RookOnSeventh)[0], params.rook_on_seventh);
+        assert_eq!(traced_term("4k3/5ppp/8/8/8/8/r4PPP/6K1 b - - 0 1", EvalTerm::RookOnSeventh)[1], params.rook_on_seventh);
+        //Behind the d4 passer, also when the pawn belongs to the other side, but not in front of it or with a piece in between
+        assert_eq!(traced_term("4k3/8/8/8/3P4/8/8/3RK3 w - - 0 1", EvalTerm::RookFiles)[0], params.rook_behind_passer);
+        assert_eq!(traced_term("4k3/8/8/8/3P4/8/7K/3r4 w - - 0 1", EvalTerm::RookFiles)[1], params.semi_open_file_score + params.rook_behind_passer);
+        assert_eq!(traced_term("3rk3/8/8/8/3P4/8/8/4K3 b - - 0 1", EvalTerm::RookFiles)[1], params.semi_open_file_score);
+        assert_eq!(traced_term("4k3/8/8/8/3P4/8/3N4/3RK3 w - - 0 1", EvalTerm::RookFiles)[0], Score::ZERO);
+        assert_eq!(traced_term("r3k3/Bp6/1p6/8/8/8/8/4K3 w - - 0 1", EvalTerm::TrappedPieces)[0], params.trapped_bishop);
+        assert_eq!(traced_term("4k3/8/8/8/8/8/5PPP/5KR1 w - - 0 1", EvalTerm::TrappedPieces)[0], params.trapped_rook);
+    }
+
+    #[test]
+    pub fn threats_are_counted_for_the_attacker() {
+        let params = EvalParams::default();
+
+        //The knight is forked by a pawn, and the rook on a5 is hanging to the bishop
+        let [white, black] = traced_term("4k3/8/8/r7/2n5/1P6/3B4/4K3 w - - 0 1", EvalTerm::Threats);
+        assert_eq!(white, params.pawn_threat + params.hanging_piece);
+        assert_eq!(black, Score::ZERO);
+    }
+
+    #[test]
+    pub fn evaluation_is_symmetric() {
+        let white = Game::new_from_fen("r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 4 4").unwrap();
+        let black = Game::new_from_fen("rnbqk2r/pppp1ppp/5n2/2b1p3/4P3/2N2N2/PPPP1PPP/R1BQKB1R b KQkq - 4 4").unwrap();
+        assert_eq!(evaluate(&white, &EvalParams::default()), evaluate(&black, &EvalParams::default()));
     }
 }
\ No newline at end of file
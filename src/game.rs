@@ -1,5 +1,47 @@
+use std::fmt;
+
 use super::*;
 
+///Why a fen string could not be parsed
+#[derive(Debug, PartialEq)]
+pub enum FenError {
+    Empty,
+    RankCount(usize),
+    RankLength { rank: usize, squares: usize },
+    InvalidPiece(char),
+    KingCount { color: Color, count: u32 },
+    PawnOnBackRank(&'static str),
+    MissingActiveColor,
+    InvalidActiveColor(String),
+    InvalidCastling(String),
+    CastlingWithoutPieces(char),
+    InvalidEnpassant(String),
+    InvalidHalfMoves(String),
+    InvalidFullMoves(String),
+    InactiveKingInCheck,
+}
+
+impl fmt::Display for FenError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FenError::Empty => write!(f, "Empty fen string"),
+            FenError::RankCount(count) => write!(f, "Expected 8 ranks, found {}", count),
+            FenError::RankLength { rank, squares } => write!(f, "Rank {} has {} squares instead of 8", rank, squares),
+            FenError::InvalidPiece(char) => write!(f, "Unknown piece '{}'", char),
+            FenError::KingCount { color, count } => write!(f, "{:?} has {} kings instead of 1", color, count),
+            FenError::PawnOnBackRank(square) => write!(f, "Pawn on the back rank at {}", square),
+            FenError::MissingActiveColor => write!(f, "Missing active color"),
+            FenError::InvalidActiveColor(active) => write!(f, "Active color must be 'w' or 'b', not '{}'", active),
+            FenError::InvalidCastling(castling) => write!(f, "Illegal castling rights '{}'", castling),
+            FenError::CastlingWithoutPieces(right) => write!(f, "Castling right '{}' without the king and rook on their squares", right),
+            FenError::InvalidEnpassant(square) => write!(f, "Illegal en passant square '{}'", square),
+            FenError::InvalidHalfMoves(half_moves) => write!(f, "Illegal half move clock '{}'", half_moves),
+            FenError::InvalidFullMoves(full_moves) => write!(f, "Illegal full move number '{}'", full_moves),
+            FenError::InactiveKingInCheck => write!(f, "The side not to move is in check"),
+        }
+    }
+}
+
 #[derive(Clone, Copy)]
 pub struct Game {
     pub bitboards: [Bitboard; 12],
@@ -16,6 +58,8 @@ pub struct Game {
     pub full_moves: u16,
     pub half_moves: u8,
     pub zobrist_hash: u64,
+    ///Zobrist hash of the pawns alone, for the pawn hash table
+    pub pawn_hash: u64,
 }
 
 impl Game {
@@ -65,57 +109,150 @@ impl Game {
         result
     }
 
+    pub fn to_fen(self) -> String {
+        let mut fen = String::new();
+        for y in 0..8 {
+            let mut empty = 0;
+            for x in 0..8 {
+                match (0..12).find(|piece| self.bitboards[*piece].get_bit(8 * y + x)) {
+                    Some(piece) => {
+                        if empty > 0 { fen += &empty.to_string(); empty = 0; }
+                        fen += PIECE_STRINGS[piece];
+                    },
+                    None => empty += 1
+                }
+            }
+            if empty > 0 { fen += &empty.to_string(); }
+            if y != 7 { fen += "/"; }
+        }
+
+        let castling = self.castling_ability_string();
+        let enpassant = if self.enpassant_square == Square::None { "-" } else { SQUARE_STRINGS[self.enpassant_square as usize] };
+
+        format!("{} {} {} {} {} {}",
+            fen,
+            if self.active_player == Color::White { "w" } else { "b" },
+            if castling.is_empty() { "-" } else { castling.as_str() },
+            enpassant,
+            self.half_moves,
+            self.full_moves)
+    }
+
     pub fn new_from_start_pos() -> Self {
         Game::new_from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1").unwrap()
     }
 
-    pub fn new_from_fen(input: &str) -> Option<Self> {
-        let fen = input.trim();
-        let mut split = fen.split(' ').peekable();
+    pub fn new_from_fen(input: &str) -> Result<Self, FenError> {
+        let mut split = input.split_whitespace();
 
         let mut bitboards =        [Bitboard::new(); 12];
         let mut white_occupancies = Bitboard::new();
         let mut black_occupancies = Bitboard::new();
         let mut all_occupancies =   Bitboard::new();
 
-        let mut i = 0;
+        let board_str = split.next().ok_or(FenError::Empty)?;
+        let ranks: Vec<&str> = board_str.split('/').collect();
+        if ranks.len() != 8 {
+            return Err(FenError::RankCount(ranks.len()));
+        }
 
-        if split.peek().is_none() { return None }
-        let board_str = split.next().unwrap();
+        for (y, rank) in ranks.iter().enumerate() {
+            let mut x = 0;
+            for char in rank.chars() {
+                if ('1'..='8').contains(&char) {
+                    x += char.to_digit(10).unwrap_or(0) as usize;
+                    continue;
+                }
 
-        for char in board_str.chars() {
-            if char.is_numeric(){
-                for _i in 0..char.to_digit(10).unwrap_or(0) {
-                    i += 1;
+                let piece = char_to_piece(char).ok_or(FenError::InvalidPiece(char))?;
+                if x < 8 {
+                    let square = (8 * y + x) as u8;
+                    bitboards[piece as usize].set_bit(square);
+                    all_occupancies.set_bit(square);
+                    if char.is_uppercase() { white_occupancies.set_bit(square) } else { black_occupancies.set_bit(square) };
                 }
+                x += 1;
             }
-            else if char != '/' {
-                let piece = char_to_piece(char);
-                if piece.is_none() { return None };
-                bitboards[char_to_piece(char).unwrap() as usize].set_bit(i);
-                all_occupancies.set_bit(i);
-                if char.is_uppercase() { white_occupancies.set_bit(i) } else { black_occupancies.set_bit(i) };
-
-                i+=1;
+
+            if x != 8 {
+                return Err(FenError::RankLength { rank: 8 - y, squares: x });
             }
         }
 
-        if split.peek().is_none() { return None }
-        let active = split.next().unwrap();
-        let active_color = if active == "w" { Color::White } else { Color::Black };
+        for (king, color) in [(Piece::WhiteKing, Color::White), (Piece::BlackKing, Color::Black)] {
+            let count = bitboards[king as usize].to_u64().count_ones();
+            if count != 1 {
+                return Err(FenError::KingCount { color, count });
+            }
+        }
+
+        let pawns = bitboards[Piece::WhitePawn as usize].or(bitboards[Piece::BlackPawn as usize]).to_u64();
+        if pawns & 0xFF000000000000FF != 0 {
+            return Err(FenError::PawnOnBackRank(SQUARE_STRINGS[(pawns & 0xFF000000000000FF).trailing_zeros() as usize]));
+        }
+
+        let active_color = match split.next() {
+            Some("w") => Color::White,
+            Some("b") => Color::Black,
+            Some(active) => return Err(FenError::InvalidActiveColor(active.to_string())),
+            None => return Err(FenError::MissingActiveColor)
+        };
 
-        let castling_str =  if split.peek().is_some() { split.next().unwrap() } else { "" };
+        let castling_str = split.next().unwrap_or("-");
         let mut castling_ability: u8 = 0;
-        if castling_str.contains('K') {castling_ability = castling_ability | CastlingAbility::WhiteKingSide as u8 }
-        if castling_str.contains('Q') {castling_ability = castling_ability | CastlingAbility::WhiteQueenSide as u8}
-        if castling_str.contains('k') {castling_ability = castling_ability | CastlingAbility::BlackKingSide as u8}
-        if castling_str.contains('q') {castling_ability = castling_ability | CastlingAbility::BlackQueenSide as u8}
+        if castling_str != "-" {
+            for char in castling_str.chars() {
+                let right = match char {
+                    'K' => CastlingAbility::WhiteKingSide,
+                    'Q' => CastlingAbility::WhiteQueenSide,
+                    'k' => CastlingAbility::BlackKingSide,
+                    'q' => CastlingAbility::BlackQueenSide,
+                    _ => return Err(FenError::InvalidCastling(castling_str.to_string()))
+                } as u8;
+                if castling_ability & right != 0 {
+                    return Err(FenError::InvalidCastling(castling_str.to_string()));
+                }
+                castling_ability |= right;
+            }
+        }
 
-        let enpassant = if split.peek().is_some() { split.next().unwrap() } else { "-" };
-        let enpassant_sq: Square = if enpassant != "-" { square_from_string(enpassant) } else { Square::None };
+        //Castling moves the king and rook from their starting squares, so they must be there
+        for (char, right, king, rook, king_square, rook_square) in [
+            ('K', CastlingAbility::WhiteKingSide, Piece::WhiteKing, Piece::WhiteRook, Square::e1, Square::h1),
+            ('Q', CastlingAbility::WhiteQueenSide, Piece::WhiteKing, Piece::WhiteRook, Square::e1, Square::a1),
+            ('k', CastlingAbility::BlackKingSide, Piece::BlackKing, Piece::BlackRook, Square::e8, Square::h8),
+            ('q', CastlingAbility::BlackQueenSide, Piece::BlackKing, Piece::BlackRook, Square::e8, Square::a8)
+        ] {
+            if castling_ability & right as u8 != 0 &&
+               !(bitboards[king as usize].get_bit(king_square as u8) && bitboards[rook as usize].get_bit(rook_square as u8)) {
+                return Err(FenError::CastlingWithoutPieces(char));
+            }
+        }
 
-        let half_moves: u8 =  if split.peek().is_some() { split.next().unwrap().parse::<u8>().unwrap()  } else { 0 };
-        let full_moves: u16 = if split.peek().is_some() { split.next().unwrap().parse::<u16>().unwrap() } else { 0 };
+        //The en passant square is behind a pawn that just moved two squares, so the pawn is in front of it and
+        //the square it came from is empty
+        let enpassant = split.next().unwrap_or("-");
+        let enpassant_sq = if enpassant == "-" {
+            Square::None
+        } else {
+            let (expected_rank, pawn, forward) = if active_color == Color::White { ('6', Piece::BlackPawn, 8) } else { ('3', Piece::WhitePawn, -8) };
+            match square_from_string(enpassant) {
+                Some(square) if enpassant.ends_with(expected_rank) &&
+                                bitboards[pawn as usize].get_bit((square as i8 + forward) as u8) &&
+                                !all_occupancies.get_bit(square as u8) &&
+                                !all_occupancies.get_bit((square as i8 - forward) as u8) => square,
+                _ => return Err(FenError::InvalidEnpassant(enpassant.to_string()))
+            }
+        };
+
+        let half_moves = match split.next() {
+            Some(half_moves) => half_moves.parse::<u8>().map_err(|_| FenError::InvalidHalfMoves(half_moves.to_string()))?,
+            None => 0
+        };
+        let full_moves = match split.next() {
+            Some(full_moves) => full_moves.parse::<u16>().map_err(|_| FenError::InvalidFullMoves(full_moves.to_string()))?,
+            None => 1
+        };
 
         let mut game = Self { 
             bitboards: bitboards,
@@ -129,12 +266,18 @@ impl Game {
 
             full_moves: full_moves,
             half_moves: half_moves,
-            zobrist_hash: 0
+            zobrist_hash: 0,
+            pawn_hash: 0
         };
 
+        if game.is_in_check(opposite_color(active_color)) {
+            return Err(FenError::InactiveKingInCheck);
+        }
+
         game.zobrist_hash = game.make_zobrist_hash();
+        game.pawn_hash = game.make_pawn_hash();
 
-        Some(game)
+        Ok(game)
     }
 
     #[inline(always)]
@@ -182,6 +325,25 @@ impl Game {
         self.bitboards[piece as usize]
     }
 
+    ///Neither side has the material to deliver mate: bare kings, a single minor piece, or only bishops on one square color
+    pub fn is_insufficient_material(&self) -> bool {
+        let mating_material = self.bitboards[Piece::WhitePawn as usize].to_u64()  | self.bitboards[Piece::BlackPawn as usize].to_u64()  |
+                              self.bitboards[Piece::WhiteRook as usize].to_u64()  | self.bitboards[Piece::BlackRook as usize].to_u64()  |
+                              self.bitboards[Piece::WhiteQueen as usize].to_u64() | self.bitboards[Piece::BlackQueen as usize].to_u64();
+        if mating_material != 0 {
+            return false;
+        }
+
+        let knights = self.bitboards[Piece::WhiteKnight as usize].to_u64() | self.bitboards[Piece::BlackKnight as usize].to_u64();
+        let bishops = self.bitboards[Piece::WhiteBishop as usize].to_u64() | self.bitboards[Piece::BlackBishop as usize].to_u64();
+
+        if (knights | bishops).count_ones() <= 1 {
+            return true;
+        }
+
+        knights == 0 && (bishops & LIGHT_SQUARES == 0 || bishops & !LIGHT_SQUARES == 0)
+    }
+
     pub fn parse_move(&mut self, input: String) -> Option<Move> {
         let moves = generate_moves(self, MoveTypes::All).legal_values(self);
         let m = moves.iter().find(|m| m.to_uci() == input);
@@ -215,11 +377,24 @@ impl Game {
 
         hash
     }
+
+    pub fn make_pawn_hash(&self) -> u64 {
+        let mut hash = 0;
+
+        for piece in [Piece::WhitePawn as usize, Piece::BlackPawn as usize] {
+            let mut bb = self.bitboards[piece];
+            while !bb.is_empty() {
+                hash ^= PIECE_KEYS[piece][bb.extract_bit() as usize];
+            }
+        }
+
+        hash
+    }
 }
 
 #[cfg(test)]
 mod make_tests {
-    //use super::*;
+    use super::*;
 
     #[test]
     pub fn zobrist() {
@@ -228,4 +403,92 @@ mod make_tests {
 
         //debug_perft(&mut game, 5);
     }
-}
\ No newline at end of file
+
+    #[test]
+    pub fn to_fen_writes_all_fields() {
+        let fen = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+        assert_eq!(Game::new_from_fen(fen).unwrap().to_fen(), fen);
+
+        let fen = "rnbqkbnr/ppp1pppp/8/8/3pP3/8/PPPP1PPP/RNBQKBNR b Kq e3 3 12";
+        assert_eq!(Game::new_from_fen(fen).unwrap().to_fen(), fen);
+    }
+
+    #[test]
+    pub fn fen_round_trips_over_the_perft_positions() {
+        let fens = [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 10",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 10",
+            "r2q1rk1/pP1p2pp/Q4n2/bbp1p3/Np6/1B3NBn/pPPP1PPP/R3K2R b KQ - 0 1",
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+            "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+        ];
+
+        for fen in fens {
+            assert_eq!(fen, Game::new_from_fen(fen).unwrap().to_fen());
+        }
+
+        //Missing fields get their defaults
+        assert_eq!("4k3/8/8/8/8/8/8/4K3 b - - 0 1", Game::new_from_fen("4k3/8/8/8/8/8/8/4K3 b").unwrap().to_fen());
+    }
+
+    #[test]
+    pub fn illegal_fens_are_rejected() {
+        let cases = [
+            ("", FenError::Empty),
+            ("rnbqkbnr/pppppppp/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", FenError::RankCount(7)),
+            ("rnbqkbnr/pppppppp/8/8/7/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", FenError::RankLength { rank: 4, squares: 7 }),
+            ("rnbqkbnr/ppppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", FenError::RankLength { rank: 7, squares: 9 }),
+            ("rnbqkbnr/pppppppp/9/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", FenError::InvalidPiece('9')),
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQXBNR w KQkq - 0 1", FenError::InvalidPiece('X')),
+            ("4k3/8/8/8/8/8/8/8 w - - 0 1", FenError::KingCount { color: Color::White, count: 0 }),
+            ("4k3/8/8/8/8/8/8/3KK3 w - - 0 1", FenError::KingCount { color: Color::White, count: 2 }),
+            ("4k3/8/8/8/8/8/8/P3K3 w - - 0 1", FenError::PawnOnBackRank("a1")),
+            ("4k2p/8/8/8/8/8/8/4K3 w - - 0 1", FenError::PawnOnBackRank("h8")),
+            ("4k3/8/8/8/8/8/8/4K3", FenError::MissingActiveColor),
+            ("4k3/8/8/8/8/8/8/4K3 x - - 0 1", FenError::InvalidActiveColor("x".to_string())),
+            ("4k3/8/8/8/8/8/8/4K3 w KX - 0 1", FenError::InvalidCastling("KX".to_string())),
+            ("4k3/8/8/8/8/8/8/4K3 w KK - 0 1", FenError::InvalidCastling("KK".to_string())),
+            ("4k3/8/8/8/8/8/3K4/8 w K - 0 1", FenError::CastlingWithoutPieces('K')),
+            ("4k3/8/8/8/8/8/8/4K2R w KQ - 0 1", FenError::CastlingWithoutPieces('Q')),
+            ("r3k3/8/8/8/8/8/8/4K3 w k - 0 1", FenError::CastlingWithoutPieces('k')),
+            ("r2k4/8/8/8/8/8/8/4K3 w q - 0 1", FenError::CastlingWithoutPieces('q')),
+            ("4k3/8/8/8/8/8/8/4K3 w - e3 0 1", FenError::InvalidEnpassant("e3".to_string())),
+            ("4k3/8/8/8/8/8/8/4K3 w - z9 0 1", FenError::InvalidEnpassant("z9".to_string())),
+            ("4k3/8/8/8/8/8/8/4K3 w - e6 0 1", FenError::InvalidEnpassant("e6".to_string())),
+            ("4k3/4p3/8/4p3/8/8/8/4K3 w - e6 0 1", FenError::InvalidEnpassant("e6".to_string())),
+            ("4k3/8/8/8/4P3/8/4P3/4K3 b - e3 0 1", FenError::InvalidEnpassant("e3".to_string())),
+            ("4k3/8/8/8/8/8/8/4K3 w - - x 1", FenError::InvalidHalfMoves("x".to_string())),
+            ("4k3/8/8/8/8/8/8/4K3 w - - 300 1", FenError::InvalidHalfMoves("300".to_string())),
+            ("4k3/8/8/8/8/8/8/4K3 w - - 0 -1", FenError::InvalidFullMoves("-1".to_string())),
+            ("4k3/8/8/8/8/8/8/K3R3 w - - 0 1", FenError::InactiveKingInCheck),
+        ];
+
+        for (fen, error) in cases {
+            assert_eq!(Some(error), Game::new_from_fen(fen).err(), "{}", fen);
+        }
+    }
+
+    #[test]
+    pub fn bare_kings_and_single_minors_are_insufficient() {
+        assert!(Game::new_from_fen("4k3/8/8/8/8/8/8/4K3 w - - 0 1").unwrap().is_insufficient_material());
+        assert!(Game::new_from_fen("4k3/8/8/8/8/8/8/2N1K3 w - - 0 1").unwrap().is_insufficient_material());
+        assert!(Game::new_from_fen("4kb2/8/8/8/8/8/8/4K3 b - - 0 1").unwrap().is_insufficient_material());
+    }
+
+    #[test]
+    pub fn same_colored_bishops_are_insufficient() {
+        //c1 and f8 are both dark squares
+        assert!(Game::new_from_fen("4kb2/8/8/8/8/8/8/2B1K3 w - - 0 1").unwrap().is_insufficient_material());
+        //c1 is dark, c8 is light
+        assert!(!Game::new_from_fen("2b1k3/8/8/8/8/8/8/2B1K3 w - - 0 1").unwrap().is_insufficient_material());
+    }
+
+    #[test]
+    pub fn mating_material_is_sufficient() {
+        assert!(!Game::new_from_fen("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1").unwrap().is_insufficient_material());
+        assert!(!Game::new_from_fen("4k3/8/8/8/8/8/8/3RK3 w - - 0 1").unwrap().is_insufficient_material());
+        assert!(!Game::new_from_fen("4k3/8/8/8/8/8/8/1NN1K3 w - - 0 1").unwrap().is_insufficient_material());
+        assert!(!Game::new_from_fen("4k3/8/8/8/8/8/8/1NB1K3 w - - 0 1").unwrap().is_insufficient_material());
+    }
+}
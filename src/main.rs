@@ -11,9 +11,21 @@ mod perft;
 mod evaluation;
 mod transposition_table;
 mod repetition_table;
-
-use core::panic;
-use std::{io::{self}, process, time::SystemTime};
+mod eval_params;
+mod tuner;
+mod nnue;
+mod datagen;
+mod eval_trace;
+mod pawn_table;
+mod endgame;
+mod syzygy;
+mod uci;
+mod san;
+mod pgn;
+mod epd;
+mod polyglot;
+
+use std::{fs, io::{self}, process, time::SystemTime};
 
 use game::*;
 
@@ -29,233 +41,268 @@ use perft::*;
 use evaluation::*;
 use transposition_table::*;
 use repetition_table::*;
+use eval_params::*;
+use tuner::*;
+use nnue::*;
+use datagen::*;
+use eval_trace::*;
+use pawn_table::*;
+use endgame::*;
+use syzygy::*;
+use uci::*;
+use pgn::*;
+use epd::*;
+use polyglot::*;
+
+///The state kept between commands
+struct Engine {
+    game: Game,
+    tt: TranspositionTable,
+    repetition_table: RepetitionTable,
+    evaluator: Evaluator,
+    book: Option<OpeningBook>,
+    ///Play from the book before searching
+    own_book: bool,
+    book_selection: BookSelection,
+}
 
-fn main() {
-    let io_receiver = IoWrapper::init();
-
-    let mut game = Game::new_from_start_pos();
-
-    let mut tt = TranspositionTable::new();
-
-    let mut repetition_table = RepetitionTable::new();
-    
-    loop {
-        let input = io_receiver.read_line();
-        if input != "" {
-            let mut split = input.split(" ").peekable();
-            match split.next().unwrap().to_ascii_lowercase().as_str() {
-                "exit" | "x" | "quit" => { println!(" Exited!"); process::exit(0) },
-                "help" => print_help(),
-                "d" => { game.pretty_print(); }
-                "position" => {
-                    if !split.peek().is_some() { continue; }
-                    repetition_table.clear();
-                    let p = parse_position(input.split_at(9).1.to_string(), &mut repetition_table);
-
-                    if p.is_none() {
-                        panic!(" Illegal fen string");
-                    } else {
-                        game = p.unwrap();
-                    }
-                },
-                "perft" => {
-                    if !split.peek().is_some() { continue; }
-                    let mut split2 = split.next().unwrap().splitn(2, " ").peekable();
-                    if !split2.peek().is_some() { continue; }
-                    let pos = split2.next().unwrap().to_string();
-                    let depth = (if pos == "simple" { if !split2.peek().is_some() { println!(" Please provide depth"); continue; } split2.next().unwrap() } else { pos.as_str() }).parse::<u8>().unwrap();
-                    go_perft(depth, game, pos != "simple");
-                },
-                "perft!" => {
-                    let depth = split.next().unwrap().parse::<u8>().unwrap();
-                    for i in 1..depth + 1 {
-                        go_perft(i, game, false)
-                    }
-                    println!(" Done with perft!")
-                },
-                "psuite" => {
-                    if split.peek().is_some() {
-                        let pos = split.next().unwrap().to_string();
-                        if pos == "long" {
-                            psuite_long()
-                        }
-                    }
-                    else {
-                        psuite();
-                    }
-                },
-                "uci" => {
-                    print!("id name JENCE\n");
-                    print!("id author Joachim Enggaard Nebel\n");
-                    print!("uciok\n");
-                },
-                "ucinewgame" | "cleartt" => {
-                    repetition_table.clear();
-                    tt.clear();
-                },
-                "isready" => print!("readyok\n"),
-                "go" => {
-                    if split.peek().is_none() { continue; }
-                    parse_go(input.split_at(2).1.to_string(), &mut game, &io_receiver, &mut tt, &mut repetition_table)
-                },
-                "eval" => {
-                    let result = evaluate(&game);
-                    println!(" {}", result);
-                },
-                "sbench" => {
-                    sbench(&io_receiver)
-                },
-                "move" => {
-                    while !split.peek().is_none() {
-                        let mov = split.next().unwrap();
-                        let parsed = game.parse_move(mov.to_string());
-                        if parsed.is_none() {
-                            panic!("Illegal move");
-                        }
-                        else {
-                            make_search_move(&mut game, &parsed.unwrap(), &mut repetition_table);
-                        }
-                    }
-                },
+impl Engine {
+    fn new() -> Self {
+        Self {
+            game: Game::new_from_start_pos(), tt: TranspositionTable::new(), repetition_table: RepetitionTable::new(), evaluator: Evaluator::new(),
+            book: None, own_book: false, book_selection: BookSelection::Weighted
+        }
+    }
 
-                _ => println!(" {}", " Unknown command")
-            }
+    fn book_move(&self) -> Option<Move> {
+        if !self.own_book {
+            return None;
         }
+        self.book.as_ref()?.pick(&self.game, self.book_selection, &mut rand::thread_rng())
     }
 }
 
-fn parse_position(args: String, rep_table: &mut RepetitionTable) -> Option<Game> {
-    let pos = args.split(" ").next().unwrap().to_string();
-    let rest: String;
-    let mut game;
-    if pos == "startpos" {
-        game = Game::new_from_start_pos();
+fn main() {
+    let io_receiver = IoWrapper::init();
 
-        rest = args.chars().skip(9).collect();
-    }
-    else if pos == "fen" {
-        if args.len() < 5 {
-            return None;
+    let mut engine = Engine::new();
+    
+    loop {
+        let input = io_receiver.read_line();
+        if input.is_empty() {
+            continue;
         }
-        let fen: String = args.chars().skip(4).take_while(|c| *c != 'm').collect();
-
-        rest = args.chars().skip(4 + fen.len()).collect();
 
-        let result = Game::new_from_fen(fen.as_str());
-        match result {
-            Some(g) => game = g,
-            None => return None
+        match parse_command(&input) {
+            Ok(Command::Quit) => { println!(" Exited!"); process::exit(0) },
+            Ok(command) => if let Err(e) = run_command(command, &mut engine, &io_receiver) {
+                println!("info string {}", e);
+            },
+            Err(e) => println!("info string {}", e)
         }
     }
-    else { return None; }
-
-    let mut split = rest.split(" ").peekable();
+}
 
-    if *split.peek().unwrap() == "moves" {
-        split.next();
-        while !split.peek().is_none() {
-            let mov = split.next().unwrap();
-            let parsed = game.parse_move(mov.to_string());
-            if parsed.is_none() {
-                panic!("Illegal move");
+///Runs a parsed command. Errors leave the engine in the state it was in before the command
+fn run_command(command: Command, engine: &mut Engine, io_receiver: &IoWrapper) -> Result<(), String> {
+    match command {
+        Command::Quit => process::exit(0),
+        Command::Help => print_help(),
+        Command::Display => engine.game.pretty_print(),
+        Command::Position { fen, moves } => {
+            let mut game = match fen {
+                Some(fen) => Game::new_from_fen(&fen).map_err(|e| format!("Illegal fen string: {}", e))?,
+                None => Game::new_from_start_pos()
+            };
+            let mut rep_table = RepetitionTable::new();
+            rep_table.push(game.zobrist_hash, game.half_moves);
+            make_moves(&mut game, &moves, &mut rep_table)?;
+
+            engine.game = game;
+            engine.repetition_table = rep_table;
+        },
+        Command::Perft { depth, detail } => go_perft(depth, engine.game, detail),
+        Command::PerftEach(depth) => {
+            for i in 1..=depth {
+                go_perft(i, engine.game, false)
+            }
+            println!(" Done with perft!")
+        },
+        Command::Psuite { path, long } => {
+            let text = match &path {
+                Some(path) => fs::read_to_string(path).map_err(|e| format!("Could not read '{}': {}", path, e))?,
+                None => PERFT_SUITE.to_string()
+            };
+            let positions = parse_perft_suite(&text)?;
+            println!(" Performance test running...");
+            run_perft_suite(&positions, if long { PSUITE_LONG_NODES } else { PSUITE_NODES }, true);
+        },
+        Command::Uci => {
+            print!("id name JENCE\n");
+            print!("id author Joachim Enggaard Nebel\n");
+            println!("option name EvalFile type string default <empty>");
+            println!("option name NNUEFile type string default <empty>");
+            println!("option name UseNNUE type check default {}", cfg!(feature = "nnue"));
+            println!("option name SyzygyPath type string default <empty>");
+            println!("option name OwnBook type check default false");
+            println!("option name BookFile type string default <empty>");
+            println!("option name BookSelection type combo default Weighted var Weighted var Best");
+            print!("uciok\n");
+        },
+        Command::NewGame => {
+            engine.repetition_table.clear();
+            engine.tt.clear();
+        },
+        Command::IsReady => print!("readyok\n"),
+        Command::SetOption { name, value } => set_option(&name, &value, engine)?,
+        Command::Go(params) => {
+            for arg in &params.ignored {
+                println!("info string Ignoring 'go' argument '{}'", arg);
+            }
+            if params.random {
+                search_random(&mut engine.game);
+            }
+            else if let Some(mov) = engine.book_move().filter(|_| !params.infinite) {
+                println!("info string Book move");
+                println!("bestmove {}", mov.to_uci());
             }
             else {
-                make_search_move(&mut game, &parsed.unwrap(), rep_table);
+                let time = params.search_time(engine.game.active_player);
+                search(&mut engine.game, params.depth.unwrap_or(-1), time, io_receiver, &mut engine.tt, &mut engine.repetition_table, &engine.evaluator);
+            }
+        },
+        Command::Eval { json } => {
+            let trace = trace_evaluation(&engine.game, &engine.evaluator.params);
+            if json {
+                println!("{}", trace.to_json());
+                return Ok(());
+            }
+
+            println!("{}", trace.to_table());
+            if let Some((name, score)) = probe_endgame(&engine.game, trace.tapered_total(), &engine.evaluator.params) {
+                println!(" Endgame knowledge ({}) changes the total to {}", name, score);
+            }
+            if engine.evaluator.active_network().is_some() {
+                println!(" NNUE evaluation is active, the table shows the classical evaluation");
+            }
+            println!(" Side to move: {}", engine.evaluator.evaluate(&engine.game));
+        },
+        Command::SaveEval(path) => {
+            engine.evaluator.params.save(&path)?;
+            println!(" Saved evaluation parameters to {}", path);
+        },
+        Command::Tune { positions, output, iterations } => run_tuner(&positions, &output, iterations, &engine.evaluator.params)?,
+        Command::Datagen { output, config } => {
+            let positions = run_datagen(&config, &engine.evaluator, &output)?;
+            println!(" Wrote {} positions to {}", positions, output);
+        },
+        Command::PgnFens { pgn, output, skip_plies } => {
+            let (games, positions) = extract_positions(&pgn, &output, skip_plies)?;
+            println!(" Wrote {} positions from {} games to {}", positions, games, output);
+        },
+        Command::TestSuite { path, limit } => { run_test_suite(&path, &limit, &engine.evaluator)?; },
+        Command::BookMoves => {
+            let book = engine.book.as_ref().ok_or("No book loaded, set the BookFile option")?;
+            let entries = book.probe(&engine.game);
+            if entries.is_empty() {
+                println!(" No book moves for this position");
+            }
+            let total: u32 = entries.iter().map(|entry| entry.weight as u32).sum();
+            for entry in entries {
+                println!(" {:<8} {:<6} Weight: {:<6} {:.1}%", engine.game.move_to_san(&entry.mov), entry.mov.to_uci(), entry.weight,
+                    100.0 * entry.weight as f64 / total.max(1) as f64);
             }
+        },
+        Command::MakeBook { pgn, output, config } => {
+            let (games, entries) = build_book(&pgn, &output, &config)?;
+            println!(" Wrote {} entries from {} games to {}", entries, games, output);
+        },
+        Command::Sbench => sbench(io_receiver, &engine.evaluator),
+        Command::Move(moves) => {
+            //Apply to a copy, so an illegal move leaves the position untouched
+            let mut game = engine.game;
+            let mut rep_table = engine.repetition_table.clone();
+            for mov in &moves {
+                let parsed = game.parse_move(mov.to_string()).or_else(|| game.parse_san(mov)).ok_or(format!("Illegal move '{}'", mov))?;
+                make_search_move(&mut game, &parsed, &mut rep_table, None);
+            }
+
+            engine.game = game;
+            engine.repetition_table = rep_table;
         }
     }
 
-    Some(game)
+    Ok(())
 }
 
-fn parse_go(args: String, game: &mut Game, io_receiver: &IoWrapper, tt: &mut TranspositionTable, rep_table: &mut RepetitionTable){
-    let mut split = args.split(" ").peekable();
-
-    //Load arguments
-    let mut inc = 0;
-    let mut time = -1;
-    let mut moves_to_go = 30;
-    let mut move_time = -1;
-    let mut depth = -1;
+fn make_moves(game: &mut Game, moves: &[String], rep_table: &mut RepetitionTable) -> Result<(), String> {
+    for mov in moves {
+        let parsed = game.parse_move(mov.to_string()).ok_or(format!("Illegal move '{}'", mov))?;
+        make_search_move(game, &parsed, rep_table, None);
+    }
+    Ok(())
+}
 
-    while split.peek().is_some() {
-        let arg = split.next().unwrap();
-        if arg == "" {
-            continue;
-        }
-        match arg {
-            "binc" => if game.active_player == Color::Black {
-                let t = split.next().unwrap();
-                inc = t.parse::<i64>().unwrap(); 
-                } else {
-                    split.next();
-            },
-            "winc" => if game.active_player == Color::White {
-                let t = split.next().unwrap();
-                inc = t.parse::<i64>().unwrap(); 
-                } else {
-                    split.next();
-            },
-            "btime" => if game.active_player == Color::Black {
-                let t = split.next().unwrap();
-                time = t.parse::<i64>().unwrap();
-                } else {
-                    split.next();
-            },
-            "wtime" => if game.active_player == Color::White {
-                let t = split.next().unwrap();
-                time = t.parse::<i64>().unwrap(); 
-                } else {
-                    split.next();
-            },
-            "movestogo" => {
-                let t = split.next().unwrap();
-                moves_to_go = t.parse::<i64>().unwrap(); 
-            },
-            "movetime" => {
-                let t = split.next().unwrap();
-                move_time = t.parse::<i64>().unwrap(); 
-            },
-            //Fixed depth
-            "depth" => {
-                if split.peek().is_none() { return; }
-                let depth_str = split.next().unwrap();
-                let d = depth_str.parse::<i8>();
-                if d.is_err() { return; }
-                depth = d.unwrap()
-            },
-            "infinite" => {},
-            //Random mover
-            "random" => {
-                search_random(game);
-                return;
-            },
-            
-            _ => {
-                println!("Illegal 'go' command: '{}'", arg);
+//Handles "setoption name [name] value [value]"
+fn set_option(name: &str, value: &str, engine: &mut Engine) -> Result<(), String> {
+    let cleared = value.is_empty() || value == "<empty>";
+    let evaluator = &mut engine.evaluator;
+    match name.to_ascii_lowercase().as_str() {
+        "evalfile" => {
+            evaluator.params = if cleared { EvalParams::default() } else { EvalParams::load(value)? };
+        },
+        "nnuefile" => {
+            if cleared {
+                evaluator.network = None;
+                return Ok(());
             }
-        }
+            let network = Network::load(value)?;
+            println!("info string Loaded network with {} hidden neurons", network.hidden_size);
+            evaluator.network = Some(network);
+        },
+        "usennue" => {
+            evaluator.use_nnue = parse_check("UseNNUE", value)?;
+            if evaluator.use_nnue && evaluator.network.is_none() {
+                println!("info string No network loaded, using the classical evaluation until NNUEFile is set");
+            }
+        },
+        "syzygypath" => {
+            if cleared {
+                evaluator.tablebases = None;
+                return Ok(());
+            }
+            let tablebases = Tablebases::load(value)?;
+            println!("info string Found {} tablebases with up to {} pieces", tablebases.table_count(), tablebases.max_pieces);
+            evaluator.tablebases = Some(tablebases);
+        },
+        "ownbook" => engine.own_book = parse_check("OwnBook", value)?,
+        "bookfile" => {
+            if cleared {
+                engine.book = None;
+                return Ok(());
+            }
+            let book = OpeningBook::load(value)?;
+            println!("info string Loaded book with {} entries", book.len());
+            engine.book = Some(book);
+        },
+        "bookselection" => {
+            engine.book_selection = match value.to_ascii_lowercase().as_str() {
+                "weighted" => BookSelection::Weighted,
+                "best" => BookSelection::Best,
+                _ => return Err(format!("Illegal value '{}' for BookSelection", value))
+            };
+        },
+        _ => return Err(format!("Unknown option '{}'", name))
     }
+    Ok(())
+}
 
-    //Decide time
-    if move_time != -1 {
-        time = move_time
-    } else if time != -1 {
-        if time > 2000 {
-            time /= moves_to_go;
-            time += inc;
-            time -= 100;
-        }
-        else if inc != 0 {
-            time = inc - 500;
-        }
-        else {
-            time /= moves_to_go;
-        }
+fn parse_check(name: &str, value: &str) -> Result<bool, String> {
+    match value.to_ascii_lowercase().as_str() {
+        "true" => Ok(true),
+        "false" => Ok(false),
+        _ => Err(format!("Illegal value '{}' for {}", value, name))
     }
-
-    //Run search
-    search(game, depth, time, &io_receiver, tt, rep_table);
 }
 
 pub fn read_line() -> String {
@@ -265,7 +312,7 @@ pub fn read_line() -> String {
     input.trim().to_string()
 }
 
-pub fn sbench(io_receiver: &IoWrapper) {
+pub fn sbench(io_receiver: &IoWrapper, evaluator: &Evaluator) {
     let poss = [
         Game::new_from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1").unwrap(),    //Tricky position
         Game::new_from_fen("rnbqkb1r/pp1p1pPp/8/2p1pP2/1P1P4/3P3P/P1P1P3/RNBQKBNR w KQkq e6 0 1").unwrap(),     //killer position
@@ -281,11 +328,15 @@ pub fn sbench(io_receiver: &IoWrapper) {
     let depth = 10;
     let mut tt_hits = 0;
     let mut nodes = 0;
+    let mut pawn_table_probes = 0;
+    let mut pawn_table_hits = 0;
     for mut p in poss {
         //p.pretty_print();
-        let result = search(&mut p, depth, -1, &io_receiver, &mut TranspositionTable::new(), &mut RepetitionTable::new());
+        let result = search(&mut p, depth, -1, &io_receiver, &mut TranspositionTable::new(), &mut RepetitionTable::new(), evaluator);
         nodes += result.nodes_visited;
         tt_hits += result.tt_hits;
+        pawn_table_probes += result.pawn_table_probes;
+        pawn_table_hits += result.pawn_table_hits;
         if !result.reached_max_ply {
             println!("Cancelled!");
             return;
@@ -293,133 +344,27 @@ pub fn sbench(io_receiver: &IoWrapper) {
     }
     let duration = start.elapsed().unwrap();
     println!(" RESULT: Depth: {}\t Nodes: {}\t TT hits: {}\tTime: {}ms", depth, nodes, tt_hits, duration.as_millis()); 
+    println!(" Pawn table hit rate: {:.1}%", 100.0 * pawn_table_hits as f64 / pawn_table_probes.max(1) as f64);
 }
 
-fn go_perft(depth: u8, mut game: Game, detail: bool) {
-    let start = SystemTime::now();
-    let result = perft(&mut game, depth, detail);
-    let duration = start.elapsed().unwrap();
-    println!(" Found {} moves for depth {} in {}ms", result, depth, duration.as_millis());
-}
-
-fn psuite() {
-    println!(" Performance test running...");
-    let mut game = Game::new_from_start_pos();
-
-    //startpos
-    let start = SystemTime::now();
-    let r1 = perft(&mut game, 5, false);
-    let duration1 = start.elapsed().unwrap();
-    if r1 != 4865609 {println!(" ERROR! Found {} moves for depth 5 on start position, and expected 4,865,609", r1); }
-    println!(" Perft on starting position at depth 5 found in {}ms", duration1.as_millis());
+fn run_tuner(positions_path: &str, output_path: &str, iterations: u32, eval_params: &EvalParams) -> Result<(), String> {
+    let positions = load_tuning_positions(positions_path)?;
+    println!(" Loaded {} positions", positions.len());
 
-    //Kiwipete
-    let mut game = Game::new_from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 10").unwrap();
-    let start = SystemTime::now();
-    let r2 = perft(&mut game, 4, false);
-    let duration2 = start.elapsed().unwrap();
-    if r2 != 4085603 {println!(" ERROR! Found {} moves for depth 4 on Kiwipete, and expected 4,085,603", r2);}
-    println!(" Perft on Kiwipete at depth 4 found in {}ms", duration2.as_millis());
+    let k = find_best_k(&positions, eval_params, 3);
+    println!(" K: {:.3}\t Error: {:.8}", k, tuning_error(&positions, eval_params, k));
 
-    //Position 3
-    let mut game = Game::new_from_fen("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 10").unwrap();
-    let start = SystemTime::now();
-    let r3 = perft(&mut game, 6, false);
-    let duration3 = start.elapsed().unwrap();
-    if r3 != 11030083 {println!(" ERROR! Found {} moves for depth 6 on Position 3, and expected 11,030,083", r3);}
-    println!(" Perft on Position 3 at depth 6 found in {}ms", duration3.as_millis());
-
-    //Position 4
-    let mut game = Game::new_from_fen("r2q1rk1/pP1p2pp/Q4n2/bbp1p3/Np6/1B3NBn/pPPP1PPP/R3K2R b KQ - 0 1").unwrap();
-    let start = SystemTime::now();
-    let r4 = perft(&mut game, 5, false);
-    let duration4 = start.elapsed().unwrap();
-    if r4 != 15833292 {println!(" ERROR! Found {} moves for depth 5 on Position 4, and expected 15,833,292", r4);}
-    println!(" Perft on Position 4 at depth 5 found in {}ms", duration4.as_millis());
-
-    //Position 5
-    let mut game = Game::new_from_fen("rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8").unwrap();
-    let start = SystemTime::now();
-    let r5 = perft(&mut game, 4, false);
-    let duration5 = start.elapsed().unwrap();
-    if r5 != 2103487 {println!(" ERROR! Found {} moves for depth 4 on Position 5, and expected 2,103,487", r5);}
-    println!(" Perft on Position 5 at depth 4 found in {}ms", duration5.as_millis());
-
-    //Position 6
-    let mut game = Game::new_from_fen("r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10").unwrap();
-    let start = SystemTime::now();
-    let r6 = perft(&mut game, 4, false);
-    let duration6 = start.elapsed().unwrap();
-    if r6 != 3894594 {println!(" ERROR! Found {} moves for depth 4 on Position 6, and expected 3,894,594", r6);}
-    println!(" Perft on Position 6 at depth 4 found in {}ms", duration6.as_millis());
-
-    //Result
-    println!("\n Performance test done!\n Results are as follows:");
-    println!(" 1: {}ms\n 2: {}ms\n 3: {}ms\n 4: {}ms\n 5: {}ms\n 6: {}ms", duration1.as_millis(), duration2.as_millis(),duration3.as_millis(),duration4.as_millis(),duration5.as_millis(),duration6.as_millis());
-    let time = duration1.as_millis()+duration2.as_millis()+duration3.as_millis()+duration4.as_millis()+duration5.as_millis()+duration6.as_millis();
-    let total_result = r1+r2+r3+r4+r5+r6;
-    println!(" total: {}ms", time);
-    println!(" speed: {}/s", (total_result as f64 / (time as f64 / 1000 as f64)) as u64);
+    let tuned = tune(&positions, eval_params, k, iterations, true);
+    tuned.save(output_path)?;
+    println!(" Saved tuned parameters to {}", output_path);
+    Ok(())
 }
 
-fn psuite_long() {
-    println!(" Long performance test running...");
-    let mut game = Game::new_from_start_pos();
-
-    //startpos
-    let start = SystemTime::now();
-    let r1 = perft(&mut game, 6, false);
-    let duration1 = start.elapsed().unwrap();
-    if r1 != 119060324 {println!(" ERROR! Found {} moves for depth 6 on start position, and expected 119,060,324", r1); return }
-    println!(" Perft on starting position at depth 6 found in {}ms", duration1.as_millis());
-
-    //Kiwipete
-    let mut game = Game::new_from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 10").unwrap();
-    let start = SystemTime::now();
-    let r2 = perft(&mut game, 5, false);
-    let duration2 = start.elapsed().unwrap();
-    if r2 != 193690690 {println!(" ERROR! Found {} moves for depth 5 on Kiwipete, and expected 193,690,690", r2);}
-    println!(" Perft on Kiwipete at depth 5 found in {}ms", duration2.as_millis());
-
-    //Position 3
-    let mut game = Game::new_from_fen("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 10").unwrap();
-    let start = SystemTime::now();
-    let r3 = perft(&mut game, 7, false);
-    let duration3 = start.elapsed().unwrap();
-    if r3 != 178633661 {println!(" ERROR! Found {} moves for depth 7 on Position 3, and expected 178,633,661", r3);}
-    println!(" Perft on Position 3 at depth 7 found in {}ms", duration3.as_millis());
-
-    //Position 4
-    let mut game = Game::new_from_fen("r2q1rk1/pP1p2pp/Q4n2/bbp1p3/Np6/1B3NBn/pPPP1PPP/R3K2R b KQ - 0 1").unwrap();
-    let start = SystemTime::now();
-    let r4 = perft(&mut game, 6, false);
-    let duration4 = start.elapsed().unwrap();
-    if r4 != 706045033 {println!(" ERROR! Found {} moves for depth 6 on Position 4, and expected 706,045,033", r4);}
-    println!(" Perft on Position 4 at depth 6 found in {}ms", duration4.as_millis());
-
-    //Position 5
-    let mut game = Game::new_from_fen("rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8").unwrap();
-    let start = SystemTime::now();
-    let r5 = perft(&mut game, 5, false);
-    let duration5 = start.elapsed().unwrap();
-    if r5 != 89941194 {println!(" ERROR! Found {} moves for depth 5 on Position 5, and expected 89,941,194", r5);}
-    println!(" Perft on Position 5 at depth 5 found in {}ms", duration5.as_millis());
-
-    //Position 6
-    let mut game = Game::new_from_fen("r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10").unwrap();
+fn go_perft(depth: u8, mut game: Game, detail: bool) {
     let start = SystemTime::now();
-    let r6 = perft(&mut game, 5, false);
-    let duration6 = start.elapsed().unwrap();
-    if r6 != 164075551 {println!(" ERROR! Found {} moves for depth 5 on Position 6, and expected 164,075,551", r6);}
-    println!(" Perft on Position 6 at depth 5 found in {}ms", duration6.as_millis());
-
-    //Result
-    println!("\n Performance test done!\n Results are as follows:");
-    println!(" 1: {}ms\n 2: {}ms\n 3: {}ms\n 4: {}ms\n 5: {}ms\n 6: {}ms", duration1.as_millis(), duration2.as_millis(),duration3.as_millis(),duration4.as_millis(),duration5.as_millis(),duration6.as_millis());
-    let time = duration1.as_millis()+duration2.as_millis()+duration3.as_millis()+duration4.as_millis()+duration5.as_millis()+duration6.as_millis();
-    let total_result = r1+r2+r3+r4+r5+r6;
-    println!(" total: {}ms", time);
-    println!(" speed: {}/s", total_result as i128 / (time as i128 / 1000 as i128));
+    let result = perft(&mut game, depth, detail);
+    let duration = start.elapsed().unwrap();
+    println!(" Found {} moves for depth {} in {}ms", result, depth, duration.as_millis());
 }
 
 fn print_help() {
@@ -431,10 +376,26 @@ fn print_help() {
  perft [depth] simple    Same as perft but does not print result pr. move
  perft! [depth]          Performs a seperate simple perft for each depth <= [depth]
  unmake/undo             Unmakes the last made move if one exists
- make/move [move]        Make a move on the board. On the standard UCI form: \"a2a4\" and one of \"kbrq\" appended for promotions
- eval                    Prints the heuristic evaluation of the current position
+ make/move [move]        Make a move on the board. On the standard UCI form: \"a2a4\" and one of \"kbrq\" appended for promotions, or in SAN: \"Nf3\"
+ eval                    Prints the heuristic evaluation of the current position, broken down by term
+ eval json               Prints the breakdown as JSON
+ saveeval [path]         Writes the current evaluation parameters to a file
+ tune [positions] [output] [iterations]   Texel tunes the evaluation parameters on a file of positions labeled with game results
+ datagen [output] [args] Plays self-play games and writes quiet positions with scores and results. Args: games, threads, depth, nodes, random, seed, pgn
+ pgnfens [pgn] [output] [skip]          Writes the positions of finished games in a PGN file, after the first [skip] plies, labeled for the tuner
+ setoption name EvalFile value [path]   Loads evaluation parameters from a file. An empty path restores the defaults
+ setoption name NNUEFile value [path]   Loads an NNUE network
+ setoption name UseNNUE value [bool]    Switches between the classical and the NNUE evaluation
+ setoption name SyzygyPath value [dirs] Probes the Syzygy tablebases in the given directories. An empty path disables probing
  psuite                  Runs a suite of perft tests to validate movegenerator and to test performance
  psuite long             Same as psuite but runs to a deeper depth
+ psuite [epd] [long]     Runs the perft tests of a file in the perftsuite.epd format instead
+ testsuite [epd] movetime [ms]   Searches the positions of an EPD file and checks the moves against their bm and am operations. \"depth [n]\" also works
+ setoption name OwnBook value [bool]    Plays moves from the opening book set with BookFile before searching
+ setoption name BookFile value [path]   Loads a Polyglot opening book. An empty path unloads it
+ setoption name BookSelection value [Weighted/Best]   Picks book moves at random by weight or by the highest weight
+ bookmoves               Lists the book moves for the current position
+ makebook [pgn] [output] [args]         Builds a Polyglot book from the finished games in a PGN file. Args: plies, mingames, minscore
  sbench                  Runs a benchmark of the searching algorithm
  help                    Writes out all legal commands. Note that the list provided from this command is out of date")
 }
\ No newline at end of file
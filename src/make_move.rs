@@ -1,10 +1,32 @@
 use super::*;
 
-pub fn make_move(game: &mut Game, cmove: &Move) -> bool {
-    make_search_move(game, cmove, &mut RepetitionTable::new())
+///Makes the move and records the new position in the repetition table, if legal
+pub fn make_search_move(game: &mut Game, cmove: &Move, rep_table: &mut RepetitionTable, accumulators: Option<&mut AccumulatorStack>) -> bool {
+    if !make_evaluated_move(game, cmove, accumulators) {
+        return false
+    }
+
+    rep_table.push(game.zobrist_hash, game.half_moves);
+
+    true
+}
+
+///Makes the move and pushes the updated NNUE accumulator, if legal
+pub fn make_evaluated_move(game: &mut Game, cmove: &Move, accumulators: Option<&mut AccumulatorStack>) -> bool {
+    let captured = if cmove.is_capture() && accumulators.is_some() { Some(captured_piece(game, *cmove)) } else { None };
+
+    if !make_move(game, cmove) {
+        return false
+    }
+
+    if let Some(accumulators) = accumulators {
+        accumulators.push_move(cmove, captured);
+    }
+
+    true
 }
 
-pub fn make_search_move(game: &mut Game, cmove: &Move, rep_table: &mut RepetitionTable) -> bool {
+pub fn make_move(game: &mut Game, cmove: &Move) -> bool {
 
     let from_square = cmove.from_square();
     let to_square   = cmove.to_square();
@@ -26,6 +48,11 @@ pub fn make_search_move(game: &mut Game, cmove: &Move, rep_table: &mut Repetitio
     game.bitboards[piece as usize].set_bit(to_square);
     game.zobrist_hash ^= PIECE_KEYS[piece as usize][to_square as usize];
 
+    let moving_pawn = piece == Piece::WhitePawn as u8 || piece == Piece::BlackPawn as u8;
+    if moving_pawn {
+        game.pawn_hash ^= PIECE_KEYS[piece as usize][from_square as usize] ^ PIECE_KEYS[piece as usize][to_square as usize];
+    }
+
     game.all_occupancies.unset_bit(from_square);
     game.all_occupancies.set_bit(to_square);
 
@@ -38,12 +65,14 @@ pub fn make_search_move(game: &mut Game, cmove: &Move, rep_table: &mut Repetitio
                 game.black_occupancies.unset_bit(to_square + 8);
                 game.all_occupancies.unset_bit(to_square + 8);
                 game.zobrist_hash ^= PIECE_KEYS[Piece::BlackPawn as usize][to_square as usize + 8];
+                game.pawn_hash ^= PIECE_KEYS[Piece::BlackPawn as usize][to_square as usize + 8];
             }
             else {
                 game.bitboards[Piece::WhitePawn as usize].unset_bit(to_square - 8);
                 game.white_occupancies.unset_bit(to_square - 8);
                 game.all_occupancies.unset_bit(to_square - 8);
                 game.zobrist_hash ^= PIECE_KEYS[Piece::WhitePawn as usize][to_square as usize - 8];
+                game.pawn_hash ^= PIECE_KEYS[Piece::WhitePawn as usize][to_square as usize - 8];
             }
         } else {
             let start;
@@ -63,6 +92,9 @@ pub fn make_search_move(game: &mut Game, cmove: &Move, rep_table: &mut Repetitio
                 if game.bitboards[piece].get_bit(to_square) {
                     game.bitboards[piece].unset_bit(to_square);
                     game.zobrist_hash ^= PIECE_KEYS[piece as usize][to_square as usize];
+                    if piece == start {
+                        game.pawn_hash ^= PIECE_KEYS[piece][to_square as usize];
+                    }
 
                     break;
                 }
@@ -85,7 +117,7 @@ pub fn make_search_move(game: &mut Game, cmove: &Move, rep_table: &mut Repetitio
     }
 
     //Increment half moves counter if quiet and reset if pawn
-    if piece == Piece::WhitePawn as u8 || piece == Piece::BlackPawn as u8 || capturing{
+    if moving_pawn || capturing {
         game.half_moves = 0;
     }
     else {
@@ -103,6 +135,7 @@ pub fn make_search_move(game: &mut Game, cmove: &Move, rep_table: &mut Repetitio
         //Zobrist update
         game.zobrist_hash ^= PIECE_KEYS[piece as usize][to_square as usize];
         game.zobrist_hash ^= PIECE_KEYS[promotion as usize][to_square as usize];
+        game.pawn_hash ^= PIECE_KEYS[piece as usize][to_square as usize];
     }
 
     //Castling
@@ -179,8 +212,6 @@ pub fn make_search_move(game: &mut Game, cmove: &Move, rep_table: &mut Repetitio
     game.active_player = opposite_color(game.active_player);
     game.zobrist_hash ^= SIDE_KEY;
 
-    rep_table.insert(game.zobrist_hash);
-
     true
 }
 
@@ -327,7 +358,7 @@ mod make_tests {
 
     #[test]
     pub fn moving_rook_disables_castling_for_that_side() {
-        let mut game = Game::new_from_fen("4k3/8/8/8/8/8/8/R3K2R w KQkq - 0 2").unwrap();
+        let mut game = Game::new_from_fen("4k3/8/8/8/8/8/8/R3K2R w KQ - 0 2").unwrap();
         make_move(&mut game, &Move::new_friendly(Square::h1, Square::h2, Piece::WhiteRook, Piece::None, false, false, false, false));
         assert!(game.castling_ability & CastlingAbility::WhiteKingSide as u8 == 0);
         assert!(game.castling_ability & CastlingAbility::WhiteQueenSide as u8 != 0);
@@ -360,7 +391,7 @@ mod make_tests {
 
     #[test]
     pub fn pawn_promotes_correctly_on_back_row_capture() {
-        let mut game = Game::new_from_fen("2n5/1P6/8/8/8/8/8/K6k w QK - 0 2").unwrap();
+        let mut game = Game::new_from_fen("2n5/1P6/8/8/8/8/8/K6k w - - 0 2").unwrap();
         make_move(&mut game, &Move::new_friendly(Square::b7, Square::c8, Piece::WhitePawn, Piece::WhiteBishop, false, false, false, false));
         assert_eq!(game.get_piece_bitboard(Piece::WhiteBishop).get_bit_sq(Square::c8), true);
         assert_eq!(game.white_occupancies.get_bit_sq(Square::c8), true);
@@ -446,9 +446,9 @@ mod move_gen_tests {
 
     #[test]
     pub fn black_can_enpassant_capture_correctly() {
-        let mut game = Game::new_from_fen("k7/8/8/8/8/pP6/8/7K b - b2 0 25").unwrap();
+        let mut game = Game::new_from_fen("k7/8/8/8/pP6/8/8/7K b - b3 0 25").unwrap();
         let moves = generate_moves(&mut game, MoveTypes::All);
-        assert!(moves.contains(&Move::new_friendly(Square::a3, Square::b2, Piece::BlackPawn, Piece::None, true, false, true, false)));
+        assert!(moves.contains(&Move::new_friendly(Square::a4, Square::b3, Piece::BlackPawn, Piece::None, true, false, true, false)));
     }
 
     #[test]
@@ -617,7 +617,7 @@ mod move_gen_tests {
 
     #[test]
     pub fn is_in_check_is_true_when_in_check_by_rook() {
-        let game = Game::new_from_fen("k7/8/8/8/4r3/8/8/4K3 w K - 0 25").unwrap();
+        let game = Game::new_from_fen("k7/8/8/8/4r3/8/8/4K3 w - - 0 25").unwrap();
         assert_eq!(game.is_in_check(Color::White), true);
     }
 
@@ -656,14 +656,14 @@ mod move_gen_tests {
 
     #[test]
     pub fn pawns_cant_capture_straight() {
-        let mut game = Game::new_from_fen("k7/8/8/p7/P7/8/8/K7 w K - 0 25").unwrap();
+        let mut game = Game::new_from_fen("k7/8/8/p7/P7/8/8/K7 w - - 0 25").unwrap();
         let moves = generate_moves(&mut game, MoveTypes::All).all_from(Square::d8);
         assert_eq!(moves.contains(&Move::new_friendly(Square::a4, Square::a5, Piece::WhitePawn, Piece::None, true, false, false, false)), false);
     }
 
     #[test]
     pub fn pawns_cant_move_straight_into_piece() {
-        let mut game = Game::new_from_fen("k7/8/8/p7/P7/8/8/K7 w K - 0 25").unwrap();
+        let mut game = Game::new_from_fen("k7/8/8/p7/P7/8/8/K7 w - - 0 25").unwrap();
         let moves = generate_moves(&mut game, MoveTypes::All).all_from(Square::a4);
         assert_eq!(moves.contains(&Move::new_friendly(Square::a4, Square::a5, Piece::WhitePawn, Piece::None, false, false, false, false)), false);
     }
@@ -61,10 +61,10 @@ impl MoveList {
 
     #[inline(always)]
     ///Sorts the moves by their score_move() value with insertion sort
-    pub fn sort_moves(&mut self, game: &Game, envir: &mut SearchEnv) {
+    pub fn sort_moves(&mut self, game: &Game, envir: &mut SearchEnv, hash_move: Move) {
         let mut scores = [0; MOVE_LIST_SIZE];
         for i in 0..self.count {
-            scores[i] = score_move(&game, self.moves[i], envir)
+            scores[i] = score_move(&game, self.moves[i], envir, hash_move)
         }
         
         //Unoptimized!
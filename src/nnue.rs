@@ -0,0 +1,383 @@
+use std::fs;
+
+use super::*;
+
+const FEATURES: usize = 768;
+const QA: i32 = 255;
+const QB: i32 = 64;
+const SCALE: i32 = 400;
+
+///A (768 -> N)x2 -> 1 network with a clipped ReLU hidden layer, evaluated from the side to move's perspective.
+///The file is the quantised output of a bullet style trainer, little endian i16s in this order:
+///feature weights [768][N], feature biases [N], output weights [2N], output bias.
+///Features are color * 384 + piece type * 64 + square with a1 = 0, relative to the perspective
+pub struct Network {
+    pub hidden_size: usize,
+    feature_weights: Vec<i16>,
+    feature_biases: Vec<i16>,
+    output_weights: Vec<i16>,
+    output_bias: i32
+}
+
+impl Network {
+    pub fn load(path: &str) -> Result<Self, String> {
+        let bytes = fs::read(path).map_err(|e| format!("Could not read '{}': {}", path, e))?;
+        Self::from_bytes(&bytes)
+    }
+
+    ///Trainers pad the file to a multiple of 64 bytes, so the hidden size is the largest one that fits
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        let values: Vec<i16> = bytes.chunks_exact(2).map(|b| i16::from_le_bytes([b[0], b[1]])).collect();
+
+        let hidden_size = values.len().saturating_sub(1) / (FEATURES + 3);
+        if hidden_size == 0 || values.len() - (hidden_size * (FEATURES + 3) + 1) >= 32 {
+            return Err(format!("Network file of {} bytes doesn't match a 768xN network", bytes.len()));
+        }
+
+        Ok(Self::from_values(hidden_size, &values))
+    }
+
+    fn from_values(hidden_size: usize, values: &[i16]) -> Self {
+        let (feature_weights, rest) = values.split_at(FEATURES * hidden_size);
+        let (feature_biases, rest) = rest.split_at(hidden_size);
+        let (output_weights, rest) = rest.split_at(2 * hidden_size);
+
+        Self {
+            hidden_size,
+            feature_weights: feature_weights.to_vec(),
+            feature_biases: feature_biases.to_vec(),
+            output_weights: output_weights.to_vec(),
+            output_bias: rest[0] as i32
+        }
+    }
+
+    #[inline(always)]
+    fn weights(&self, feature: usize) -> &[i16] {
+        &self.feature_weights[feature * self.hidden_size..(feature + 1) * self.hidden_size]
+    }
+
+    ///Output for the two halves of an accumulator, the side to move's first
+    fn output(&self, us: &[i16], them: &[i16]) -> i32 {
+        let (our_weights, their_weights) = self.output_weights.split_at(self.hidden_size);
+        let sum = clipped_dot(us, our_weights) + clipped_dot(them, their_weights);
+
+        (sum + self.output_bias) * SCALE / (QA * QB)
+    }
+}
+
+//The accumulator updates and the output layer use AVX2 when the target has it, like the BMI2 instructions in
+//attack_tables, and plain loops otherwise. Values wrap on overflow either way
+#[cfg(all(target_arch = "x86_64", target_feature = "avx2"))]
+const LANES: usize = 16;
+
+fn add_weights_scalar(values: &mut [i16], weights: &[i16]) {
+    for (value, weight) in values.iter_mut().zip(weights) { *value = value.wrapping_add(*weight) }
+}
+
+fn sub_weights_scalar(values: &mut [i16], weights: &[i16]) {
+    for (value, weight) in values.iter_mut().zip(weights) { *value = value.wrapping_sub(*weight) }
+}
+
+///Sum of the values clipped to 0..=QA times the weights
+fn clipped_dot_scalar(values: &[i16], weights: &[i16]) -> i32 {
+    values.iter().zip(weights).map(|(&value, &weight)| (value as i32).clamp(0, QA) * weight as i32).sum()
+}
+
+#[cfg(all(target_arch = "x86_64", target_feature = "avx2"))]
+fn add_weights(values: &mut [i16], weights: &[i16]) {
+    use std::arch::x86_64::*;
+    assert_eq!(values.len(), weights.len());
+
+    let chunks = values.len() / LANES;
+    for i in 0..chunks {
+        unsafe {
+            let value = values.as_mut_ptr().add(i * LANES) as *mut __m256i;
+            let weight = weights.as_ptr().add(i * LANES) as *const __m256i;
+            _mm256_storeu_si256(value, _mm256_add_epi16(_mm256_loadu_si256(value), _mm256_loadu_si256(weight)));
+        }
+    }
+    add_weights_scalar(&mut values[chunks * LANES..], &weights[chunks * LANES..]);
+}
+
+#[cfg(all(target_arch = "x86_64", target_feature = "avx2"))]
+fn sub_weights(values: &mut [i16], weights: &[i16]) {
+    use std::arch::x86_64::*;
+    assert_eq!(values.len(), weights.len());
+
+    let chunks = values.len() / LANES;
+    for i in 0..chunks {
+        unsafe {
+            let value = values.as_mut_ptr().add(i * LANES) as *mut __m256i;
+            let weight = weights.as_ptr().add(i * LANES) as *const __m256i;
+            _mm256_storeu_si256(value, _mm256_sub_epi16(_mm256_loadu_si256(value), _mm256_loadu_si256(weight)));
+        }
+    }
+    sub_weights_scalar(&mut values[chunks * LANES..], &weights[chunks * LANES..]);
+}
+
+#[cfg(all(target_arch = "x86_64", target_feature = "avx2"))]
+fn clipped_dot(values: &[i16], weights: &[i16]) -> i32 {
+    use std::arch::x86_64::*;
+    assert_eq!(values.len(), weights.len());
+
+    let chunks = values.len() / LANES;
+    let sum = unsafe {
+        let zero = _mm256_setzero_si256();
+        let max = _mm256_set1_epi16(QA as i16);
+        let mut sum = zero;
+        for i in 0..chunks {
+            let value = _mm256_loadu_si256(values.as_ptr().add(i * LANES) as *const __m256i);
+            let weight = _mm256_loadu_si256(weights.as_ptr().add(i * LANES) as *const __m256i);
+            let clipped = _mm256_min_epi16(_mm256_max_epi16(value, zero), max);
+            //Multiplies the 16 bit lanes and adds neighbouring products into 32 bit lanes
+            sum = _mm256_add_epi32(sum, _mm256_madd_epi16(clipped, weight));
+        }
+
+        let sum = _mm_add_epi32(_mm256_castsi256_si128(sum), _mm256_extracti128_si256(sum, 1));
+        let sum = _mm_add_epi32(sum, _mm_shuffle_epi32(sum, 0b01_00_11_10));
+        let sum = _mm_add_epi32(sum, _mm_shuffle_epi32(sum, 0b10_11_00_01));
+        _mm_cvtsi128_si32(sum)
+    };
+    sum + clipped_dot_scalar(&values[chunks * LANES..], &weights[chunks * LANES..])
+}
+
+#[cfg(not(all(target_arch = "x86_64", target_feature = "avx2")))]
+fn add_weights(values: &mut [i16], weights: &[i16]) {
+    add_weights_scalar(values, weights)
+}
+
+#[cfg(not(all(target_arch = "x86_64", target_feature = "avx2")))]
+fn sub_weights(values: &mut [i16], weights: &[i16]) {
+    sub_weights_scalar(values, weights)
+}
+
+#[cfg(not(all(target_arch = "x86_64", target_feature = "avx2")))]
+fn clipped_dot(values: &[i16], weights: &[i16]) -> i32 {
+    clipped_dot_scalar(values, weights)
+}
+
+///Feature index of a piece on a square, as seen by white and by black.
+///Squares are a8 = 0 internally, so white's view is mirrored
+#[inline(always)]
+fn feature_indices(piece: usize, square: usize) -> (usize, usize) {
+    (piece * 64 + (square ^ 56), ((piece + 6) % 12) * 64 + square)
+}
+
+///Hidden layer values for both perspectives, one entry per position in the current line
+pub struct AccumulatorStack<'a> {
+    network: &'a Network,
+    //Each entry holds white's perspective followed by black's
+    stack: Vec<Vec<i16>>,
+    top: usize
+}
+
+impl <'a>AccumulatorStack<'a> {
+    ///Starts a stack with the accumulator for the given position
+    pub fn new(network: &'a Network, game: &Game) -> Self {
+        let mut stack = Self { network, stack: vec![vec![0; 2 * network.hidden_size]], top: 0 };
+        stack.refresh(game);
+        stack
+    }
+
+    ///Recomputes the current accumulator from scratch
+    pub fn refresh(&mut self, game: &Game) {
+        let hidden_size = self.network.hidden_size;
+        let accumulator = &mut self.stack[self.top];
+        let (white, black) = accumulator.split_at_mut(hidden_size);
+        white.copy_from_slice(&self.network.feature_biases);
+        black.copy_from_slice(&self.network.feature_biases);
+
+        for piece in 0..12 {
+            let mut board = game.bitboards[piece];
+            while !board.is_empty() {
+                let (white_feature, black_feature) = feature_indices(piece, board.extract_bit() as usize);
+                add_weights(white, self.network.weights(white_feature));
+                add_weights(black, self.network.weights(black_feature));
+            }
+        }
+    }
+
+    ///Pushes the accumulator after a move, given the piece it captured if any.
+    ///The new accumulator is the previous one plus the added and minus the removed features
+    pub fn push_move(&mut self, cmove: &Move, captured: Option<usize>) {
+        let piece = cmove.piece() as usize;
+        let from = cmove.from_square() as usize;
+        let to = cmove.to_square() as usize;
+        let white_moving = piece < 6;
+
+        let mut added: [(usize, usize); 2] = [(0, 0); 2];
+        let mut removed: [(usize, usize); 2] = [(0, 0); 2];
+        let mut added_count = 1;
+        let mut removed_count = 1;
+
+        removed[0] = (piece, from);
+        added[0] = if cmove.promotion() != Piece::None as u8 { (cmove.promotion() as usize, to) } else { (piece, to) };
+
+        if let Some(captured) = captured {
+            let captured_square = if !cmove.is_enpassant() { to } else if white_moving { to + 8 } else { to - 8 };
+            removed[1] = (captured, captured_square);
+            removed_count = 2;
+        }
+        else if cmove.is_castling() {
+            let rook = if white_moving { Piece::WhiteRook as usize } else { Piece::BlackRook as usize };
+            let (rook_from, rook_to) = match to {
+                62 => (63, 61),
+                58 => (56, 59),
+                6 =>  (7, 5),
+                2 =>  (0, 3),
+                _ => unreachable!()
+            };
+            removed[1] = (rook, rook_from);
+            added[1] = (rook, rook_to);
+            removed_count = 2;
+            added_count = 2;
+        }
+
+        if self.top + 1 == self.stack.len() {
+            self.stack.push(vec![0; 2 * self.network.hidden_size]);
+        }
+
+        let hidden_size = self.network.hidden_size;
+        let (previous, next) = self.stack.split_at_mut(self.top + 1);
+        let previous = &previous[self.top];
+        let next = &mut next[0];
+        next.copy_from_slice(previous);
+
+        let (white, black) = next.split_at_mut(hidden_size);
+        for &(piece, square) in &added[..added_count] {
+            let (white_feature, black_feature) = feature_indices(piece, square);
+            add_weights(white, self.network.weights(white_feature));
+            add_weights(black, self.network.weights(black_feature));
+        }
+        for &(piece, square) in &removed[..removed_count] {
+            let (white_feature, black_feature) = feature_indices(piece, square);
+            sub_weights(white, self.network.weights(white_feature));
+            sub_weights(black, self.network.weights(black_feature));
+        }
+
+        self.top += 1;
+    }
+
+    pub fn pop(&mut self) {
+        self.top -= 1;
+    }
+
+    ///Evaluation of the current position from the side to move's point of view
+    pub fn evaluate(&self, side: Color) -> i32 {
+        let (white, black) = self.stack[self.top].split_at(self.network.hidden_size);
+        if side == Color::White { self.network.output(white, black) } else { self.network.output(black, white) }
+    }
+
+    #[cfg(test)]
+    fn current(&self) -> &[i16] {
+        &self.stack[self.top]
+    }
+}
+
+///Evaluates a position without an accumulator from an earlier one
+pub fn evaluate_nnue(game: &Game, network: &Network) -> i32 {
+    AccumulatorStack::new(network, game).evaluate(game.active_player)
+}
+
+#[cfg(test)]
+mod nnue_tests {
+    use crate::*;
+
+    //Deterministic pseudo random network
+    fn test_network(hidden_size: usize) -> Network {
+        let mut state: u32 = 12345;
+        let count = hidden_size * (768 + 3) + 1;
+        let mut bytes = Vec::new();
+        for _ in 0..count {
+            state = state.wrapping_mul(1103515245).wrapping_add(12345);
+            let value = ((state >> 16) % 128) as i16 - 64;
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        Network::from_bytes(&bytes).unwrap()
+    }
+
+    #[test]
+    pub fn reads_hidden_size_from_file_length() {
+        let network = test_network(16);
+        assert_eq!(network.hidden_size, 16);
+
+        //Padding is allowed, but not a truncated file
+        let mut bytes = vec![0u8; 2 * (32 * 771 + 1)];
+        assert_eq!(Network::from_bytes(&bytes).unwrap().hidden_size, 32);
+        bytes.extend_from_slice(&[0; 40]);
+        assert_eq!(Network::from_bytes(&bytes).unwrap().hidden_size, 32);
+        assert!(Network::from_bytes(&bytes[..1000]).is_err());
+    }
+
+    #[test]
+    pub fn incremental_updates_match_refresh() {
+        let network = test_network(32);
+        //Castling, a promotion with capture, en passant and regular captures
+        let lines = [
+            ("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1", "e1g1 e8c8 e5f7 h3g2 f7d8 g2f1q"),
+            ("rnbqkbnr/ppp1pppp/8/8/3pP3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1", "d4e3 d2e3 e7e5 d1d8"),
+        ];
+
+        for (fen, moves) in lines {
+            let mut game = Game::new_from_fen(fen).unwrap();
+            let mut stack = AccumulatorStack::new(&network, &game);
+
+            for mov in moves.split_whitespace() {
+                let parsed = game.parse_move(mov.to_string()).unwrap();
+                assert!(make_evaluated_move(&mut game, &parsed, Some(&mut stack)));
+
+                assert_eq!(stack.current(), AccumulatorStack::new(&network, &game).current(), "after {}", mov);
+                assert_eq!(stack.evaluate(game.active_player), evaluate_nnue(&game, &network));
+            }
+        }
+    }
+
+    #[test]
+    pub fn pop_returns_to_the_previous_accumulator() {
+        let network = test_network(16);
+        let mut game = Game::new_from_start_pos();
+        let mut stack = AccumulatorStack::new(&network, &game);
+        let root = stack.current().to_vec();
+
+        let parsed = game.parse_move("e2e4".to_string()).unwrap();
+        assert!(make_evaluated_move(&mut game, &parsed, Some(&mut stack)));
+        assert_ne!(stack.current(), root.as_slice());
+
+        stack.pop();
+        assert_eq!(stack.current(), root.as_slice());
+    }
+
+    #[test]
+    pub fn simd_matches_the_plain_loops() {
+        use super::{add_weights, sub_weights, clipped_dot, add_weights_scalar, sub_weights_scalar, clipped_dot_scalar};
+
+        let mut state: u32 = 34;
+        let mut random = || {
+            state = state.wrapping_mul(1103515245).wrapping_add(12345);
+            (state >> 8) as i16
+        };
+
+        //A length that isn't a multiple of the vector width, with values far outside the clipping range
+        let values: Vec<i16> = (0..71).map(|_| random() / 64).collect();
+        let weights: Vec<i16> = (0..71).map(|_| random()).collect();
+
+        assert_eq!(clipped_dot(&values, &weights), clipped_dot_scalar(&values, &weights));
+
+        let (mut simd, mut scalar) = (values.clone(), values.clone());
+        add_weights(&mut simd, &weights);
+        add_weights_scalar(&mut scalar, &weights);
+        assert_eq!(simd, scalar);
+        sub_weights(&mut simd, &weights);
+        sub_weights_scalar(&mut scalar, &weights);
+        assert_eq!(simd, scalar);
+        assert_eq!(simd, values);
+    }
+
+    #[test]
+    pub fn evaluation_is_color_symmetric() {
+        let network = test_network(16);
+        let white = Game::new_from_fen("r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 4 4").unwrap();
+        let black = Game::new_from_fen("rnbqk2r/pppp1ppp/5n2/2b1p3/4P3/2N2N2/PPPP1PPP/R1BQKB1R b KQkq - 4 4").unwrap();
+        assert_eq!(evaluate_nnue(&white, &network), evaluate_nnue(&black, &network));
+    }
+}
@@ -0,0 +1,77 @@
+use super::*;
+
+pub const PAWN_TABLE_SIZE: usize = 16384;
+
+///Cached pawn structure evaluation of a pawn configuration
+#[derive(Copy, Clone, Default)]
+pub struct PawnEntry {
+    pub hash: u64,
+    ///Stacked, isolated and passed pawn terms from white's point of view
+    pub score: Score,
+    ///[white, black]
+    pub passed_pawns: [Bitboard; 2]
+}
+
+///Pawn structure scores by pawn hash. As a pawnless board hashes to 0, the empty entries are already correct for it
+pub struct PawnTable {
+    table: Box<[PawnEntry]>,
+    pub probes: u64,
+    pub hits: u64
+}
+
+impl PawnTable {
+    pub fn new() -> Self {
+        Self { table: vec![PawnEntry::default(); PAWN_TABLE_SIZE].into_boxed_slice(), probes: 0, hits: 0 }
+    }
+
+    ///The pawn structure of the position, evaluated and stored if it isn't in the table
+    pub fn probe(&mut self, game: &Game, params: &EvalParams) -> PawnEntry {
+        let index = (game.pawn_hash % PAWN_TABLE_SIZE as u64) as usize;
+        self.probes += 1;
+
+        let entry = self.table[index];
+        if entry.hash == game.pawn_hash {
+            self.hits += 1;
+            return entry;
+        }
+
+        let entry = evaluate_pawns(game, params, &mut ());
+        self.table[index] = entry;
+        entry
+    }
+}
+
+#[cfg(test)]
+mod pawn_table_tests {
+    use crate::*;
+
+    #[test]
+    pub fn pawn_hash_is_updated_incrementally() {
+        //Pawn pushes, captures by and of pawns, en passant and promotion
+        let mut game = Game::new_from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1").unwrap();
+        for mov in ["a2a4", "b4a3", "d5e6", "h3g2", "e6f7", "e8d8", "a1b1", "g2h1q", "e2f1", "a3b2", "f7f8q"] {
+            let parsed = game.parse_move(mov.to_string()).unwrap();
+            assert!(make_move(&mut game, &parsed));
+            assert_eq!(game.pawn_hash, game.make_pawn_hash(), "after {}", mov);
+        }
+    }
+
+    #[test]
+    pub fn cached_entries_match_evaluation() {
+        let params = EvalParams::default();
+        let mut table = PawnTable::new();
+
+        for fen in ["8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 10", "4k3/8/8/8/8/8/8/4K3 w - - 0 1"] {
+            let game = Game::new_from_fen(fen).unwrap();
+            let first = table.probe(&game, &params);
+            let second = table.probe(&game, &params);
+
+            assert_eq!(first.score, evaluate_pawns(&game, &params, &mut ()).score);
+            assert_eq!(second.score, first.score);
+            assert_eq!(evaluate_with_pawn_table(&game, &params, &mut table), evaluate(&game, &params));
+        }
+
+        assert_eq!(table.probes, 6);
+        assert_eq!(table.hits, 5);
+    }
+}
@@ -1,5 +1,13 @@
+use std::time::SystemTime;
+
 use super::*;
 
+///The positions of the standard perft suite, in the "fen ;D1 20 ;D2 400" format
+pub const PERFT_SUITE: &str = include_str!("../perftsuite.epd");
+///Largest node counts checked by "psuite" and "psuite long"
+pub const PSUITE_NODES: u128 = 20_000_000;
+pub const PSUITE_LONG_NODES: u128 = 1_000_000_000;
+
 pub fn perft(game: &mut Game, depth: u8, print: bool) -> u128 {
     let moves = generate_moves(game, MoveTypes::All);
 
@@ -14,11 +22,179 @@ pub fn perft(game: &mut Game, depth: u8, print: bool) -> u128 {
             let r = perft(&mut copy, depth - 1, false);
 
             if print {
-                println!("{}{}: {}", SQUARE_STRINGS[m.from_square() as usize], SQUARE_STRINGS[m.to_square() as usize], r)
+                println!("{}: {}", m.to_uci(), r)
             }
 
             r
         }
         else { 0 }
     }).sum()
-}
\ No newline at end of file
+}
+
+//Perft without bulk counting, where every move is checked for legality by making it
+fn reference_perft(game: &Game, depth: u8) -> u128 {
+    if depth == 0 {
+        return 1;
+    }
+
+    let mut copy = *game;
+    let moves = generate_moves(&mut copy, MoveTypes::All);
+    moves.iter().map(|m| {
+        let mut copy = *game;
+        if make_move(&mut copy, m) { reference_perft(&copy, depth - 1) } else { 0 }
+    }).sum()
+}
+
+///Divides until perft and the reference perft disagree on the moves of a position, and describes the difference.
+///None if they agree, in which case a wrong count comes from the move generator itself
+pub fn find_perft_divergence(game: &Game, depth: u8) -> Option<String> {
+    let mut line = Vec::new();
+    let mut game = *game;
+
+    for depth in (1..=depth).rev() {
+        let moves = generate_moves(&mut game, MoveTypes::All);
+
+        if depth == 1 {
+            let legal: Vec<String> = moves.legal_values(&game).iter().map(|m| m.to_uci()).collect();
+            let made: Vec<String> = moves.iter().filter(|m| make_move(&mut game.clone(), m)).map(|m| m.to_uci()).collect();
+            let only_legal: Vec<&String> = legal.iter().filter(|m| !made.contains(m)).collect();
+            let only_made: Vec<&String> = made.iter().filter(|m| !legal.contains(m)).collect();
+            if only_legal.is_empty() && only_made.is_empty() {
+                return None;
+            }
+            return Some(format!("In {} after '{}', is_legal accepts {:?} and make_move accepts {:?}", game.to_fen(), line.join(" "), only_legal, only_made));
+        }
+
+        let diverging = moves.iter().find_map(|m| {
+            let mut copy = game;
+            (make_move(&mut copy, m) && perft(&mut copy, depth - 1, false) != reference_perft(&copy, depth - 1)).then_some((*m, copy))
+        });
+        match diverging {
+            Some((mov, next)) => {
+                line.push(mov.to_uci());
+                game = next;
+            },
+            None => return None
+        }
+    }
+
+    None
+}
+
+pub struct PerftPosition {
+    pub fen: String,
+    ///Depths and their expected node counts
+    pub expected: Vec<(u8, u128)>,
+}
+
+///Reads perft positions, one per line as "fen ;D1 20 ;D2 400". Empty lines and lines starting with '#' are skipped
+pub fn parse_perft_suite(text: &str) -> Result<Vec<PerftPosition>, String> {
+    let mut positions = Vec::new();
+    for (line_number, line) in text.lines().enumerate() {
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let error = |e: String| format!("Line {}: {}", line_number + 1, e);
+
+        let mut parts = line.split(';');
+        let fen = parts.next().unwrap_or_default().trim();
+        Game::new_from_fen(fen).map_err(|e| error(format!("Illegal fen string: {}", e)))?;
+
+        let mut expected = Vec::new();
+        for part in parts.map(str::trim).filter(|p| !p.is_empty()) {
+            let entry = part.strip_prefix('D').and_then(|p| p.split_once(char::is_whitespace))
+                .and_then(|(depth, count)| Some((depth.parse::<u8>().ok()?, count.trim().parse::<u128>().ok()?)));
+            match entry {
+                Some((depth, count)) if depth > 0 => expected.push((depth, count)),
+                _ => return Err(error(format!("Illegal depth entry '{}'", part)))
+            }
+        }
+
+        positions.push(PerftPosition { fen: fen.to_string(), expected });
+    }
+    Ok(positions)
+}
+
+pub struct PerftSuiteResult {
+    pub positions: usize,
+    ///A description of each wrong count
+    pub failures: Vec<String>,
+    pub nodes: u128,
+    pub millis: u128,
+}
+
+///Checks every depth of each position with an expected count of at most max_nodes. A position stops at its first wrong depth,
+///which is divided down to the first move where perft and the reference perft disagree
+pub fn run_perft_suite(positions: &[PerftPosition], max_nodes: u128, print: bool) -> PerftSuiteResult {
+    let mut result = PerftSuiteResult { positions: 0, failures: Vec::new(), nodes: 0, millis: 0 };
+    let start = SystemTime::now();
+
+    for (index, position) in positions.iter().enumerate() {
+        let game = Game::new_from_fen(&position.fen).unwrap();
+        let mut checked = None;
+
+        for &(depth, expected) in position.expected.iter().filter(|(_, count)| *count <= max_nodes) {
+            let depth_start = SystemTime::now();
+            let found = perft(&mut game.clone(), depth, false);
+            let millis = depth_start.elapsed().unwrap().as_millis();
+            result.nodes += found;
+
+            if found != expected {
+                let cause = find_perft_divergence(&game, depth).unwrap_or("perft and the reference perft agree, so the difference is in the move generator or the expected count".to_string());
+                let failure = format!("{} at depth {}: found {} and expected {}. {}", position.fen, depth, found, expected, cause);
+                if print {
+                    println!(" ERROR! {}", failure);
+                }
+                result.failures.push(failure);
+                checked = None;
+                break;
+            }
+            checked = Some((depth, found, millis));
+        }
+
+        result.positions += 1;
+        if let (true, Some((depth, nodes, millis))) = (print, checked) {
+            println!(" {}: depth {}\t {} nodes in {}ms\t {}/s", index + 1, depth, nodes, millis, nodes * 1000 / millis.max(1));
+        }
+    }
+
+    result.millis = start.elapsed().unwrap().as_millis();
+    if print {
+        println!("\n Perft suite done: {}/{} positions correct", result.positions - result.failures.len(), result.positions);
+        println!(" total: {} nodes in {}ms", result.nodes, result.millis);
+        println!(" speed: {}/s", result.nodes * 1000 / result.millis.max(1));
+    }
+    result
+}
+
+#[cfg(test)]
+mod perft_tests {
+    use crate::*;
+
+    #[test]
+    pub fn perft_suite_passes() {
+        let positions = parse_perft_suite(PERFT_SUITE).unwrap();
+        assert_eq!(positions.len(), 12);
+
+        let result = run_perft_suite(&positions, 200_000, false);
+        assert!(result.failures.is_empty(), "{:?}", result.failures);
+    }
+
+    #[test]
+    pub fn wrong_counts_are_reported() {
+        let positions = parse_perft_suite("# A comment\n\n8/8/8/8/8/8/6k1/4K2R w K - 0 1 ;D1 12 ;D2 39 ;D3 564\n").unwrap();
+        assert_eq!(positions[0].expected, [(1, 12), (2, 39), (3, 564)]);
+
+        let result = run_perft_suite(&positions, u128::MAX, false);
+        assert_eq!(result.failures.len(), 1);
+        assert!(result.failures[0].contains("at depth 2: found 38 and expected 39"));
+    }
+
+    #[test]
+    pub fn malformed_suites_are_rejected() {
+        assert!(parse_perft_suite("8/8/8/8/8/8/6k1/4K2R w K - 0 1 ;D1").is_err());
+        assert!(parse_perft_suite("8/8/8/8/8/8/6k1/4K2R w K - 0 1 ;X1 12").is_err());
+        assert!(parse_perft_suite("8/8/8/8/8/8/6k1/4K2R w K - 0 1 ;D0 1").is_err());
+        assert!(parse_perft_suite("8/8/8/8 w - - 0 1 ;D1 12").is_err());
+    }
+}
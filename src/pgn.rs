@@ -0,0 +1,606 @@
+use std::{collections::VecDeque, fs};
+
+use super::*;
+
+const START_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+//The tags every PGN game has, in the order they are written
+const SEVEN_TAG_ROSTER: [&str; 7] = ["Event", "Site", "Date", "Round", "White", "Black", "Result"];
+const LINE_LENGTH: usize = 80;
+
+///A move in the movetext with its annotations
+pub struct PgnMove {
+    pub mov: Move,
+    ///Numeric annotation glyphs, where "!" is 1, "?" is 2, "!!" is 3, "??" is 4, "!?" is 5 and "?!" is 6
+    pub nags: Vec<u8>,
+    pub comment: Option<String>,
+    ///Alternatives to this move, played from the position before it
+    pub variations: Vec<Vec<PgnMove>>,
+}
+
+impl PgnMove {
+    pub fn new(mov: Move) -> Self {
+        Self { mov, nags: Vec::new(), comment: None, variations: Vec::new() }
+    }
+}
+
+pub struct PgnGame {
+    pub tags: Vec<(String, String)>,
+    ///The position before the first move, from the "FEN" tag if there is one
+    pub start: Game,
+    ///Comment before the first move
+    pub comment: Option<String>,
+    pub moves: Vec<PgnMove>,
+    ///"1-0", "0-1", "1/2-1/2" or "*"
+    pub result: String,
+}
+
+impl PgnGame {
+    ///A game with the given moves and unknown players and event
+    pub fn new(start: &Game, moves: &[Move], result: &str) -> Self {
+        let mut game = Self {
+            tags: Vec::new(),
+            start: *start,
+            comment: None,
+            moves: moves.iter().map(|mov| PgnMove::new(*mov)).collect(),
+            result: result.to_string()
+        };
+
+        for name in SEVEN_TAG_ROSTER {
+            game.set_tag(name, if name == "Date" { "????.??.??" } else { "?" });
+        }
+        game.set_tag("Result", result);
+        let fen = start.to_fen();
+        if fen != START_FEN {
+            game.set_tag("SetUp", "1");
+            game.set_tag("FEN", &fen);
+        }
+
+        game
+    }
+
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags.iter().find(|(n, _)| n == name).map(|(_, value)| value.as_str())
+    }
+
+    pub fn set_tag(&mut self, name: &str, value: &str) {
+        match self.tags.iter_mut().find(|(n, _)| n == name) {
+            Some(tag) => tag.1 = value.to_string(),
+            None => self.tags.push((name.to_string(), value.to_string()))
+        }
+    }
+
+    ///The position before the first move and after every move of the main line
+    pub fn positions(&self) -> Vec<Game> {
+        let mut game = self.start;
+        let mut positions = vec![game];
+        for mov in &self.moves {
+            make_move(&mut game, &mov.mov);
+            positions.push(game);
+        }
+        positions
+    }
+
+    pub fn to_pgn(&self) -> String {
+        let mut pgn = String::new();
+
+        //The seven tag roster comes first, then the rest in their order
+        for name in SEVEN_TAG_ROSTER {
+            let value = match (name, self.tag(name)) {
+                ("Result", _) => self.result.as_str(),
+                (_, Some(value)) => value,
+                ("Date", None) => "????.??.??",
+                (_, None) => "?"
+            };
+            pgn += &format!("[{} \"{}\"]\n", name, escape_tag_value(value));
+        }
+        for (name, value) in self.tags.iter().filter(|(name, _)| !SEVEN_TAG_ROSTER.contains(&name.as_str())) {
+            pgn += &format!("[{} \"{}\"]\n", name, escape_tag_value(value));
+        }
+        pgn.push('\n');
+
+        let mut tokens = Vec::new();
+        if let Some(comment) = &self.comment {
+            tokens.push(format!("{{{}}}", comment));
+        }
+        write_line(&self.start, &self.moves, &mut tokens);
+        tokens.push(self.result.clone());
+
+        //Wrap the movetext, without spaces inside the parentheses of variations
+        let mut line = String::new();
+        for (i, token) in tokens.iter().enumerate() {
+            let joined = i > 0 && tokens[i - 1] != "(" && token != ")";
+            if joined && line.len() + 1 + token.len() > LINE_LENGTH {
+                pgn += &line;
+                pgn.push('\n');
+                line.clear();
+            }
+            else if joined && !line.is_empty() {
+                line.push(' ');
+            }
+            line += token;
+        }
+        pgn += &line;
+        pgn += "\n\n";
+
+        pgn
+    }
+}
+
+fn escape_tag_value(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+//Writes the moves played from the start position, with their annotations and variations
+fn write_line(start: &Game, moves: &[PgnMove], tokens: &mut Vec<String>) {
+    let mut game = *start;
+    //Black moves are numbered at the start of a line and after comments and variations
+    let mut needs_number = true;
+
+    for mov in moves {
+        if game.active_player == Color::White {
+            tokens.push(format!("{}.", game.full_moves));
+        }
+        else if needs_number {
+            tokens.push(format!("{}...", game.full_moves));
+        }
+        tokens.push(game.move_to_san(&mov.mov));
+        tokens.extend(mov.nags.iter().map(|nag| format!("${}", nag)));
+        needs_number = false;
+
+        if let Some(comment) = &mov.comment {
+            tokens.push(format!("{{{}}}", comment));
+            needs_number = true;
+        }
+        for variation in &mov.variations {
+            tokens.push("(".to_string());
+            write_line(&game, variation, tokens);
+            tokens.push(")".to_string());
+            needs_number = true;
+        }
+
+        make_move(&mut game, &mov.mov);
+    }
+}
+
+enum Token {
+    Tag(String, String),
+    Comment(String),
+    Nag(u8),
+    VariationStart,
+    VariationEnd,
+    Result(String),
+    Move(String),
+}
+
+///Reads the games of a PGN file one at a time. A game that can't be read is returned as an error,
+///and reading continues with the next game
+pub struct PgnReader<'a> {
+    text: &'a str,
+    position: usize,
+    //Tokens split off the one before them, like the "!" in "e4!"
+    pending: VecDeque<Token>,
+    keep_variations: bool,
+    games_read: usize,
+}
+
+impl<'a> PgnReader<'a> {
+    pub fn new(text: &'a str, keep_variations: bool) -> Self {
+        Self { text, position: 0, pending: VecDeque::new(), keep_variations, games_read: 0 }
+    }
+
+    fn next_token(&mut self) -> Option<Token> {
+        if let Some(token) = self.pending.pop_front() {
+            return Some(token);
+        }
+
+        let bytes = self.text.as_bytes();
+        loop {
+            while self.position < bytes.len() && bytes[self.position].is_ascii_whitespace() {
+                self.position += 1;
+            }
+            if self.position >= bytes.len() {
+                return None;
+            }
+
+            let start = self.position;
+            match bytes[start] {
+                //Escaped lines
+                b'%' if start == 0 || bytes[start - 1] == b'\n' => {
+                    self.position = self.find_byte(start, b'\n');
+                },
+                b'[' => {
+                    self.position = self.find_tag_end(start);
+                    let tag = parse_tag(&self.text[start + 1..self.position]);
+                    self.position += 1;
+                    //Malformed tags are ignored
+                    if tag.is_some() {
+                        return tag;
+                    }
+                },
+                b'{' => {
+                    self.position = self.find_byte(start, b'}');
+                    let comment = self.text[start + 1..self.position].trim().to_string();
+                    self.position += 1;
+                    return Some(Token::Comment(comment));
+                },
+                b';' => {
+                    self.position = self.find_byte(start, b'\n');
+                    return Some(Token::Comment(self.text[start + 1..self.position].trim().to_string()));
+                },
+                b'(' => { self.position += 1; return Some(Token::VariationStart) },
+                b')' => { self.position += 1; return Some(Token::VariationEnd) },
+                _ => {
+                    while self.position < bytes.len() && !bytes[self.position].is_ascii_whitespace() && !b"[]{}();".contains(&bytes[self.position]) {
+                        self.position += 1;
+                    }
+                    if let Some(token) = self.symbol_token(&self.text[start..self.position]) {
+                        return Some(token);
+                    }
+                }
+            }
+        }
+    }
+
+    //Index of the byte, or the end of the text
+    fn find_byte(&self, from: usize, byte: u8) -> usize {
+        self.text.as_bytes()[from..].iter().position(|b| *b == byte).map_or(self.text.len(), |i| from + i)
+    }
+
+    //Index of the ']' ending the tag, skipping the ones in the quoted value
+    fn find_tag_end(&self, from: usize) -> usize {
+        let bytes = self.text.as_bytes();
+        let mut quoted = false;
+        let mut i = from;
+        while i < bytes.len() {
+            match bytes[i] {
+                b'\\' if quoted => i += 1,
+                b'"' => quoted = !quoted,
+                b']' if !quoted => return i,
+                b'\n' => quoted = false,
+                _ => {}
+            }
+            i += 1;
+        }
+        bytes.len()
+    }
+
+    //Move numbers are skipped, and annotations after a move become separate NAGs
+    fn symbol_token(&mut self, symbol: &str) -> Option<Token> {
+        if let Some(nag) = symbol.strip_prefix('$') {
+            return nag.parse().ok().map(Token::Nag);
+        }
+        if ["1-0", "0-1", "1/2-1/2", "*"].contains(&symbol) {
+            return Some(Token::Result(symbol.to_string()));
+        }
+
+        //"12." and "12..." before the move, but not the zeros of "0-0"
+        let digits = symbol.len() - symbol.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+        let symbol = if symbol[digits..].starts_with('.') { symbol[digits..].trim_start_matches('.') } else { symbol };
+        let mov = symbol.trim_end_matches(['!', '?']);
+        let annotation = &symbol[mov.len()..];
+        let nag = match annotation {
+            "" => None,
+            "!" => Some(1),
+            "?" => Some(2),
+            "!!" => Some(3),
+            "??" => Some(4),
+            "!?" => Some(5),
+            "?!" => Some(6),
+            _ => None
+        };
+
+        match (mov.is_empty(), nag) {
+            (true, nag) => nag.map(Token::Nag),
+            (false, Some(nag)) => {
+                self.pending.push_back(Token::Nag(nag));
+                Some(Token::Move(mov.to_string()))
+            },
+            (false, None) => Some(Token::Move(mov.to_string()))
+        }
+    }
+
+    fn peek_token(&mut self) -> Option<&Token> {
+        if self.pending.is_empty() {
+            let token = self.next_token()?;
+            self.pending.push_front(token);
+        }
+        self.pending.front()
+    }
+
+    fn parse_game(&mut self) -> Result<PgnGame, String> {
+        let mut tags = Vec::new();
+        while let Some(Token::Tag(_, _)) = self.peek_token() {
+            if let Some(Token::Tag(name, value)) = self.next_token() {
+                tags.push((name, value));
+            }
+        }
+
+        let start = match tags.iter().find(|(name, _)| name == "FEN") {
+            Some((_, fen)) => Game::new_from_fen(fen).map_err(|e| format!("Illegal fen string: {}", e))?,
+            None => Game::new_from_start_pos()
+        };
+        let mut game = PgnGame { tags, start, comment: None, moves: Vec::new(), result: "*".to_string() };
+
+        let result = self.parse_line(&start, &mut game.moves, &mut game.comment, 0)?;
+        game.result = result.or_else(|| game.tag("Result").map(str::to_string)).unwrap_or("*".to_string());
+
+        Ok(game)
+    }
+
+    //Reads moves until the end of the line. Returns the result token ending the main line
+    fn parse_line(&mut self, start: &Game, moves: &mut Vec<PgnMove>, comment: &mut Option<String>, depth: usize) -> Result<Option<String>, String> {
+        let mut game = *start;
+        //The position before the last move, where its variations start
+        let mut before = *start;
+
+        loop {
+            match self.peek_token() {
+                None if depth == 0 => return Ok(None),
+                None => return Err("Unterminated variation".to_string()),
+                //The next game begins without a result
+                Some(Token::Tag(_, _)) if depth == 0 => return Ok(None),
+                Some(Token::Tag(_, _)) => return Err("Unterminated variation".to_string()),
+                _ => {}
+            }
+
+            match self.next_token().unwrap() {
+                Token::Move(san) => {
+                    let mov = game.parse_san(&san).ok_or(format!("Illegal move '{}' in {}", san, game.to_fen()))?;
+                    before = game;
+                    make_move(&mut game, &mov);
+                    moves.push(PgnMove::new(mov));
+                },
+                Token::Nag(nag) => if let Some(last) = moves.last_mut() {
+                    last.nags.push(nag);
+                },
+                Token::Comment(text) => {
+                    let target = match moves.last_mut() {
+                        Some(last) => &mut last.comment,
+                        None => &mut *comment
+                    };
+                    *target = Some(match target.take() {
+                        Some(existing) => format!("{} {}", existing, text),
+                        None => text
+                    });
+                },
+                Token::VariationStart => {
+                    if moves.is_empty() {
+                        return Err("Variation before the first move".to_string());
+                    }
+                    if !self.keep_variations {
+                        self.skip_variation()?;
+                        continue;
+                    }
+                    let mut variation = Vec::new();
+                    //Comments before the first move of a variation are dropped
+                    self.parse_line(&before, &mut variation, &mut None, depth + 1)?;
+                    if !variation.is_empty() {
+                        moves.last_mut().unwrap().variations.push(variation);
+                    }
+                },
+                Token::VariationEnd if depth > 0 => return Ok(None),
+                Token::VariationEnd => return Err("Unmatched ')'".to_string()),
+                Token::Result(result) if depth == 0 => return Ok(Some(result)),
+                Token::Result(_) => return Err("Unterminated variation".to_string()),
+                Token::Tag(_, _) => unreachable!()
+            }
+        }
+    }
+
+    //Skips to the end of a variation, including the ones inside it
+    fn skip_variation(&mut self) -> Result<(), String> {
+        let mut depth = 1;
+        while depth > 0 {
+            match self.peek_token() {
+                None | Some(Token::Tag(_, _)) | Some(Token::Result(_)) => return Err("Unterminated variation".to_string()),
+                _ => {}
+            }
+            match self.next_token() {
+                Some(Token::VariationStart) => depth += 1,
+                Some(Token::VariationEnd) => depth -= 1,
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    //Skips the rest of a game that couldn't be read
+    fn skip_game(&mut self) {
+        while let Some(token) = self.peek_token() {
+            if let Token::Tag(_, _) = token {
+                return;
+            }
+            if let Some(Token::Result(_)) = self.next_token() {
+                return;
+            }
+        }
+    }
+}
+
+impl Iterator for PgnReader<'_> {
+    type Item = Result<PgnGame, String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.peek_token()?;
+        self.games_read += 1;
+
+        let game = self.parse_game();
+        if game.is_err() {
+            self.skip_game();
+        }
+        Some(game.map_err(|e| format!("Game {}: {}", self.games_read, e)))
+    }
+}
+
+//The inside of "[Name "Value"]"
+fn parse_tag(tag: &str) -> Option<Token> {
+    let (name, value) = tag.trim().split_once(char::is_whitespace)?;
+    let value = value.trim().strip_prefix('"')?.strip_suffix('"')?;
+
+    let mut unescaped = String::new();
+    let mut chars = value.chars();
+    while let Some(char) = chars.next() {
+        match char {
+            '\\' => unescaped.extend(chars.next()),
+            _ => unescaped.push(char)
+        }
+    }
+
+    Some(Token::Tag(name.to_string(), unescaped))
+}
+
+///Writes the main line positions of the finished games in a PGN file as "fen [result]", which the tuner reads.
+///The first skip_plies positions of each game are left out. Returns the number of games and positions written
+pub fn extract_positions(pgn_path: &str, output_path: &str, skip_plies: usize) -> Result<(usize, usize), String> {
+    let text = fs::read_to_string(pgn_path).map_err(|e| format!("Could not read '{}': {}", pgn_path, e))?;
+
+    let mut output = String::new();
+    let mut games = 0;
+    let mut positions = 0;
+    for game in PgnReader::new(&text, false) {
+        let game = match game {
+            Ok(game) => game,
+            Err(e) => { println!(" Skipped {}", e); continue; }
+        };
+        let result = match game.result.as_str() {
+            "1-0" => "1.0",
+            "0-1" => "0.0",
+            "1/2-1/2" => "0.5",
+            _ => continue
+        };
+
+        games += 1;
+        for position in game.positions().iter().skip(skip_plies) {
+            output += &format!("{} [{}]\n", position.to_fen(), result);
+            positions += 1;
+        }
+    }
+
+    fs::write(output_path, output).map_err(|e| format!("Could not write '{}': {}", output_path, e))?;
+    Ok((games, positions))
+}
+
+#[cfg(test)]
+mod pgn_tests {
+    use crate::*;
+
+    const GAMES: &str = r#"[Event "Casual game"]
+[Site "?"]
+[Date "2024.01.02"]
+[Round "1"]
+[White "JENCE"]
+[Black "Some \"quoted\" player"]
+[Result "1-0"]
+
+{Opening comment} 1. e4 e5 2. Nf3 Nc6 3. Bb5 $1 {The Ruy Lopez} a6 (3... Nf6 4. O-O (4. d3) 4... Nxe4) 4. Ba4!? Nf6 5. 0-0
+; rest of line comment
+Be7 6. Re1 b5 7. Bb3 d6 8. c3 O-O 1-0
+
+[Event "Broken"]
+
+1. e4 e5 2. Ke3 Nc6 *
+
+[Event "From a position"]
+[SetUp "1"]
+[FEN "8/4P1k1/8/8/8/8/8/K7 w - - 0 60"]
+
+60. e8=Q Kh6 61. Qe3+ 1/2-1/2
+"#;
+
+    fn uci_moves(moves: &[PgnMove]) -> Vec<String> {
+        moves.iter().map(|m| m.mov.to_uci()).collect()
+    }
+
+    #[test]
+    pub fn games_are_read_with_annotations() {
+        let games: Vec<Result<PgnGame, String>> = PgnReader::new(GAMES, true).collect();
+        assert_eq!(games.len(), 3);
+
+        let game = games[0].as_ref().unwrap();
+        assert_eq!(game.tag("White"), Some("JENCE"));
+        assert_eq!(game.tag("Black"), Some("Some \"quoted\" player"));
+        assert_eq!(game.result, "1-0");
+        assert_eq!(game.comment.as_deref(), Some("Opening comment"));
+        assert_eq!(game.moves.len(), 16);
+        assert_eq!(uci_moves(&game.moves[..5]), ["e2e4", "e7e5", "g1f3", "b8c6", "f1b5"]);
+
+        let bishop = &game.moves[4];
+        assert_eq!(bishop.nags, [1]);
+        assert_eq!(bishop.comment.as_deref(), Some("The Ruy Lopez"));
+        assert_eq!(game.moves[5].variations.len(), 1);
+        let variation = &game.moves[5].variations[0];
+        assert_eq!(uci_moves(variation), ["g8f6", "e1g1", "f6e4"]);
+        assert_eq!(uci_moves(&variation[1].variations[0]), ["d2d3"]);
+
+        assert_eq!(game.moves[6].nags, [5]);
+        assert_eq!(game.moves[8].mov.to_uci(), "e1g1");
+        assert_eq!(game.moves[8].comment.as_deref(), Some("rest of line comment"));
+
+        let positions = game.positions();
+        assert_eq!(positions.len(), 17);
+        assert_eq!(positions[16].to_fen(), "r1bq1rk1/2p1bppp/p1np1n2/1p2p3/4P3/1BP2N2/PP1P1PPP/RNBQR1K1 w - - 1 9");
+    }
+
+    #[test]
+    pub fn broken_games_are_skipped() {
+        let games: Vec<Result<PgnGame, String>> = PgnReader::new(GAMES, false).collect();
+
+        assert!(games[1].as_ref().is_err_and(|e| e.starts_with("Game 2: Illegal move 'Ke3'")));
+
+        let game = games[2].as_ref().unwrap();
+        assert_eq!(game.start.full_moves, 60);
+        assert_eq!(uci_moves(&game.moves), ["e7e8q", "g7h6", "e8e3"]);
+        assert_eq!(game.result, "1/2-1/2");
+
+        //Variations are dropped unless kept
+        assert!(games[0].as_ref().unwrap().moves.iter().all(|m| m.variations.is_empty()));
+    }
+
+    #[test]
+    pub fn written_games_read_back_the_same() {
+        for game in PgnReader::new(GAMES, true).flatten() {
+            let pgn = game.to_pgn();
+            assert!(pgn.lines().all(|line| line.len() <= 80));
+
+            let read: Vec<PgnGame> = PgnReader::new(&pgn, true).flatten().collect();
+            assert_eq!(read.len(), 1);
+            assert_eq!(read[0].to_pgn(), pgn);
+            assert!(game.tags.iter().all(|(name, value)| read[0].tag(name) == Some(value.as_str())));
+            assert_eq!(uci_moves(&read[0].moves), uci_moves(&game.moves));
+        }
+
+        let game = PgnReader::new(GAMES, true).next().unwrap().unwrap();
+        assert!(game.to_pgn().replace('\n', " ").contains("3. Bb5 $1 {The Ruy Lopez} 3... a6 (3... Nf6 4. O-O (4. d3) 4... Nxe4) 4. Ba4 $5 Nf6 5. O-O"));
+    }
+
+    #[test]
+    pub fn positions_are_extracted_for_tuning() {
+        let dir = std::env::temp_dir();
+        let pgn_path = dir.join("jence_extract_test.pgn");
+        let output_path = dir.join("jence_extract_test.txt");
+        std::fs::write(&pgn_path, GAMES).unwrap();
+
+        let (games, positions) = extract_positions(pgn_path.to_str().unwrap(), output_path.to_str().unwrap(), 10).unwrap();
+        //The broken game is skipped, and the last game is shorter than the skipped plies
+        assert_eq!((games, positions), (2, 7));
+
+        let loaded = load_tuning_positions(output_path.to_str().unwrap()).unwrap();
+        assert_eq!(loaded.len(), 7);
+        assert!(loaded.iter().all(|p| p.result == 1.0));
+    }
+
+    #[test]
+    pub fn new_games_have_the_seven_tag_roster() {
+        let start = Game::new_from_fen("8/4P1k1/8/8/8/8/8/K7 w - - 0 60").unwrap();
+        let mut game = start;
+        let moves: Vec<Move> = ["e7e8q", "g7h6"].iter().map(|m| {
+            let mov = game.parse_move(m.to_string()).unwrap();
+            make_move(&mut game, &mov);
+            mov
+        }).collect();
+
+        let pgn = PgnGame::new(&start, &moves, "*").to_pgn();
+        assert_eq!(pgn, "[Event \"?\"]\n[Site \"?\"]\n[Date \"????.??.??\"]\n[Round \"?\"]\n[White \"?\"]\n[Black \"?\"]\n[Result \"*\"]\n\
+                         [SetUp \"1\"]\n[FEN \"8/4P1k1/8/8/8/8/8/K7 w - - 0 60\"]\n\n60. e8=Q Kh6 *\n\n");
+    }
+}
@@ -1,33 +1,226 @@
+#[derive(Clone, Copy)]
+struct RepetitionEntry {
+    hash: u64,
+    //How many of the preceding positions can possibly repeat this one
+    reversible: usize
+}
+
+///History of the positions in the game and the current search line
+#[derive(Clone)]
 pub struct RepetitionTable {
-    pub table: [u64; 1000],
-    pub index: usize
+    entries: Vec<RepetitionEntry>,
+    //Entries from this index on were made by the search
+    root: usize
 }
 
 impl RepetitionTable {
     pub fn new() -> Self {
-        Self {table: [0; 1000], index: 0 }
+        Self { entries: Vec::with_capacity(1024), root: 0 }
+    }
+
+    ///Adds the position reached by a move. Positions before the last capture or pawn move can't repeat
+    pub fn push(&mut self, hash: u64, half_moves: u8) {
+        let reversible = match self.entries.last() {
+            Some(last) => (half_moves as usize).min(last.reversible + 1),
+            None => 0
+        };
+
+        self.entries.push(RepetitionEntry { hash, reversible });
+    }
+
+    ///Adds the position reached by a null move, which can't repeat anything before it
+    pub fn push_null(&mut self, hash: u64) {
+        self.entries.push(RepetitionEntry { hash, reversible: 0 });
     }
 
-    pub fn insert(&mut self, hash: u64) {
-        self.table[self.index] = hash;
-        self.index += 1;
+    pub fn pop(&mut self) {
+        self.entries.pop();
     }
 
-    pub fn move_back(&mut self) {
-        self.index -= 1
+    ///Marks the current position as the search root, adding it if it isn't the latest position
+    pub fn set_root(&mut self, hash: u64, half_moves: u8) {
+        if self.entries.last().map(|e| e.hash) != Some(hash) {
+            self.push(hash, half_moves);
+        }
+
+        self.root = self.entries.len();
     }
 
-    pub fn is_now_in_threefold_repetition(&mut self) -> bool {
-        let curr_hash = self.table[self.index];
-        for h in (0..self.index).rev() {
-            if self.table[h] == curr_hash {
-                return true;
+    ///Is the current position a draw by repetition.
+    ///Repeating a position from within the search is enough, while positions from before the root must have occured twice already
+    pub fn is_repetition(&self) -> bool {
+        let current = match self.entries.last() {
+            Some(entry) => *entry,
+            None => return false
+        };
+        let current_index = self.entries.len() - 1;
+
+        let mut earlier_occurences = 0;
+
+        //Same side to move and at least two moves by each side
+        let mut distance = 4;
+        while distance <= current.reversible {
+            let index = current_index - distance;
+
+            if self.entries[index].hash == current.hash {
+                if index >= self.root {
+                    return true;
+                }
+
+                earlier_occurences += 1;
+                if earlier_occurences == 2 {
+                    return true;
+                }
             }
+
+            distance += 2;
         }
-        return false
+
+        false
+    }
+
+    #[cfg(test)]
+    pub fn len(&self) -> usize {
+        self.entries.len()
     }
 
     pub fn clear(&mut self) {
-        self.index = 0;
+        self.entries.clear();
+        self.root = 0;
+    }
+}
+
+#[cfg(test)]
+mod repetition_tests {
+    use crate::*;
+
+    fn play(game: &mut Game, rep_table: &mut RepetitionTable, moves: &str) {
+        for mov in moves.split_whitespace() {
+            let parsed = game.parse_move(mov.to_string()).unwrap();
+            assert!(make_search_move(game, &parsed, rep_table, None));
+        }
+    }
+
+    fn start(rep_table: &mut RepetitionTable) -> Game {
+        let game = Game::new_from_start_pos();
+        rep_table.push(game.zobrist_hash, game.half_moves);
+        game
+    }
+
+    #[test]
+    pub fn twofold_inside_search_is_a_repetition() {
+        let mut rep_table = RepetitionTable::new();
+        let mut game = start(&mut rep_table);
+
+        rep_table.set_root(game.zobrist_hash, game.half_moves);
+        play(&mut game, &mut rep_table, "g1f3 g8f6 f3g1 f6g8");
+        assert!(!rep_table.is_repetition());
+
+        play(&mut game, &mut rep_table, "g1f3");
+        assert!(rep_table.is_repetition());
     }
-}
\ No newline at end of file
+
+    #[test]
+    pub fn returning_to_the_root_once_is_not_a_repetition() {
+        let mut rep_table = RepetitionTable::new();
+        let mut game = start(&mut rep_table);
+
+        rep_table.set_root(game.zobrist_hash, game.half_moves);
+        play(&mut game, &mut rep_table, "g1f3 g8f6 f3g1 f6g8");
+        assert!(!rep_table.is_repetition());
+
+        play(&mut game, &mut rep_table, "g1f3 g8f6 f3g1 f6g8");
+        assert!(rep_table.is_repetition());
+    }
+
+    #[test]
+    pub fn twofold_before_root_is_not_a_repetition() {
+        let mut rep_table = RepetitionTable::new();
+        let mut game = start(&mut rep_table);
+
+        play(&mut game, &mut rep_table, "g1f3 g8f6 f3g1 f6g8");
+        rep_table.set_root(game.zobrist_hash, game.half_moves);
+        assert!(!rep_table.is_repetition());
+    }
+
+    #[test]
+    pub fn threefold_before_root_is_a_repetition() {
+        let mut rep_table = RepetitionTable::new();
+        let mut game = start(&mut rep_table);
+
+        play(&mut game, &mut rep_table, "g1f3 g8f6 f3g1 f6g8 g1f3 g8f6 f3g1 f6g8");
+        rep_table.set_root(game.zobrist_hash, game.half_moves);
+        assert!(rep_table.is_repetition());
+    }
+
+    #[test]
+    pub fn irreversible_move_breaks_repetition() {
+        let mut rep_table = RepetitionTable::new();
+        let mut game = Game::new_from_fen("4k3/8/8/8/8/8/P7/4K3 w - - 0 1").unwrap();
+        rep_table.set_root(game.zobrist_hash, game.half_moves);
+
+        play(&mut game, &mut rep_table, "e1d1 e8d8 d1e1 d8e8 e1d1");
+        assert!(rep_table.is_repetition());
+
+        //The kings return to the same squares, but with the pawn moved
+        play(&mut game, &mut rep_table, "e8d8 a2a3 d8e8 d1e1 e8d8");
+        assert!(!rep_table.is_repetition());
+
+        play(&mut game, &mut rep_table, "e1d1");
+        assert!(rep_table.is_repetition());
+    }
+
+    #[test]
+    pub fn half_move_clock_bounds_the_lookback() {
+        let mut reset = RepetitionTable::new();
+        reset.set_root(9, 0);
+        reset.push(1, 1);
+        reset.push(2, 2);
+        reset.push(3, 0);
+        reset.push(4, 1);
+        reset.push(1, 2);
+        assert!(!reset.is_repetition());
+
+        let mut reversible = RepetitionTable::new();
+        reversible.set_root(9, 0);
+        reversible.push(1, 1);
+        reversible.push(2, 2);
+        reversible.push(3, 3);
+        reversible.push(4, 4);
+        reversible.push(1, 5);
+        assert!(reversible.is_repetition());
+    }
+
+    #[test]
+    pub fn null_move_is_a_barrier() {
+        let mut rep_table = RepetitionTable::new();
+        rep_table.set_root(9, 0);
+        rep_table.push(1, 1);
+        rep_table.push(2, 2);
+        rep_table.push_null(3);
+        rep_table.push(4, 4);
+        rep_table.push(1, 5);
+        assert!(!rep_table.is_repetition());
+    }
+
+    #[test]
+    pub fn grows_beyond_long_games() {
+        let mut rep_table = RepetitionTable::new();
+        for i in 0..5000 {
+            rep_table.push(i, (i % 100) as u8);
+        }
+        assert_eq!(rep_table.len(), 5000);
+        assert!(!rep_table.is_repetition());
+    }
+
+    #[test]
+    pub fn search_leaves_the_history_as_it_was() {
+        let mut rep_table = RepetitionTable::new();
+        let mut game = Game::new_from_fen("6k1/8/8/8/8/8/5Q2/6K1 w - - 0 1").unwrap();
+        rep_table.push(game.zobrist_hash, game.half_moves);
+
+        let result = search(&mut game, 4, -1, &IoWrapper::silent(), &mut TranspositionTable::new(), &mut rep_table, &Evaluator::new());
+        assert!(result.score > 500);
+        assert_eq!(rep_table.len(), 1);
+    }
+}
@@ -0,0 +1,248 @@
+use super::*;
+
+const SAN_PIECES: [char; 6] = ['P', 'N', 'B', 'R', 'Q', 'K'];
+
+fn file_char(square: u8) -> char {
+    (b'a' + square % 8) as char
+}
+
+fn rank_char(square: u8) -> char {
+    (b'8' - square / 8) as char
+}
+
+///Piece letter in SAN to the piece type, 0-5 as for white pieces
+fn san_piece_type(char: char) -> Option<u8> {
+    SAN_PIECES.iter().position(|c| *c == char).map(|i| i as u8)
+}
+
+impl Game {
+    ///Writes a legal move in Standard Algebraic Notation, like "Nbd7", "exd6", "e8=Q+" or "O-O#"
+    pub fn move_to_san(&self, mov: &Move) -> String {
+        let mut copy = *self;
+        let legal_moves = generate_moves(&mut copy, MoveTypes::All).legal_values(self);
+
+        let mut san = String::new();
+        let piece_type = mov.piece() % 6;
+        let from = mov.from_square();
+        let to = mov.to_square();
+
+        if mov.is_castling() {
+            san += if to % 8 == 6 { "O-O" } else { "O-O-O" };
+        }
+        else if piece_type == 0 {
+            if mov.is_capture() {
+                san.push(file_char(from));
+                san.push('x');
+            }
+            san += SQUARE_STRINGS[to as usize];
+            if mov.promotion() != Piece::None as u8 {
+                san.push('=');
+                san.push(SAN_PIECES[mov.promotion() as usize % 6]);
+            }
+        }
+        else {
+            san.push(SAN_PIECES[piece_type as usize]);
+
+            //Other pieces of the same kind that can go to the same square
+            let others: Vec<u8> = legal_moves.iter()
+                .filter(|m| m.piece() == mov.piece() && m.to_square() == to && m.from_square() != from)
+                .map(|m| m.from_square())
+                .collect();
+            if !others.is_empty() {
+                if others.iter().all(|sq| sq % 8 != from % 8) {
+                    san.push(file_char(from));
+                }
+                else if others.iter().all(|sq| sq / 8 != from / 8) {
+                    san.push(rank_char(from));
+                }
+                else {
+                    san += SQUARE_STRINGS[from as usize];
+                }
+            }
+
+            if mov.is_capture() {
+                san.push('x');
+            }
+            san += SQUARE_STRINGS[to as usize];
+        }
+
+        //Check and mate
+        make_move(&mut copy, mov);
+        if copy.is_in_check(copy.active_player) {
+            let mut reply_game = copy;
+            let has_reply = !generate_moves(&mut reply_game, MoveTypes::All).legal_values(&copy).is_empty();
+            san.push(if has_reply { '+' } else { '#' });
+        }
+
+        san
+    }
+
+    ///Finds the legal move written in Standard Algebraic Notation. Accepts "0-0" for castling, promotions without '=',
+    ///long forms like "Ng1-f3" and missing or extra check and annotation symbols
+    pub fn parse_san(&self, input: &str) -> Option<Move> {
+        let mut copy = *self;
+        let legal_moves = generate_moves(&mut copy, MoveTypes::All).legal_values(self);
+
+        let san = input.trim().trim_end_matches(['+', '#', '!', '?']).trim_end_matches("e.p.").trim();
+
+        //Castling
+        let castling = san.replace('0', "O");
+        if castling == "O-O" || castling == "O-O-O" {
+            let file = if castling == "O-O" { 6 } else { 2 };
+            return legal_moves.into_iter().find(|m| m.is_castling() && m.to_square() % 8 == file);
+        }
+
+        let mut chars: Vec<char> = san.chars().filter(|c| *c != 'x' && *c != ':' && *c != '-').collect();
+
+        let piece_type = match chars.first().copied().and_then(san_piece_type) {
+            Some(piece_type) => { chars.remove(0); piece_type },
+            None => 0
+        };
+
+        //Promotion, with or without '='
+        let mut promotion = None;
+        if piece_type == 0 && chars.len() >= 3 {
+            let last = chars[chars.len() - 1].to_ascii_uppercase();
+            let before = chars[chars.len() - 2];
+            if let (Some(promoted), true) = (san_piece_type(last), before == '=' || before == '1' || before == '8') {
+                promotion = Some(promoted);
+                chars.pop();
+                if before == '=' {
+                    chars.pop();
+                }
+            }
+        }
+
+        if chars.len() < 2 {
+            return None;
+        }
+        let to: String = chars.split_off(chars.len() - 2).into_iter().collect();
+        let to = square_from_string(&to)? as u8;
+
+        //What is left disambiguates the origin
+        let mut from_file = None;
+        let mut from_rank = None;
+        for char in chars {
+            match char {
+                'a'..='h' => from_file = Some(char as u8 - b'a'),
+                '1'..='8' => from_rank = Some(b'8' - char as u8),
+                _ => return None
+            }
+        }
+
+        let mut candidates = legal_moves.into_iter().filter(|m| {
+            m.piece() % 6 == piece_type &&
+            m.to_square() == to &&
+            from_file.is_none_or(|file| m.from_square() % 8 == file) &&
+            from_rank.is_none_or(|rank| m.from_square() / 8 == rank) &&
+            match promotion {
+                Some(promoted) => m.promotion() != Piece::None as u8 && m.promotion() % 6 == promoted,
+                None => m.promotion() == Piece::None as u8
+            }
+        });
+
+        //Ambiguous input matches nothing
+        match (candidates.next(), candidates.next()) {
+            (Some(mov), None) => Some(mov),
+            _ => None
+        }
+    }
+}
+
+#[cfg(test)]
+mod san_tests {
+    use crate::*;
+
+    fn san(fen: &str, uci: &str) -> String {
+        let mut game = Game::new_from_fen(fen).unwrap();
+        let mov = game.parse_move(uci.to_string()).unwrap();
+        game.move_to_san(&mov)
+    }
+
+    fn parse(fen: &str, san: &str) -> Option<String> {
+        Game::new_from_fen(fen).unwrap().parse_san(san).map(|m| m.to_uci())
+    }
+
+    #[test]
+    pub fn moves_are_written_in_san() {
+        let start = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+        assert_eq!(san(start, "e2e4"), "e4");
+        assert_eq!(san(start, "g1f3"), "Nf3");
+
+        let kiwipete = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+        assert_eq!(san(kiwipete, "e1g1"), "O-O");
+        assert_eq!(san(kiwipete, "e1c1"), "O-O-O");
+        assert_eq!(san(kiwipete, "d5e6"), "dxe6");
+        assert_eq!(san(kiwipete, "e5f7"), "Nxf7");
+        assert_eq!(san(kiwipete, "g2h3"), "gxh3");
+
+        //En passant
+        assert_eq!(san("k7/8/8/3pP3/8/8/8/7K w - d6 0 1", "e5d6"), "exd6");
+        //Promotion with check and mate
+        assert_eq!(san("8/4P1k1/8/8/8/8/8/K7 w - - 0 1", "e7e8q"), "e8=Q");
+        assert_eq!(san("7k/4P3/8/8/8/8/8/K7 w - - 0 1", "e7e8q"), "e8=Q+");
+        assert_eq!(san("6k1/5ppp/8/8/8/8/8/K3R3 w - - 0 1", "e1e8"), "Re8#");
+    }
+
+    #[test]
+    pub fn ambiguous_moves_are_disambiguated() {
+        //By file
+        assert_eq!(san("4k3/8/8/8/8/8/8/R4R1K w - - 0 1", "a1c1"), "Rac1");
+        //By rank
+        assert_eq!(san("4k3/R7/8/8/8/8/8/R6K w - - 0 1", "a1a4"), "R1a4");
+        //By file, rank or both
+        let queens = "k7/8/8/8/8/2Q5/8/2Q1Q2K w - - 0 1";
+        assert_eq!(san(queens, "e1d2"), "Qed2");
+        assert_eq!(san(queens, "c3d2"), "Q3d2");
+        assert_eq!(san(queens, "c1d2"), "Qc1d2");
+        //A pinned piece doesn't count
+        assert_eq!(san("k2r4/8/8/1N1N4/8/8/8/3K4 w - - 0 1", "b5c3"), "Nc3");
+        assert_eq!(san("k7/8/8/1N1N4/8/8/8/3K4 w - - 0 1", "b5c3"), "Nbc3");
+    }
+
+    #[test]
+    pub fn common_variants_are_parsed() {
+        let kiwipete = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+        assert_eq!(parse(kiwipete, "O-O"), Some("e1g1".to_string()));
+        assert_eq!(parse(kiwipete, "0-0-0"), Some("e1c1".to_string()));
+        assert_eq!(parse(kiwipete, "Nxf7"), Some("e5f7".to_string()));
+        assert_eq!(parse(kiwipete, "Nf7!?"), Some("e5f7".to_string()));
+        assert_eq!(parse(kiwipete, "Ne5xf7"), Some("e5f7".to_string()));
+        assert_eq!(parse(kiwipete, "gxh3"), Some("g2h3".to_string()));
+        assert_eq!(parse(kiwipete, "g2-g4"), Some("g2g4".to_string()));
+
+        let promotion = "7k/4P3/8/8/8/8/8/K7 w - - 0 1";
+        assert_eq!(parse(promotion, "e8=Q+"), Some("e7e8q".to_string()));
+        assert_eq!(parse(promotion, "e8=Q"), Some("e7e8q".to_string()));
+        assert_eq!(parse(promotion, "e8N"), Some("e7e8n".to_string()));
+        assert_eq!(parse(promotion, "e8=r"), Some("e7e8r".to_string()));
+
+        //Promotions need a piece, and ambiguous, illegal or malformed moves are rejected
+        assert_eq!(parse(promotion, "e8"), None);
+        assert_eq!(parse("4k3/8/8/8/8/8/8/R4R1K w - - 0 1", "Rc1"), None);
+        assert_eq!(parse(kiwipete, "Ke3"), None);
+        assert_eq!(parse(kiwipete, "Nz9"), None);
+        assert_eq!(parse(kiwipete, ""), None);
+    }
+
+    #[test]
+    pub fn san_round_trips_over_the_perft_positions() {
+        let fens = [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            "r2q1rk1/pP1p2pp/Q4n2/bbp1p3/Np6/1B3NBn/pPPP1PPP/R3K2R b KQ - 0 1",
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+            "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+        ];
+
+        for fen in fens {
+            let mut game = Game::new_from_fen(fen).unwrap();
+            let moves = generate_moves(&mut game, MoveTypes::All).legal_values(&game);
+            for mov in moves {
+                let san = game.move_to_san(&mov);
+                assert!(game.parse_san(&san) == Some(mov), "{} in {} did not round trip", san, fen);
+            }
+        }
+    }
+}
@@ -1,28 +1,64 @@
-use rand::{Rng};
+use rand::seq::SliceRandom;
 
 use super::*;
 
 const MAX_PLY: usize = 64;
 const FULL_DEPTH_MOVES: u8 = 4;
 const REDUCTION_LIMIT: u8 = 3;
+const MAX_EXTENSIONS: u8 = 16;
+const SINGULAR_DEPTH: u8 = 8;
+const SINGULAR_MARGIN: i32 = 2;
+const IIR_DEPTH: u8 = 4;
+const MAX_HISTORY: i32 = 16384;
+const MAX_HISTORY_BONUS: i32 = 1200;
 pub const MATE_VALUE: i32 = 49000;
 pub const MATE_BOUND: i32 = 48000; //Lower bound for mating score
+pub const TB_WIN: i32 = 47000; //Tablebase wins are scored below mates
 const INFINITY: i32 = 50000;
 
 const INPUT_POLL_INTERVAL: u64 = 16383;
 
 pub fn search_random(game: &mut Game) {
-    let moves = generate_moves(&mut *game, MoveTypes::All);
-    let rand = rand::thread_rng().gen_range(0..moves.len());
-    print!("bestmove {}\n", moves.get(rand).to_uci());
+    let moves = generate_moves(&mut *game, MoveTypes::All).legal_values(game);
+    match moves.choose(&mut rand::thread_rng()) {
+        Some(mov) => println!("bestmove {}", mov.to_uci()),
+        //No legal moves
+        None => println!("bestmove 0000")
+    }
 }
 
 //Start a search, max_time = -1 for no limit
-pub fn search(game: &mut Game, depth: i8, max_time: i64, io_receiver: &IoWrapper, tt: &mut TranspositionTable, rep_table: &mut RepetitionTable) -> SearchResult {
+pub fn search(game: &mut Game, depth: i8, max_time: i64, io_receiver: &IoWrapper, tt: &mut TranspositionTable, rep_table: &mut RepetitionTable, evaluator: &Evaluator) -> SearchResult {
+
+    rep_table.set_root(game.zobrist_hash, game.half_moves);
 
-    let mut envir = SearchEnv::new(max_time, io_receiver, tt, rep_table);
+    let mut envir = SearchEnv::new(max_time, io_receiver, tt, rep_table, evaluator);
+    envir.nnue = evaluator.active_network().map(|network| AccumulatorStack::new(network, game));
+
+    iterative_deepening(game, depth, &mut envir, true)
+}
 
-    let mut score = 0;
+///Search without output or input, stopping after max_time milliseconds (-1 for no limit) or max_nodes nodes (0 for no limit).
+///Used for self-play and test suites
+pub fn search_silently(game: &mut Game, depth: i8, max_time: i64, max_nodes: u64, tt: &mut TranspositionTable, rep_table: &mut RepetitionTable, evaluator: &Evaluator) -> SearchResult {
+
+    rep_table.set_root(game.zobrist_hash, game.half_moves);
+
+    let io_receiver = IoWrapper::silent();
+    let mut envir = SearchEnv::new(max_time, &io_receiver, tt, rep_table, evaluator);
+    envir.nnue = evaluator.active_network().map(|network| AccumulatorStack::new(network, game));
+    if max_nodes != 0 {
+        envir.max_nodes = max_nodes;
+    }
+
+    iterative_deepening(game, depth, &mut envir, false)
+}
+
+fn iterative_deepening(game: &mut Game, depth: i8, envir: &mut SearchEnv, print_info: bool) -> SearchResult {
+    let mut score;
+    let mut best_score = 0;
+    let mut best_move = NULL_MOVE;
+    let mut completed_depth = 0;
 
     let mut alpha = -INFINITY;
     let mut beta  =  INFINITY;
@@ -30,10 +66,18 @@ pub fn search(game: &mut Game, depth: i8, max_time: i64, io_receiver: &IoWrapper
     let mut current_depth: u8 = 1;
     let max_depth = if depth == -1 { MAX_PLY as u8 } else { depth as u8 };
 
+    //Only the tablebase optimal moves are searched at the root
+    if let Some(tablebases) = &envir.evaluator.tablebases {
+        envir.root_moves = tablebases.root_moves(game);
+        if envir.root_moves.is_some() {
+            envir.tb_hits += 1;
+        }
+    }
+
     while current_depth <= max_depth as u8 {
         envir.follow_pv = true;
 
-        score = negamax(game, current_depth as u8, alpha, beta, &mut envir);
+        score = negamax(game, current_depth as u8, alpha, beta, envir);
 
         if envir.stopping { break }
 
@@ -50,27 +94,43 @@ pub fn search(game: &mut Game, depth: i8, max_time: i64, io_receiver: &IoWrapper
         alpha = score - 50;
         beta  = score + 50;
 
-        if score >= -MATE_VALUE && score < -MATE_BOUND {
-            print!("info score mate {} depth {} nodes {} time {} pv ", -(score + MATE_VALUE) / 2 - 1, current_depth, envir.nodes, envir.start_time.elapsed().unwrap().as_millis());
-        }
-        else if score <= MATE_VALUE && score > MATE_BOUND {
-            print!("info score mate {} depth {} nodes {} time {} pv ", (MATE_VALUE - score) / 2 + 1, current_depth, envir.nodes, envir.start_time.elapsed().unwrap().as_millis());
-        }
-        else {
-            print!("info score cp {} depth {} nodes {} time {} pv ", score, current_depth, envir.nodes, envir.start_time.elapsed().unwrap().as_millis());
-        }
+        best_score = score;
+        best_move = envir.pv_table[0][0];
+        completed_depth = current_depth;
 
-        for i in 0..envir.pv_lengths[0] {
-            print!("{} ", envir.pv_table[0][i].to_uci());
+        if print_info {
+            if score >= -MATE_VALUE && score < -MATE_BOUND {
+                print!("info score mate {} depth {} nodes {} tbhits {} time {} pv ", -(score + MATE_VALUE) / 2 - 1, current_depth, envir.nodes, envir.tb_hits, envir.start_time.elapsed().unwrap().as_millis());
+            }
+            else if score <= MATE_VALUE && score > MATE_BOUND {
+                print!("info score mate {} depth {} nodes {} tbhits {} time {} pv ", (MATE_VALUE - score) / 2 + 1, current_depth, envir.nodes, envir.tb_hits, envir.start_time.elapsed().unwrap().as_millis());
+            }
+            else {
+                print!("info score cp {} depth {} nodes {} tbhits {} time {} pv ", score, current_depth, envir.nodes, envir.tb_hits, envir.start_time.elapsed().unwrap().as_millis());
+            }
+
+            for i in 0..envir.pv_lengths[0] {
+                print!("{} ", envir.pv_table[0][i].to_uci());
+            }
+            print!("\n");
         }
-        print!("\n");
         
         current_depth += 1;
     }
 
-    print!("bestmove {}\n", envir.pv_table[0][0].to_uci());
+    if print_info {
+        print!("bestmove {}\n", envir.pv_table[0][0].to_uci());
+    }
+
+    //The result is from the last completed iteration, unless none completed
+    if best_move == NULL_MOVE {
+        best_move = envir.pv_table[0][0];
+    }
 
-    SearchResult::new(envir.pv_table[0][0], envir.nodes, score, current_depth - 1, !envir.stopping, envir.tt_hits)
+    let mut result = SearchResult::new(best_move, envir.nodes, best_score, completed_depth, !envir.stopping, envir.tt_hits);
+    result.pawn_table_probes = envir.pawn_table.probes;
+    result.pawn_table_hits = envir.pawn_table.hits;
+    result
 }
 
 fn enable_pv_scoring(moves: &MoveList, envir: &mut SearchEnv) {
@@ -85,12 +145,35 @@ fn enable_pv_scoring(moves: &MoveList, envir: &mut SearchEnv) {
 }
 
 #[inline]
-fn negamax(game: &mut Game, depth: u8, alpha: i32, beta: i32, envir: &mut SearchEnv) -> i32 {
+fn negamax(game: &mut Game, depth: u8, mut alpha: i32, mut beta: i32, envir: &mut SearchEnv) -> i32 {
     
     let is_pv_node = (beta - alpha) > 1;
 
+    //Move excluded by a singular extension search of this position
+    let excluded_move = envir.excluded_moves[envir.ply as usize];
+
+    envir.pv_lengths[envir.ply as usize] = envir.ply as usize;
+
+    if envir.ply > 0 {
+        //Draws
+        if envir.repetition_table.is_repetition() || game.is_insufficient_material() {
+            return 0;
+        }
+
+        if game.half_moves >= 100 {
+            return fifty_move_score(game, envir.ply);
+        }
+
+        //Mate distance pruning
+        alpha = alpha.max(-MATE_VALUE + envir.ply as i32);
+        beta = beta.min(MATE_VALUE - envir.ply as i32 - 1);
+        if alpha >= beta {
+            return alpha;
+        }
+    }
+
     let mut score;
-    if envir.ply != 0 && !is_pv_node {
+    if envir.ply != 0 && !is_pv_node && excluded_move == NULL_MOVE {
         score = envir.transposition_table.probe(game.zobrist_hash, depth, alpha, beta, envir.ply);
         if score != UNKNOWN_SCORE {
             envir.tt_hits += 1;
@@ -98,22 +181,29 @@ fn negamax(game: &mut Game, depth: u8, alpha: i32, beta: i32, envir: &mut Search
         }
     }
 
-    envir.pv_lengths[envir.ply as usize] = envir.ply as usize;
+    //Tablebase cutoff. The tables don't know the fifty move counter, so they are only probed right after it is reset
+    if envir.ply > 0 && excluded_move == NULL_MOVE && game.half_moves == 0 {
+        if let Some(wdl) = envir.evaluator.tablebases.as_ref().and_then(|tablebases| tablebases.probe_wdl(game)) {
+            envir.tb_hits += 1;
 
-    if envir.ply > 0 && envir.repetition_table.is_now_in_threefold_repetition() {
-        return 0;
+            return match wdl {
+                Wdl::Win => TB_WIN - envir.ply as i32,
+                Wdl::Loss => -TB_WIN + envir.ply as i32,
+                _ => 0
+            };
+        }
     }
 
     //Dont't go on if reached max ply
     if envir.ply >= MAX_PLY as u8 - 1  {
-        return evaluate(&game);
+        return envir.static_eval(game);
     }
 
-    if envir.nodes & INPUT_POLL_INTERVAL == 0 {
+    if envir.nodes & INPUT_POLL_INTERVAL == 0 || envir.nodes >= envir.max_nodes {
         envir.poll_input()
     }
 
-    if depth == 0 || game.half_moves == 100 {
+    if depth == 0 {
         //return evaluate(game)
         return quiescence(game, alpha, beta, envir);
     }
@@ -122,16 +212,35 @@ fn negamax(game: &mut Game, depth: u8, alpha: i32, beta: i32, envir: &mut Search
 
     envir.nodes += 1;
 
+    let tt_entry = if excluded_move == NULL_MOVE { envir.transposition_table.probe_entry(game.zobrist_hash, envir.ply) } else { None };
+    let hash_move = match tt_entry {
+        Some(TranspositionTableEntry::Record { best, .. }) => best,
+        _ => NULL_MOVE
+    };
+
+    //Internal iterative reduction
+    //Without a hash move the ordering is poor, so search shallower and leave a best move for the next visit
+    let depth = if envir.ply > 0 && hash_move == NULL_MOVE && excluded_move == NULL_MOVE && depth >= IIR_DEPTH { depth - 1 } else { depth };
+
     let in_check = game.is_in_check(game.active_player);
 
-    let n_depth = if in_check { depth + 1 } else { depth };
+    //Extensions used on the line leading to this node
+    let mut extensions = envir.extensions[envir.ply as usize];
+
+    //Check extension
+    let n_depth = if in_check && extensions < MAX_EXTENSIONS {
+        extensions += 1;
+        depth + 1
+    } else {
+        depth
+    };
 
     let mut temp_alpha = alpha;
 
     let mut legal_moves = 0;
 
     //Null move pruning
-    if n_depth >= 3 && !in_check && envir.ply > 0 {
+    if n_depth >= 3 && !in_check && envir.ply > 0 && excluded_move == NULL_MOVE {
         let mut copy = *game;
 
         //Switch side + update hash
@@ -146,10 +255,18 @@ fn negamax(game: &mut Game, depth: u8, alpha: i32, beta: i32, envir: &mut Search
 
         //..., Depth - 1 - R (with R = 2), ...
 
+        envir.move_stack[envir.ply as usize] = NULL_MOVE;
+
         envir.ply += 1;
 
+        envir.extensions[envir.ply as usize] = extensions;
+
+        envir.repetition_table.push_null(copy.zobrist_hash);
+
         score = -negamax(&mut copy, n_depth - 1 - 2, -beta, -beta + 1, envir);
 
+        envir.repetition_table.pop();
+
         envir.ply -= 1;
 
         if envir.stopping { return 0 }
@@ -160,42 +277,100 @@ fn negamax(game: &mut Game, depth: u8, alpha: i32, beta: i32, envir: &mut Search
         }
     }
 
+    //Singular extension
+    //If every move but the hash move fails low against a margin below the hash score, the hash move is singular
+    let mut singular = false;
+    if let Some(TranspositionTableEntry::Record { depth: tt_depth, flag: tt_flag, score: tt_score, best, .. }) = tt_entry {
+        if  envir.ply > 0 &&
+            depth >= SINGULAR_DEPTH &&
+            best != NULL_MOVE &&
+            tt_flag != HashFlag::Alpha &&
+            tt_depth + 3 >= depth &&
+            tt_score.abs() < MATE_BOUND &&
+            extensions < MAX_EXTENSIONS {
+
+            let singular_beta = tt_score - SINGULAR_MARGIN * depth as i32;
+            let follow_pv = envir.follow_pv;
+
+            envir.follow_pv = false;
+            envir.excluded_moves[envir.ply as usize] = best;
+
+            score = negamax(game, (depth - 1) / 2, singular_beta - 1, singular_beta, envir);
+
+            envir.excluded_moves[envir.ply as usize] = NULL_MOVE;
+            envir.follow_pv = follow_pv;
+
+            if envir.stopping { return 0 }
+
+            singular = score < singular_beta;
+        }
+    }
+
     let mut moves = generate_moves(game, MoveTypes::All);
 
     if envir.follow_pv {
         enable_pv_scoring(&moves, envir)
     }
 
-    moves.sort_moves(game, envir);
+    moves.sort_moves(game, envir, hash_move);
+
+    let previous_move = if envir.ply > 0 { envir.move_stack[envir.ply as usize - 1] } else { NULL_MOVE };
 
     let mut moves_searched = 0;
 
+    let mut best_move = NULL_MOVE;
+
+    //Moves searched without causing a cut-off, penalized when another move does
+    let mut quiets_searched = MoveList::new();
+    let mut captures_searched = MoveList::new();
+
     for i in 0..moves.len() {
         let m = moves.get(i);
+
+        if m == excluded_move || (envir.ply == 0 && envir.root_moves.as_ref().is_some_and(|moves| !moves.contains(&m))) {
+            continue;
+        }
         
         let mut copy = game.clone();
 
-        envir.ply += 1;
-
-        if !make_search_move(&mut copy, &m, &mut envir.repetition_table) { 
-            envir.ply -= 1; 
-            
+        if !make_search_move(&mut copy, &m, &mut envir.repetition_table, envir.nnue.as_mut()) { 
             continue;
         }
 
         legal_moves += 1;
 
-        envir.repetition_table.move_back();
+        //Move extensions
+        let extension = if extensions >= MAX_EXTENSIONS {
+            0
+        } else if singular && m == hash_move {
+            1
+        } else if is_pv_node && m.is_capture() && previous_move.is_capture() && m.to_square() == previous_move.to_square() {
+            //Recapture
+            1
+        } else if is_passed_pawn_push_to_seventh(&copy, &m) {
+            1
+        } else {
+            0
+        };
+
+        envir.move_stack[envir.ply as usize] = m;
+
+        envir.ply += 1;
+
+        envir.extensions[envir.ply as usize] = extensions + extension;
+
+        let child_depth = n_depth - 1 + extension;
 
         if moves_searched == 0 {
             //Full PV Search
-            score = -negamax(&mut copy, n_depth - 1, -beta, -temp_alpha, envir);
+            score = -negamax(&mut copy, child_depth, -beta, -temp_alpha, envir);
         } else {
             //Regular search with LMR
 
             score = if  moves_searched >= FULL_DEPTH_MOVES && 
                         depth >= REDUCTION_LIMIT &&
                         !in_check &&
+                        extension == 0 &&
                         !m.is_capture() &&
                         m.promotion() == Piece::None as u8 {
                 //Reduced search
@@ -208,16 +383,18 @@ fn negamax(game: &mut Game, depth: u8, alpha: i32, beta: i32, envir: &mut Search
 
             //PVS
             if score > temp_alpha {
-                score = -negamax(&mut copy, n_depth - 1, -temp_alpha - 1, -temp_alpha, envir);
+                score = -negamax(&mut copy, child_depth, -temp_alpha - 1, -temp_alpha, envir);
 
                 //Check bounds
                 if score > temp_alpha && score < beta {
                     //Full search on failure
-                    score = -negamax(&mut copy, n_depth - 1, -beta, -temp_alpha, envir);
+                    score = -negamax(&mut copy, child_depth, -beta, -temp_alpha, envir);
                 }
             }
         }
 
+        envir.undo_search_move();
+
         envir.ply -= 1;
 
         moves_searched += 1;
@@ -228,16 +405,29 @@ fn negamax(game: &mut Game, depth: u8, alpha: i32, beta: i32, envir: &mut Search
             //Insert PV node
             envir.insert_pv_node(m);
 
+            best_move = m;
+
             //Beta cut-off
             if score >= beta {
-                //Update killer moves
                 if !m.is_capture() {
+                    //Update killer moves
                     envir.killer_moves[1][envir.ply as usize] = envir.killer_moves[0][envir.ply as usize];
                     envir.killer_moves[0][envir.ply as usize] = Some(m);
+
+                    //Update countermove
+                    if previous_move != NULL_MOVE {
+                        envir.counter_moves[previous_move.piece() as usize][previous_move.to_square() as usize] = m;
+                    }
+
+                    envir.update_quiet_history(m, &quiets_searched, depth);
+                } else {
+                    envir.update_capture_history(game, m, &captures_searched, depth);
                 }
     
                 //Record TT entry
-                envir.transposition_table.record(game.zobrist_hash, beta, depth, HashFlag::Beta, envir.ply);
+                if excluded_move == NULL_MOVE {
+                    envir.transposition_table.record(game.zobrist_hash, beta, depth, HashFlag::Beta, m, envir.ply);
+                }
     
                 return beta;
             }
@@ -245,17 +435,32 @@ fn negamax(game: &mut Game, depth: u8, alpha: i32, beta: i32, envir: &mut Search
             //Record TT entry
             hash_flag = HashFlag::Exact;
 
-            //Update history move
-            if !m.is_capture() {
-                envir.history_moves[m.piece() as usize][m.to_square() as usize] += depth as i32
-            }
-
             temp_alpha = score;
         }
+
+        if m.is_capture() {
+            captures_searched.add_move(m);
+        } else {
+            quiets_searched.add_move(m);
+        }
+    }
+
+    //Reward the move that raised alpha
+    if hash_flag == HashFlag::Exact {
+        if best_move.is_capture() {
+            envir.update_capture_history(game, best_move, &captures_searched, depth);
+        } else {
+            envir.update_quiet_history(best_move, &quiets_searched, depth);
+        }
     }
 
     //Mate & Draw
     if legal_moves == 0 {
+        //The excluded move was the only legal move
+        if excluded_move != NULL_MOVE {
+            return alpha;
+        }
+
         if in_check {
             return -MATE_VALUE + envir.ply as i32;
         }
@@ -265,23 +470,56 @@ fn negamax(game: &mut Game, depth: u8, alpha: i32, beta: i32, envir: &mut Search
     }
     
     //Record TT entry
-    envir.transposition_table.record(game.zobrist_hash, temp_alpha, depth, hash_flag, envir.ply);
+    if excluded_move == NULL_MOVE {
+        envir.transposition_table.record(game.zobrist_hash, temp_alpha, depth, hash_flag, best_move, envir.ply);
+    }
 
     temp_alpha
 }
 
+///Draw by the fifty move rule, unless the side to move is mated on the last move
+fn fifty_move_score(game: &mut Game, ply: u8) -> i32 {
+    if game.is_in_check(game.active_player) && !has_legal_moves(game) {
+        -MATE_VALUE + ply as i32
+    } else {
+        0
+    }
+}
+
+fn has_legal_moves(game: &mut Game) -> bool {
+    let moves = generate_moves(game, MoveTypes::All);
+    moves.iter().any(|m| is_legal(game, m))
+}
+
+///Pawn moving to its 7th rank with no enemy pawns able to stop it. Expects the position after the move
+#[inline(always)]
+fn is_passed_pawn_push_to_seventh(game: &Game, cmove: &Move) -> bool {
+    let to_sq = cmove.to_square();
+    if cmove.piece() == Piece::WhitePawn as u8 {
+        to_sq / 8 == 1 && game.get_piece_bitboard(Piece::BlackPawn).and(Bitboard::from_u64(WHITE_PASSED_PAWN_MASKS[to_sq as usize])).is_empty()
+    } else if cmove.piece() == Piece::BlackPawn as u8 {
+        to_sq / 8 == 6 && game.get_piece_bitboard(Piece::WhitePawn).and(Bitboard::from_u64(BLACK_PASSED_PAWN_MASKS[to_sq as usize])).is_empty()
+    } else {
+        false
+    }
+}
+
 #[inline]
 fn quiescence(game: &mut Game, alpha: i32, beta: i32, envir: &mut SearchEnv) -> i32 {
-    if envir.nodes & INPUT_POLL_INTERVAL == 0 {
+    if envir.nodes & INPUT_POLL_INTERVAL == 0 || envir.nodes >= envir.max_nodes {
         envir.poll_input()
     }
 
     envir.nodes += 1;
 
-    let eval = evaluate(&game);
+    if game.is_insufficient_material() {
+        return 0;
+    }
+
+    let eval = envir.static_eval(game);
 
     //Dont't go on if reached max ply
-    if envir.ply > MAX_PLY as u8 - 1 || game.half_moves == 100 {
+    if envir.ply > MAX_PLY as u8 - 1 {
         return eval;
     }
 
@@ -296,23 +534,28 @@ fn quiescence(game: &mut Game, alpha: i32, beta: i32, envir: &mut SearchEnv) ->
     }
 
     let mut moves = generate_moves(game, MoveTypes::Quiescence);
-    moves.sort_moves(game, envir);
+    moves.sort_moves(game, envir, NULL_MOVE);
 
     for i in 0..moves.len() {
         let m = moves.get(i);
 
+        //Captures can't repeat earlier positions, so they are kept out of the repetition table
         let mut copy = game.clone();
-        if !make_search_move(&mut copy, &m, &mut envir.repetition_table) {
+        if !make_evaluated_move(&mut copy, &m, envir.nnue.as_mut()) {
             continue;
         }
+
+        envir.move_stack[envir.ply as usize] = m;
         
         envir.ply += 1;
 
         let score = -quiescence(&mut copy, -beta, -temp_alpha, envir);
 
-        envir.ply -= 1;
+        if let Some(accumulators) = &mut envir.nnue {
+            accumulators.pop();
+        }
 
-        envir.repetition_table.move_back();
+        envir.ply -= 1;
 
         if score >= beta {
             return beta;
@@ -327,7 +570,7 @@ fn quiescence(game: &mut Game, alpha: i32, beta: i32, envir: &mut SearchEnv) ->
 }
 
 #[inline(always)]
-pub fn score_move(game: &Game, cmove: Move, envir: &mut SearchEnv) -> i32 {
+pub fn score_move(game: &Game, cmove: Move, envir: &mut SearchEnv, hash_move: Move) -> i32 {
     if envir.score_pv {
         if envir.pv_table[0][envir.ply as usize] == cmove {
             envir.score_pv = false;
@@ -335,29 +578,16 @@ pub fn score_move(game: &Game, cmove: Move, envir: &mut SearchEnv) -> i32 {
         }
     }
 
-    let to_sq = cmove.to_square();
+    if cmove == hash_move {
+        return 15000;
+    }
+
     //Captures
     if cmove.is_capture() {
-        let start;
-        let end;
-        let mut taken = 0;
-        if game.active_player == Color::White {
-            start = Piece::BlackPawn as usize;
-            end = Piece::BlackKing as usize;
-        }
-        else {
-            start = Piece::WhitePawn as usize;
-            end = Piece::WhiteKing as usize;
-        }
-
-        for bb in start..end {
-            if game.bitboards[bb].get_bit(to_sq) {
-                taken = bb;
-                break;
-            }
-        }
+        let taken = captured_piece(game, cmove);
 
-        MVV_LVA[cmove.piece() as usize][taken as usize] + 10000
+        //Capture history only breaks ties between captures of the same MVV-LVA class
+        MVV_LVA[cmove.piece() as usize][taken] + 10000 + envir.capture_history[cmove.piece() as usize][cmove.to_square() as usize][taken] / 1024
     }
 
     //Quiet moves
@@ -366,18 +596,70 @@ pub fn score_move(game: &Game, cmove: Move, envir: &mut SearchEnv) -> i32 {
             9000
         } else if envir.killer_moves[1][envir.ply as usize] == Some(cmove) {
             8000
+        } else if envir.ply > 0 && envir.counter_move(envir.move_stack[envir.ply as usize - 1]) == cmove {
+            7000
         }
         else {
-            envir.history_moves[cmove.piece() as usize][to_sq as usize]
+            //Scaled to stay below the countermove
+            envir.quiet_history(cmove) / 8
         }
     }
 }
 
+///The piece captured by the move
+#[inline(always)]
+pub fn captured_piece(game: &Game, cmove: Move) -> usize {
+    let start;
+    let end;
+    if game.active_player == Color::White {
+        start = Piece::BlackPawn as usize;
+        end = Piece::BlackKing as usize;
+    }
+    else {
+        start = Piece::WhitePawn as usize;
+        end = Piece::WhiteKing as usize;
+    }
+
+    if cmove.is_enpassant() {
+        return start;
+    }
+
+    let to_sq = cmove.to_square();
+    for bb in start..end {
+        if game.bitboards[bb].get_bit(to_sq) {
+            return bb;
+        }
+    }
+
+    start
+}
+
+///History update with gravity, keeping the entry within +-MAX_HISTORY
+#[inline(always)]
+fn apply_history_bonus(entry: &mut i32, bonus: i32) {
+    *entry += bonus - *entry * bonus.abs() / MAX_HISTORY;
+}
+
+#[inline(always)]
+fn history_bonus(depth: u8) -> i32 {
+    (depth as i32 * depth as i32).min(MAX_HISTORY_BONUS)
+}
+
 pub struct SearchEnv<'a> {
     pub nodes: u64,
     pub ply: u8,
     pub killer_moves: [[Option<Move>; MAX_PLY]; 2],
+    pub move_stack: [Move; MAX_PLY],
+    pub excluded_moves: [Move; MAX_PLY],
+    pub extensions: [u8; MAX_PLY],
+    ///[piece][to]
     pub history_moves: [[i32; 64]; 12],
+    ///[previous piece][previous to]
+    pub counter_moves: [[Move; 64]; 12],
+    ///[piece][to][captured]
+    pub capture_history: [[[i32; 12]; 64]; 12],
+    ///[previous piece * 64 + previous to][piece][to], shared by the one- and two-ply continuations
+    pub continuation_history: Box<[[[i32; 64]; 12]]>,
     pub pv_lengths: [usize; MAX_PLY],
     pub pv_table: [[Move; MAX_PLY]; MAX_PLY],
     pub follow_pv: bool,
@@ -386,18 +668,31 @@ pub struct SearchEnv<'a> {
     io_receiver: &'a IoWrapper,
     pub start_time: SystemTime,
     max_time: i64,
+    max_nodes: u64,
     transposition_table: &'a mut TranspositionTable,
     pub tt_hits: u32,
+    pub tb_hits: u64,
+    ///Moves left at the root by the tablebases
+    root_moves: Option<Vec<Move>>,
     pub repetition_table: &'a mut RepetitionTable,
+    evaluator: &'a Evaluator,
+    pub nnue: Option<AccumulatorStack<'a>>,
+    pub pawn_table: PawnTable,
 }
 
 impl <'a>SearchEnv<'a> {
-    pub fn new(max_time: i64, io_receiver: &'a IoWrapper, tt: &'a mut TranspositionTable, rep_table: &'a mut RepetitionTable) -> Self {
+    pub fn new(max_time: i64, io_receiver: &'a IoWrapper, tt: &'a mut TranspositionTable, rep_table: &'a mut RepetitionTable, evaluator: &'a Evaluator) -> Self {
         Self{
             nodes: 0,
             ply: 0,
             killer_moves: [[None; MAX_PLY]; 2],
+            move_stack: [NULL_MOVE; MAX_PLY],
+            excluded_moves: [NULL_MOVE; MAX_PLY],
+            extensions: [0; MAX_PLY],
             history_moves: [[0 as i32; 64]; 12],
+            counter_moves: [[NULL_MOVE; 64]; 12],
+            capture_history: [[[0; 12]; 64]; 12],
+            continuation_history: vec![[[0; 64]; 12]; 12 * 64].into_boxed_slice(),
             pv_lengths: [0; MAX_PLY],
             pv_table: [[NULL_MOVE; MAX_PLY]; MAX_PLY],
             follow_pv: false,
@@ -406,9 +701,32 @@ impl <'a>SearchEnv<'a> {
             io_receiver: io_receiver,
             start_time: SystemTime::now(),
             max_time: max_time,
+            max_nodes: u64::MAX,
             transposition_table: tt,
             tt_hits: 0,
-            repetition_table: rep_table
+            tb_hits: 0,
+            root_moves: None,
+            repetition_table: rep_table,
+            evaluator,
+            nnue: None,
+            pawn_table: PawnTable::new()
+        }
+    }
+
+    ///Evaluation of the current position, from the accumulators when using NNUE
+    #[inline(always)]
+    pub fn static_eval(&mut self, game: &Game) -> i32 {
+        match &self.nnue {
+            Some(accumulators) => accumulators.evaluate(game.active_player),
+            None => evaluate_with_pawn_table(game, &self.evaluator.params, &mut self.pawn_table)
+        }
+    }
+
+    ///Takes back the move made by make_search_move
+    pub fn undo_search_move(&mut self) {
+        self.repetition_table.pop();
+        if let Some(accumulators) = &mut self.nnue {
+            accumulators.pop();
         }
     }
 
@@ -424,10 +742,197 @@ impl <'a>SearchEnv<'a> {
         self.pv_lengths[ply] = self.pv_lengths[ply + 1];
     }
 
+    pub fn counter_move(&self, previous: Move) -> Move {
+        if previous == NULL_MOVE {
+            return NULL_MOVE;
+        }
+        self.counter_moves[previous.piece() as usize][previous.to_square() as usize]
+    }
+
+    ///The move made plies_back plies before the current node, if any
+    fn continuation_move(&self, plies_back: usize) -> Move {
+        if (self.ply as usize) < plies_back {
+            return NULL_MOVE;
+        }
+        self.move_stack[self.ply as usize - plies_back]
+    }
+
+    ///Butterfly history plus one- and two-ply continuation history
+    pub fn quiet_history(&self, cmove: Move) -> i32 {
+        let piece = cmove.piece() as usize;
+        let to_sq = cmove.to_square() as usize;
+
+        let mut score = self.history_moves[piece][to_sq];
+        for plies_back in 1..=2 {
+            let previous = self.continuation_move(plies_back);
+            if previous != NULL_MOVE {
+                score += self.continuation_history[previous.piece() as usize * 64 + previous.to_square() as usize][piece][to_sq];
+            }
+        }
+        score
+    }
+
+    ///Rewards the best quiet move and penalizes the quiet moves searched before it
+    pub fn update_quiet_history(&mut self, best: Move, quiets: &MoveList, depth: u8) {
+        let bonus = history_bonus(depth);
+
+        self.add_quiet_bonus(best, bonus);
+        for quiet in quiets.iter() {
+            if *quiet != best {
+                self.add_quiet_bonus(*quiet, -bonus);
+            }
+        }
+    }
+
+    fn add_quiet_bonus(&mut self, cmove: Move, bonus: i32) {
+        let piece = cmove.piece() as usize;
+        let to_sq = cmove.to_square() as usize;
+
+        apply_history_bonus(&mut self.history_moves[piece][to_sq], bonus);
+        for plies_back in 1..=2 {
+            let previous = self.continuation_move(plies_back);
+            if previous != NULL_MOVE {
+                apply_history_bonus(&mut self.continuation_history[previous.piece() as usize * 64 + previous.to_square() as usize][piece][to_sq], bonus);
+            }
+        }
+    }
+
+    ///Rewards the best capture and penalizes the captures searched before it
+    pub fn update_capture_history(&mut self, game: &Game, best: Move, captures: &MoveList, depth: u8) {
+        let bonus = history_bonus(depth);
+
+        apply_history_bonus(&mut self.capture_history[best.piece() as usize][best.to_square() as usize][captured_piece(game, best)], bonus);
+        for capture in captures.iter() {
+            if *capture != best {
+                apply_history_bonus(&mut self.capture_history[capture.piece() as usize][capture.to_square() as usize][captured_piece(game, *capture)], -bonus);
+            }
+        }
+    }
+
     pub fn poll_input(&mut self) {
-        if (self.max_time != -1 && self.start_time.elapsed().unwrap().as_millis() as i64 >= self.max_time) || self.io_receiver.try_read_line().is_some() {
+        if self.nodes >= self.max_nodes || (self.max_time != -1 && self.start_time.elapsed().unwrap().as_millis() as i64 >= self.max_time) || self.io_receiver.try_read_line().is_some() {
             self.stopping = true;
             return;
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod search_tests {
+    use super::*;
+
+    ///The tables a search environment borrows, owned by the test and dropped with it
+    struct Tables {
+        io_receiver: IoWrapper,
+        tt: TranspositionTable,
+        rep_table: RepetitionTable,
+        evaluator: Evaluator
+    }
+
+    impl Tables {
+        fn new() -> Self {
+            Self { io_receiver: IoWrapper::silent(), tt: TranspositionTable::new(), rep_table: RepetitionTable::new(), evaluator: Evaluator::new() }
+        }
+
+        fn env(&mut self) -> SearchEnv<'_> {
+            SearchEnv::new(-1, &self.io_receiver, &mut self.tt, &mut self.rep_table, &self.evaluator)
+        }
+    }
+
+    #[test]
+    pub fn history_bonus_is_bounded() {
+        let mut entry = 0;
+        for _ in 0..1000 {
+            apply_history_bonus(&mut entry, history_bonus(20));
+        }
+        assert!((1..=MAX_HISTORY).contains(&entry));
+
+        for _ in 0..1000 {
+            apply_history_bonus(&mut entry, -history_bonus(20));
+        }
+        assert!((-MAX_HISTORY..0).contains(&entry));
+    }
+
+    #[test]
+    pub fn cut_off_move_becomes_countermove() {
+        //Kh8 is answered by Qg7#, the only move that reaches a mate in one
+        let mut game = Game::new_from_fen("8/7k/5K2/8/8/8/8/6Q1 b - - 0 1").unwrap();
+        let previous = game.parse_move("h7h8".to_string()).unwrap();
+        assert!(make_move(&mut game, &previous));
+
+        let mut tables = Tables::new();
+        let mut envir = tables.env();
+        envir.move_stack[0] = previous;
+        envir.ply = 1;
+        let score = negamax(&mut game, 2, MATE_VALUE - 3, MATE_VALUE - 2, &mut envir);
+
+        assert_eq!(score, MATE_VALUE - 2);
+        assert_eq!(envir.counter_moves[previous.piece() as usize][previous.to_square() as usize].to_uci(), "g1g7");
+    }
+
+    #[test]
+    pub fn mate_distance_pruning_cuts_when_no_shorter_mate_is_possible() {
+        let mut game = Game::new_from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1").unwrap();
+        let mut tables = Tables::new();
+        let mut envir = tables.env();
+
+        //A mate in 3 plies has already been found, nothing at ply 5 can beat it
+        envir.ply = 5;
+        let alpha = MATE_VALUE - 3;
+        let score = negamax(&mut game, 6, alpha, alpha + 1, &mut envir);
+
+        assert_eq!(score, alpha);
+        assert_eq!(envir.nodes, 0);
+    }
+
+    #[test]
+    pub fn finds_shortest_mate() {
+        //Qg7# mates at once, while slower mates are also within reach
+        let mut game = Game::new_from_fen("7k/8/5K2/8/8/8/8/6Q1 w - - 0 1").unwrap();
+        let mut tables = Tables::new();
+        let mut envir = tables.env();
+
+        let score = negamax(&mut game, 5, -INFINITY, INFINITY, &mut envir);
+
+        assert_eq!(score, MATE_VALUE - 1);
+    }
+
+    #[test]
+    pub fn fifty_move_rule_scores_a_draw() {
+        //White is a queen up, but the clock has run out
+        let mut game = Game::new_from_fen("4k3/8/8/8/8/8/8/3QK3 w - - 100 80").unwrap();
+        let mut tables = Tables::new();
+        let mut envir = tables.env();
+
+        envir.ply = 1;
+        let score = negamax(&mut game, 4, -INFINITY, INFINITY, &mut envir);
+
+        assert_eq!(score, 0);
+    }
+
+    #[test]
+    pub fn mate_on_the_hundredth_half_move_is_not_a_draw() {
+        //Black is checkmated by the move that reached the fifty move limit
+        let mut game = Game::new_from_fen("R5k1/5ppp/8/8/8/8/8/6K1 b - - 100 80").unwrap();
+        let mut tables = Tables::new();
+        let mut envir = tables.env();
+
+        envir.ply = 1;
+        let score = negamax(&mut game, 4, -INFINITY, INFINITY, &mut envir);
+
+        assert_eq!(score, -MATE_VALUE + 1);
+    }
+
+    #[test]
+    pub fn insufficient_material_scores_a_draw() {
+        //White is a bishop up
+        let mut game = Game::new_from_fen("4k3/8/8/8/8/8/8/3BK3 w - - 0 1").unwrap();
+        let mut tables = Tables::new();
+        let mut envir = tables.env();
+
+        envir.ply = 1;
+        let score = negamax(&mut game, 4, -INFINITY, INFINITY, &mut envir);
+
+        assert_eq!(score, 0);
+    }
+}
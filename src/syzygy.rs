@@ -0,0 +1,1265 @@
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::path::Path;
+use std::sync::LazyLock;
+
+use memmap2::Mmap;
+
+use super::*;
+
+//Syzygy tablebase probing. The table format and the indexing follow the probing code of Stockfish,
+//which is based on Ronald de Man's original implementation. Squares in here are numbered like in
+//the tables, from a1 = 0 to h8 = 63, and pieces are coded 1-6 for white and 9-14 for black
+
+const WDL_MAGIC: [u8; 4] = [0x71, 0xE8, 0x23, 0x5D];
+const DTZ_MAGIC: [u8; 4] = [0xD7, 0x66, 0x0C, 0xA5];
+
+const TB_PIECES: usize = 7;
+
+//Table flags
+const FLAG_STM: u8 = 1;
+const FLAG_MAPPED: u8 = 2;
+const FLAG_WIN_PLIES: u8 = 4;
+const FLAG_LOSS_PLIES: u8 = 8;
+const FLAG_WIDE: u8 = 16;
+const FLAG_SINGLE_VALUE: u8 = 128;
+
+///Game theoretical value from the side to move, where cursed wins and blessed losses are draws by the fifty move rule
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Wdl {
+    Loss,
+    BlessedLoss,
+    Draw,
+    CursedWin,
+    Win
+}
+
+impl Wdl {
+    fn from_value(value: i32) -> Self {
+        match value {
+            -2 => Wdl::Loss,
+            -1 => Wdl::BlessedLoss,
+            0  => Wdl::Draw,
+            1  => Wdl::CursedWin,
+            _  => Wdl::Win
+        }
+    }
+
+    fn value(self) -> i32 {
+        self as i32 - 2
+    }
+
+    fn opposite(self) -> Self {
+        Self::from_value(-self.value())
+    }
+}
+
+struct Indices {
+    ///Squares below the a1-h8 diagonal to 0..27
+    map_b1h1h7: [usize; 64],
+    ///Squares in the a1-d1-d4 triangle to 0..9, with the diagonal last
+    map_a1d1d4: [usize; 64],
+    ///The 462 legal placements of two kings with the first in the a1-d1-d4 triangle
+    map_kk: [[usize; 64]; 10],
+    binomial: [[u64; 64]; 6],
+    ///Pawn squares a2-h7 to 0..47, where the highest value is the leading pawn
+    map_pawns: [usize; 64],
+    lead_pawn_idx: [[u64; 64]; 6],
+    lead_pawns_size: [[u64; 4]; 6],
+}
+
+static INDICES: LazyLock<Indices> = LazyLock::new(Indices::new);
+
+impl Indices {
+    fn new() -> Self {
+        let mut indices = Self {
+            map_b1h1h7: [0; 64],
+            map_a1d1d4: [0; 64],
+            map_kk: [[0; 64]; 10],
+            binomial: [[0; 64]; 6],
+            map_pawns: [0; 64],
+            lead_pawn_idx: [[0; 64]; 6],
+            lead_pawns_size: [[0; 4]; 6],
+        };
+
+        let mut code = 0;
+        for sq in 0..64 {
+            if off_a1h8(sq) < 0 {
+                indices.map_b1h1h7[sq] = code;
+                code += 1;
+            }
+        }
+
+        let mut code = 0;
+        let mut diagonal = Vec::new();
+        for sq in (0..4).flat_map(|rank| (0..4).map(move |file| rank * 8 + file)) {
+            if off_a1h8(sq) < 0 {
+                indices.map_a1d1d4[sq] = code;
+                code += 1;
+            } else if off_a1h8(sq) == 0 {
+                diagonal.push(sq);
+            }
+        }
+        for sq in diagonal {
+            indices.map_a1d1d4[sq] = code;
+            code += 1;
+        }
+
+        //Placements with both kings on the diagonal are coded last
+        let mut code = 0;
+        let mut both_on_diagonal = Vec::new();
+        for idx in 0..10 {
+            //b1 is the only square in the triangle coded as 0
+            for sq1 in (0..28).filter(|&sq| indices.map_a1d1d4[sq] == idx && (idx != 0 || sq == 1)) {
+                for sq2 in 0..64 {
+                    if distance(sq1, sq2) <= 1 || (off_a1h8(sq1) == 0 && off_a1h8(sq2) > 0) {
+                        continue;
+                    }
+
+                    if off_a1h8(sq1) == 0 && off_a1h8(sq2) == 0 {
+                        both_on_diagonal.push((idx, sq2));
+                    } else {
+                        indices.map_kk[idx][sq2] = code;
+                        code += 1;
+                    }
+                }
+            }
+        }
+        for (idx, sq2) in both_on_diagonal {
+            indices.map_kk[idx][sq2] = code;
+            code += 1;
+        }
+
+        indices.binomial[0][0] = 1;
+        for n in 1..64 {
+            for k in 0..6.min(n + 1) {
+                indices.binomial[k][n] = if k > 0 { indices.binomial[k - 1][n - 1] } else { 0 }
+                                       + if k < n { indices.binomial[k][n - 1] } else { 0 };
+            }
+        }
+
+        //The index restarts for every file of the leading pawn, as the tables are split by it
+        let mut available_squares = 48;
+        for lead_pawns in 1..6 {
+            for file in 0..4 {
+                let mut idx = 0;
+
+                for rank in 1..7 {
+                    let sq = rank * 8 + file;
+
+                    if lead_pawns == 1 {
+                        available_squares -= 2;
+                        indices.map_pawns[sq] = available_squares + 1;
+                        indices.map_pawns[sq ^ 7] = available_squares;
+                    }
+
+                    indices.lead_pawn_idx[lead_pawns][sq] = idx;
+                    idx += indices.binomial[lead_pawns - 1][indices.map_pawns[sq]];
+                }
+
+                indices.lead_pawns_size[lead_pawns][file] = idx;
+            }
+        }
+
+        indices
+    }
+}
+
+#[inline(always)]
+fn off_a1h8(sq: usize) -> i32 {
+    (sq >> 3) as i32 - (sq & 7) as i32
+}
+
+#[inline(always)]
+fn distance(sq1: usize, sq2: usize) -> usize {
+    ((sq1 >> 3).abs_diff(sq2 >> 3)).max((sq1 & 7).abs_diff(sq2 & 7))
+}
+
+///What is known about a table from its name, like "KRPvKR"
+#[derive(Debug, PartialEq)]
+struct TableInfo {
+    piece_count: usize,
+    has_pawns: bool,
+    has_unique_pieces: bool,
+    ///Pawns of the leading color and of the other color
+    pawn_count: [usize; 2],
+    ///Both sides have the same material
+    symmetric: bool,
+}
+
+impl TableInfo {
+    fn new(name: &str) -> Option<Self> {
+        let (white, black) = name.split_once('v')?;
+        if !white.starts_with('K') || !black.starts_with('K') {
+            return None;
+        }
+
+        let mut counts = [[0; 6]; 2];
+        for (side, pieces) in [white, black].iter().enumerate() {
+            for c in pieces.chars() {
+                let piece = char_to_piece(c)? as usize;
+                counts[side][piece] += 1;
+            }
+        }
+
+        //Both sides having pawns, the side with fewer is leading as it compresses better
+        let pawns = [counts[0][Piece::WhitePawn as usize], counts[1][Piece::WhitePawn as usize]];
+        let white_leads = pawns[1] == 0 || (pawns[0] > 0 && pawns[1] >= pawns[0]);
+
+        Some(Self {
+            piece_count: white.len() + black.len(),
+            has_pawns: pawns[0] + pawns[1] > 0,
+            has_unique_pieces: counts.iter().any(|side| side[..5].contains(&1)),
+            pawn_count: if white_leads { pawns } else { [pawns[1], pawns[0]] },
+            symmetric: white == black,
+        })
+    }
+}
+
+#[derive(Default)]
+struct PairsData {
+    flags: u8,
+    max_sym_len: u8,
+    min_sym_len: u8,
+    num_blocks: usize,
+    block_size: usize,
+    span: usize,
+    sparse_index_size: usize,
+    block_length_size: usize,
+
+    //Offsets into the table file
+    lowest_sym: usize,
+    btree: usize,
+    block_length: usize,
+    sparse_index: usize,
+    data: usize,
+
+    ///The lowest symbol of each length, padded to 64 bits
+    base64: Vec<u64>,
+    ///Number of values (minus one) a symbol expands to
+    symlen: Vec<u8>,
+    ///The order of the pieces defines the groups
+    pieces: [u8; TB_PIECES],
+    group_idx: [u64; TB_PIECES + 1],
+    group_len: [usize; TB_PIECES + 1],
+    ///Start of the value maps for wins, losses, cursed wins and blessed losses, only used in DTZ tables
+    map_idx: [usize; 4],
+}
+
+struct Table {
+    ///The file is mapped rather than read, so only the parts that are probed are loaded from disk
+    data: Mmap,
+    dtz: bool,
+    ///Offset of the DTZ value maps
+    map: usize,
+    ///Per side to move and file of the leading pawn
+    items: [[PairsData; 4]; 2],
+}
+
+impl Table {
+    fn read(path: &Path, info: &TableInfo, dtz: bool) -> Option<Self> {
+        //The tables are not expected to change while they are mapped
+        let data = unsafe { Mmap::map(&File::open(path).ok()?) }.ok()?;
+        let magic = if dtz { DTZ_MAGIC } else { WDL_MAGIC };
+        if data.len() < 5 || data[..4] != magic || ((data[4] & 2) != 0) != info.has_pawns {
+            return None;
+        }
+
+        let mut table = Self { data, dtz, map: 0, items: Default::default() };
+        if table.init(info)? > table.data.len() {
+            return None;
+        }
+
+        Some(table)
+    }
+
+    ///Reads the headers, returning the end of the table
+    fn init(&mut self, info: &TableInfo) -> Option<usize> {
+        let sides = if !self.dtz && !info.symmetric { 2 } else { 1 };
+        let files = if info.has_pawns { 4 } else { 1 };
+        let pawns_on_both_sides = info.has_pawns && info.pawn_count[1] > 0;
+
+        let mut pos = 5;
+        for file in 0..files {
+            let first = *self.data.get(pos)?;
+            let second = if pawns_on_both_sides { *self.data.get(pos + 1)? } else { 0xFF };
+            let order = [
+                [(first & 0xF) as usize, (second & 0xF) as usize],
+                [(first >> 4) as usize, (second >> 4) as usize]
+            ];
+            pos += 1 + pawns_on_both_sides as usize;
+
+            for k in 0..info.piece_count {
+                let byte = *self.data.get(pos)?;
+                for side in 0..sides {
+                    self.items[side][file].pieces[k] = if side == 1 { byte >> 4 } else { byte & 0xF };
+                }
+                pos += 1;
+            }
+
+            for (side, order) in order.iter().enumerate().take(sides) {
+                set_groups(&mut self.items[side][file], info, order, file)?;
+            }
+        }
+
+        pos += pos & 1;
+
+        for file in 0..files {
+            for side in 0..sides {
+                pos = set_sizes(&mut self.items[side][file], &self.data, pos)?;
+            }
+        }
+
+        if self.dtz {
+            pos = self.set_dtz_map(pos, files)?;
+        }
+
+        for file in 0..files {
+            for side in 0..sides {
+                let d = &mut self.items[side][file];
+                d.sparse_index = pos;
+                pos += d.sparse_index_size * 6;
+            }
+        }
+
+        for file in 0..files {
+            for side in 0..sides {
+                let d = &mut self.items[side][file];
+                d.block_length = pos;
+                pos += d.block_length_size * 2;
+            }
+        }
+
+        for file in 0..files {
+            for side in 0..sides {
+                let d = &mut self.items[side][file];
+                pos = (pos + 0x3F) & !0x3F;
+                d.data = pos;
+                pos = pos.checked_add(d.num_blocks.checked_mul(d.block_size)?)?;
+            }
+        }
+
+        Some(pos)
+    }
+
+    fn set_dtz_map(&mut self, mut pos: usize, files: usize) -> Option<usize> {
+        self.map = pos;
+
+        for file in 0..files {
+            let flags = self.items[0][file].flags;
+            if flags & FLAG_MAPPED == 0 {
+                continue;
+            }
+
+            if flags & FLAG_WIDE != 0 {
+                pos += pos & 1;
+                for i in 0..4 {
+                    self.items[0][file].map_idx[i] = (pos - self.map) / 2 + 1;
+                    pos += 2 * read_u16(&self.data, pos)? as usize + 2;
+                }
+            } else {
+                for i in 0..4 {
+                    self.items[0][file].map_idx[i] = pos - self.map + 1;
+                    pos += *self.data.get(pos)? as usize + 1;
+                }
+            }
+        }
+
+        Some(pos + (pos & 1))
+    }
+
+    fn get(&self, stm: usize, file: usize) -> &PairsData {
+        &self.items[if self.dtz { 0 } else { stm }][file]
+    }
+
+    ///Value of a position, or None if a DTZ table only holds the other side to move
+    fn probe(&self, info: &TableInfo, board: &TbBoard, black_stronger: bool, wdl: Wdl) -> Option<i32> {
+        let indices = &*INDICES;
+
+        let mut squares = [0usize; TB_PIECES];
+        let mut pieces = [0u8; TB_PIECES];
+        let mut size = 0;
+        let mut lead_pawns_count = 0;
+        let mut lead_pawns = 0u64;
+        let mut tb_file = 0;
+
+        //Symmetric tables only hold white to move, and all tables have white as the stronger side,
+        //so the colors are switched and the board flipped when that isn't the case
+        let flip = (info.symmetric && board.stm == 1) || black_stronger;
+        let flip_color = if flip { 8 } else { 0 };
+        let flip_squares = if flip { 56 } else { 0 };
+        let stm = flip as usize ^ board.stm;
+
+        //Pawns start the piece sequence, and the leading pawn is the one closest to the edge and with the lowest rank
+        if info.has_pawns {
+            let pawn = self.get(0, 0).pieces[0] ^ flip_color;
+            for sq in (0..64).filter(|&sq| board.pieces[sq] == pawn) {
+                lead_pawns |= 1 << sq;
+                squares[size] = sq ^ flip_squares;
+                size += 1;
+            }
+            lead_pawns_count = size;
+
+            let mut lead = 0;
+            for i in 1..lead_pawns_count {
+                if indices.map_pawns[squares[i]] > indices.map_pawns[squares[lead]] {
+                    lead = i;
+                }
+            }
+            squares.swap(0, lead);
+
+            let file = squares[0] & 7;
+            tb_file = file.min(7 - file);
+        }
+
+        if self.dtz {
+            let flags = self.get(stm, tb_file).flags;
+            if (flags & FLAG_STM) as usize != stm && (!info.symmetric || info.has_pawns) {
+                return None;
+            }
+        }
+
+        for sq in (0..64).filter(|&sq| board.pieces[sq] != 0 && lead_pawns & (1 << sq) == 0) {
+            squares[size] = sq ^ flip_squares;
+            pieces[size] = board.pieces[sq] ^ flip_color;
+            size += 1;
+        }
+
+        let d = self.get(stm, tb_file);
+
+        //Order the pieces like the table does
+        for i in lead_pawns_count..size - 1 {
+            for j in i + 1..size {
+                if d.pieces[i] == pieces[j] {
+                    pieces.swap(i, j);
+                    squares.swap(i, j);
+                    break;
+                }
+            }
+        }
+
+        //The leading piece is mirrored to the a-d files
+        if squares[0] & 7 > 3 {
+            for sq in squares.iter_mut().take(size) {
+                *sq ^= 7;
+            }
+        }
+
+        let mut idx;
+        if info.has_pawns {
+            idx = indices.lead_pawn_idx[lead_pawns_count][squares[0]];
+
+            squares[1..lead_pawns_count].sort_by_key(|&sq| indices.map_pawns[sq]);
+            for (i, &sq) in squares.iter().enumerate().take(lead_pawns_count).skip(1) {
+                idx += indices.binomial[i][indices.map_pawns[sq]];
+            }
+        } else {
+            //Without pawns the leading piece is also mirrored to the lower half and below the a1-h8 diagonal
+            if squares[0] >> 3 > 3 {
+                for sq in squares.iter_mut().take(size) {
+                    *sq ^= 56;
+                }
+            }
+
+            if let Some(i) = squares[..d.group_len[0]].iter().position(|&sq| off_a1h8(sq) != 0) {
+                if off_a1h8(squares[i]) > 0 {
+                    for sq in squares.iter_mut().take(size).skip(i) {
+                        *sq = ((*sq >> 3) | (*sq << 3)) & 63;
+                    }
+                }
+            }
+
+            idx = if info.has_unique_pieces {
+                encode_unique_pieces(indices, &squares)
+            } else {
+                indices.map_kk[indices.map_a1d1d4[squares[0]]][squares[1]] as u64
+            };
+        }
+
+        idx *= d.group_idx[0];
+
+        //The remaining groups are encoded by their squares in ascending order, skipping those taken by earlier groups
+        let mut group_start = d.group_len[0];
+        let mut remaining_pawns = info.has_pawns && info.pawn_count[1] > 0;
+        let mut next = 1;
+        while d.group_len[next] != 0 {
+            let group_len = d.group_len[next];
+            squares[group_start..group_start + group_len].sort();
+
+            let mut n = 0;
+            for i in 0..group_len {
+                let sq = squares[group_start + i];
+                let adjust = squares[..group_start].iter().filter(|&&s| sq > s).count();
+                n += indices.binomial[i + 1][sq - adjust - if remaining_pawns { 8 } else { 0 }];
+            }
+
+            remaining_pawns = false;
+            idx += n * d.group_idx[next];
+            group_start += group_len;
+            next += 1;
+        }
+
+        let value = decompress_pairs(d, &self.data, idx);
+
+        Some(if self.dtz { self.map_dtz(tb_file, value, wdl) } else { value - 2 })
+    }
+
+    ///Converts a stored DTZ value to plies
+    fn map_dtz(&self, file: usize, mut value: i32, wdl: Wdl) -> i32 {
+        const WDL_MAP: [usize; 5] = [1, 3, 0, 2, 0];
+
+        let d = self.get(0, file);
+        if d.flags & FLAG_MAPPED != 0 {
+            let idx = d.map_idx[WDL_MAP[(wdl.value() + 2) as usize]] + value as usize;
+            value = if d.flags & FLAG_WIDE != 0 {
+                read_u16(&self.data, self.map + 2 * idx).unwrap_or(0) as i32
+            } else {
+                self.data.get(self.map + idx).copied().unwrap_or(0) as i32
+            };
+        }
+
+        if (wdl == Wdl::Win && d.flags & FLAG_WIN_PLIES == 0) ||
+           (wdl == Wdl::Loss && d.flags & FLAG_LOSS_PLIES == 0) ||
+            wdl == Wdl::CursedWin ||
+            wdl == Wdl::BlessedLoss {
+            value *= 2;
+        }
+
+        value + 1
+    }
+}
+
+///Encodes the first three pieces together, when at least three pieces are unique
+fn encode_unique_pieces(indices: &Indices, squares: &[usize]) -> u64 {
+    let rank = |sq: usize| sq >> 3;
+    let adjust1 = (squares[1] > squares[0]) as usize;
+    let adjust2 = (squares[2] > squares[0]) as usize + (squares[2] > squares[1]) as usize;
+
+    let idx = if off_a1h8(squares[0]) != 0 {
+        (indices.map_a1d1d4[squares[0]] * 63 + (squares[1] - adjust1)) * 62 + squares[2] - adjust2
+    } else if off_a1h8(squares[1]) != 0 {
+        (6 * 63 + rank(squares[0]) * 28 + indices.map_b1h1h7[squares[1]]) * 62 + squares[2] - adjust2
+    } else if off_a1h8(squares[2]) != 0 {
+        6 * 63 * 62 + 4 * 28 * 62
+            + rank(squares[0]) * 7 * 28
+            + (rank(squares[1]) - adjust1) * 28
+            + indices.map_b1h1h7[squares[2]]
+    } else {
+        6 * 63 * 62 + 4 * 28 * 62 + 4 * 7 * 28
+            + rank(squares[0]) * 7 * 6
+            + (rank(squares[1]) - adjust1) * 6
+            + (rank(squares[2]) - adjust2)
+    };
+
+    idx as u64
+}
+
+fn set_groups(d: &mut PairsData, info: &TableInfo, order: &[usize; 2], file: usize) -> Option<()> {
+    let indices = &*INDICES;
+
+    //Pieces are grouped when equal, and the leading group has 3 unique pieces or the 2 kings when there are no pawns
+    let mut n = 0;
+    let mut first_len: i32 = if info.has_pawns { 0 } else if info.has_unique_pieces { 3 } else { 2 };
+    d.group_len[0] = 1;
+    for i in 1..info.piece_count {
+        first_len -= 1;
+        if first_len > 0 || d.pieces[i] == d.pieces[i - 1] {
+            d.group_len[n] += 1;
+        } else {
+            n += 1;
+            d.group_len[n] = 1;
+        }
+    }
+    n += 1;
+    d.group_len[n] = 0;
+
+    //More equal pieces than any table has means a corrupt header
+    if d.group_len[..n].iter().any(|&len| len > 5) {
+        return None;
+    }
+
+    //The groups are encoded in a per table order, with the leading group at order[0] and the remaining pawns at order[1]
+    let pawns_on_both_sides = info.has_pawns && info.pawn_count[1] > 0;
+    let mut next = if pawns_on_both_sides { 2 } else { 1 };
+    let mut free_squares = 64 - d.group_len[0] - if pawns_on_both_sides { d.group_len[1] } else { 0 };
+    let mut idx = 1;
+
+    let mut k = 0;
+    while next < n || k == order[0] || k == order[1] {
+        if k == order[0] {
+            d.group_idx[0] = idx;
+            idx *= if info.has_pawns {
+                indices.lead_pawns_size[d.group_len[0]][file]
+            } else if info.has_unique_pieces {
+                31332
+            } else {
+                462
+            };
+        } else if k == order[1] {
+            d.group_idx[1] = idx;
+            idx *= indices.binomial[d.group_len[1]][48 - d.group_len[0]];
+        } else {
+            d.group_idx[next] = idx;
+            idx *= indices.binomial[d.group_len[next]][free_squares];
+            free_squares -= d.group_len[next];
+            next += 1;
+        }
+        k += 1;
+    }
+
+    d.group_idx[n] = idx;
+    Some(())
+}
+
+fn set_sizes(d: &mut PairsData, data: &[u8], mut pos: usize) -> Option<usize> {
+    d.flags = *data.get(pos)?;
+    pos += 1;
+
+    //All positions have the same value
+    if d.flags & FLAG_SINGLE_VALUE != 0 {
+        d.min_sym_len = *data.get(pos)?;
+        return Some(pos + 1);
+    }
+
+    let table_size = d.group_idx[d.group_len.iter().position(|&len| len == 0)?];
+
+    d.block_size = 1usize.checked_shl(*data.get(pos)? as u32)?;
+    d.span = 1usize.checked_shl(*data.get(pos + 1)? as u32)?;
+    d.sparse_index_size = (table_size as usize).div_ceil(d.span);
+    let padding = *data.get(pos + 2)? as usize;
+    d.num_blocks = read_u32(data, pos + 3)? as usize;
+    d.block_length_size = d.num_blocks + padding;
+    d.max_sym_len = *data.get(pos + 7)?;
+    d.min_sym_len = *data.get(pos + 8)?;
+    if d.min_sym_len == 0 || d.max_sym_len > 32 {
+        return None;
+    }
+    pos += 9;
+    d.lowest_sym = pos;
+
+    //Longer symbols of the canonical Huffman code have lower values, so the padded lowest symbols decrease with the length
+    let lengths = (d.max_sym_len as usize + 1).checked_sub(d.min_sym_len as usize)?;
+    d.base64 = vec![0; lengths];
+    for i in (0..lengths.saturating_sub(1)).rev() {
+        let lowest = read_u16(data, d.lowest_sym + 2 * i)? as u64;
+        let next_lowest = read_u16(data, d.lowest_sym + 2 * (i + 1))? as u64;
+        d.base64[i] = (d.base64[i + 1] + lowest).wrapping_sub(next_lowest) / 2;
+    }
+    for (i, base) in d.base64.iter_mut().enumerate() {
+        *base = base.checked_shl((64 - i - d.min_sym_len as usize) as u32).unwrap_or(0);
+    }
+
+    pos += lengths * 2;
+    let symbols = read_u16(data, pos)? as usize;
+    pos += 2;
+    d.btree = pos;
+
+    //Symbols are pairs of smaller symbols, see "Recursive Pairing"
+    d.symlen = vec![0; symbols];
+    let mut visited = vec![false; symbols];
+    for sym in 0..symbols {
+        if !visited[sym] {
+            d.symlen[sym] = set_symlen(d, data, sym, &mut visited)?;
+        }
+    }
+
+    Some(pos + symbols * 3 + (symbols & 1))
+}
+
+fn set_symlen(d: &mut PairsData, data: &[u8], sym: usize, visited: &mut [bool]) -> Option<u8> {
+    visited[sym] = true;
+
+    let (left, right) = btree_children(d, data, sym)?;
+    if right == 0xFFF {
+        return Some(0);
+    }
+
+    for child in [left, right] {
+        if !*visited.get(child)? {
+            d.symlen[child] = set_symlen(d, data, child, visited)?;
+        }
+    }
+
+    Some(d.symlen[left].wrapping_add(d.symlen[right]).wrapping_add(1))
+}
+
+fn btree_children(d: &PairsData, data: &[u8], sym: usize) -> Option<(usize, usize)> {
+    let lr = data.get(d.btree + 3 * sym..d.btree + 3 * sym + 3)?;
+    Some(((((lr[1] & 0xF) as usize) << 8) | lr[0] as usize, ((lr[2] as usize) << 4) | (lr[1] >> 4) as usize))
+}
+
+///Finds the value at an index in the compressed table
+fn decompress_pairs(d: &PairsData, data: &[u8], idx: u64) -> i32 {
+    if d.flags & FLAG_SINGLE_VALUE != 0 {
+        return d.min_sym_len as i32;
+    }
+
+    //The sparse index points to the block and offset of every span'th value, counted from the middle of the span
+    let idx = idx as usize;
+    let k = idx / d.span;
+    let mut block = read_u32(data, d.sparse_index + 6 * k).unwrap_or(0) as usize;
+    let mut offset = read_u16(data, d.sparse_index + 6 * k + 4).unwrap_or(0) as i64;
+    offset += (idx % d.span) as i64 - (d.span / 2) as i64;
+
+    let block_length = |block: usize| read_u16(data, d.block_length + 2 * block).unwrap_or(0) as i64;
+    while offset < 0 {
+        let Some(previous) = block.checked_sub(1) else { return 0 };
+        block = previous;
+        offset += block_length(block) + 1;
+    }
+    while offset > block_length(block) {
+        offset -= block_length(block) + 1;
+        block += 1;
+    }
+
+    //Walk the Huffman symbols of the block until the one holding the offset
+    let mut ptr = d.data + block * d.block_size;
+    let mut buf64 = read_u64_be(data, ptr);
+    let mut buf64_size = 64;
+    ptr += 8;
+
+    let mut sym;
+    loop {
+        let mut len = 0;
+        while len + 1 < d.base64.len() && buf64 < d.base64[len] {
+            len += 1;
+        }
+
+        sym = ((buf64.wrapping_sub(d.base64[len])) >> (64 - len - d.min_sym_len as usize)) as usize;
+        sym += read_u16(data, d.lowest_sym + 2 * len).unwrap_or(0) as usize;
+
+        let Some(&symlen) = d.symlen.get(sym) else { return 0 };
+        if offset < symlen as i64 + 1 {
+            break;
+        }
+
+        offset -= symlen as i64 + 1;
+        len += d.min_sym_len as usize;
+        buf64 <<= len;
+        buf64_size -= len;
+
+        if buf64_size <= 32 {
+            buf64_size += 32;
+            buf64 |= (read_u32_be(data, ptr) as u64) << (64 - buf64_size);
+            ptr += 4;
+        }
+    }
+
+    //Expand the symbol until the value at the offset
+    while d.symlen[sym] != 0 {
+        let Some((left, right)) = btree_children(d, data, sym) else { return 0 };
+        let (Some(&left_len), Some(&right_len)) = (d.symlen.get(left), d.symlen.get(right)) else { return 0 };
+
+        //Children are shorter than their parent, unless the tree is corrupt
+        let next = if offset < left_len as i64 + 1 {
+            left
+        } else {
+            offset -= left_len as i64 + 1;
+            right
+        };
+        if left_len.max(right_len) >= d.symlen[sym] {
+            return 0;
+        }
+        sym = next;
+    }
+
+    btree_children(d, data, sym).map_or(0, |(left, _)| left as i32)
+}
+
+fn read_u16(data: &[u8], pos: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(data.get(pos..pos + 2)?.try_into().ok()?))
+}
+
+fn read_u32(data: &[u8], pos: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(data.get(pos..pos + 4)?.try_into().ok()?))
+}
+
+//Compressed data past the end of the file reads as zeros
+fn read_u32_be(data: &[u8], pos: usize) -> u32 {
+    let mut bytes = [0; 4];
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte = data.get(pos + i).copied().unwrap_or(0);
+    }
+    u32::from_be_bytes(bytes)
+}
+
+fn read_u64_be(data: &[u8], pos: usize) -> u64 {
+    ((read_u32_be(data, pos) as u64) << 32) | read_u32_be(data, pos + 4) as u64
+}
+
+///The board as seen by the tables
+struct TbBoard {
+    pieces: [u8; 64],
+    stm: usize,
+}
+
+impl TbBoard {
+    fn new(game: &Game) -> Self {
+        let mut pieces = [0; 64];
+        for (piece, bitboard) in game.bitboards.iter().enumerate() {
+            let mut bitboard = *bitboard;
+            while !bitboard.is_empty() {
+                let sq = bitboard.extract_bit() as usize;
+                pieces[sq ^ 56] = (piece % 6 + 1 + if piece >= 6 { 8 } else { 0 }) as u8;
+            }
+        }
+
+        Self { pieces, stm: if game.active_player == Color::White { 0 } else { 1 } }
+    }
+}
+
+struct TableEntry {
+    info: TableInfo,
+    wdl: Table,
+    dtz: Option<Table>,
+}
+
+impl TableEntry {
+    fn table(&self, dtz: bool) -> Option<&Table> {
+        if dtz { self.dtz.as_ref() } else { Some(&self.wdl) }
+    }
+}
+
+///Syzygy WDL and DTZ tables found in a set of directories
+pub struct Tablebases {
+    tables: HashMap<String, TableEntry>,
+    ///Most pieces of any table
+    pub max_pieces: u32,
+}
+
+impl Tablebases {
+    ///Finds the tables in a list of directories, separated like the PATH environment variable, and reads
+    ///their headers. Tables that can't be read are left out, as if they were missing
+    pub fn load(paths: &str) -> Result<Self, String> {
+        let mut wdl_paths = HashMap::new();
+        let mut dtz_paths = HashMap::new();
+
+        for dir in std::env::split_paths(paths) {
+            let entries = fs::read_dir(&dir).map_err(|e| format!("Could not read '{}': {}", dir.display(), e))?;
+
+            for path in entries.filter_map(|entry| entry.ok()).map(|entry| entry.path()) {
+                let Some(name) = path.file_stem().and_then(|stem| stem.to_str()).map(str::to_string) else { continue };
+
+                match path.extension().and_then(|ext| ext.to_str()) {
+                    Some("rtbw") => { wdl_paths.insert(name, path); },
+                    Some("rtbz") => { dtz_paths.insert(name, path); },
+                    _ => {}
+                }
+            }
+        }
+
+        let mut tables = HashMap::new();
+        let mut max_pieces = 0;
+        for (name, wdl_path) in wdl_paths {
+            let Some(info) = TableInfo::new(&name) else { continue };
+            if info.piece_count > TB_PIECES {
+                continue;
+            }
+
+            let Some(wdl) = Table::read(&wdl_path, &info, false) else { continue };
+            let dtz = dtz_paths.remove(&name).and_then(|path| Table::read(&path, &info, true));
+
+            max_pieces = max_pieces.max(info.piece_count as u32);
+            tables.insert(name, TableEntry { info, wdl, dtz });
+        }
+
+        Ok(Self { tables, max_pieces })
+    }
+
+    pub fn table_count(&self) -> usize {
+        self.tables.len()
+    }
+
+    ///Whether the position is covered by the tables. They know nothing of castling
+    pub fn covers(&self, game: &Game) -> bool {
+        game.castling_ability == 0 && game.all_occupancies.to_u64().count_ones() <= self.max_pieces
+    }
+
+    ///WDL value of a position, ignoring the fifty move counter
+    pub fn probe_wdl(&self, game: &Game) -> Option<Wdl> {
+        if !self.covers(game) {
+            return None;
+        }
+
+        self.search(&mut game.clone(), false).map(|(wdl, _)| wdl)
+    }
+
+    ///Plies until a capture or pawn move keeping the result, positive when winning.
+    ///Values above 100 are wins or losses spoiled by the fifty move rule
+    pub fn probe_dtz(&self, game: &Game) -> Option<i32> {
+        if !self.covers(game) {
+            return None;
+        }
+
+        self.dtz(&mut game.clone())
+    }
+
+    ///The legal moves keeping the tablebase result, and the quickest win or the slowest loss
+    ///by DTZ when those tables are found. None when the position is not covered
+    pub fn root_moves(&self, game: &Game) -> Option<Vec<Move>> {
+        if !self.covers(game) {
+            return None;
+        }
+
+        let mut game = *game;
+        let moves = legal_moves(&mut game);
+
+        //Rank the moves by DTZ from the side to move, falling back to WDL without DTZ tables
+        let ranks = match self.dtz(&mut game) {
+            Some(dtz) => moves.iter().map(|m| self.dtz_after_move(&game, m, dtz)).collect::<Option<Vec<_>>>(),
+            None => None
+        };
+        let ranks = match ranks {
+            Some(ranks) => ranks,
+            None => moves.iter().map(|m| {
+                let mut copy = game;
+                make_move(&mut copy, m);
+                self.search(&mut copy, false).map(|(wdl, _)| dtz_before_zeroing(wdl.opposite()))
+            }).collect::<Option<Vec<_>>>()?
+        };
+
+        //Wins are ranked positive, so the best is the smallest positive rank, or the smallest rank without wins or draws
+        let best = if ranks.iter().any(|&rank| rank > 0) {
+            *ranks.iter().filter(|&&rank| rank > 0).min()?
+        } else if ranks.contains(&0) {
+            0
+        } else {
+            *ranks.iter().min()?
+        };
+
+        let root_moves: Vec<Move> = moves.iter().zip(ranks).filter(|(_, rank)| *rank == best).map(|(m, _)| *m).collect();
+        if root_moves.is_empty() { None } else { Some(root_moves) }
+    }
+
+    ///DTZ from the moving side after a move
+    fn dtz_after_move(&self, game: &Game, cmove: &Move, dtz: i32) -> Option<i32> {
+        let mut copy = *game;
+        make_move(&mut copy, cmove);
+
+        if dtz > 0 && copy.is_in_check(copy.active_player) && legal_moves(&mut copy).is_empty() {
+            return Some(1);
+        }
+
+        if copy.half_moves != 0 {
+            let dtz = -self.dtz(&mut copy)?;
+            Some(dtz + dtz.signum())
+        } else {
+            let wdl = self.search(&mut copy, false)?.0.opposite();
+            Some(dtz_before_zeroing(wdl))
+        }
+    }
+
+    fn dtz(&self, game: &mut Game) -> Option<i32> {
+        let (wdl, zeroing_best) = self.search(game, true)?;
+
+        //DTZ tables don't hold draws, and hold nonsense when the best move is a capture or pawn move
+        if wdl == Wdl::Draw {
+            return Some(0);
+        }
+        if zeroing_best {
+            return Some(dtz_before_zeroing(wdl));
+        }
+
+        if let Some(dtz) = self.probe_table(game, true, wdl)? {
+            let spoiled = wdl == Wdl::CursedWin || wdl == Wdl::BlessedLoss;
+            return Some((dtz + if spoiled { 100 } else { 0 }) * wdl.value().signum());
+        }
+
+        //The table only holds the other side to move, so find the move with the best DTZ
+        let mut min_dtz = i32::MAX;
+        for m in legal_moves(game) {
+            let zeroing = m.is_capture() || m.piece() == Piece::WhitePawn as u8 || m.piece() == Piece::BlackPawn as u8;
+
+            let mut copy = *game;
+            make_move(&mut copy, &m);
+
+            //Zeroing moves count from before the move
+            let mut dtz = if zeroing {
+                -dtz_before_zeroing(self.search(&mut copy, false)?.0)
+            } else {
+                -self.dtz(&mut copy)?
+            };
+
+            if dtz == 1 && copy.is_in_check(copy.active_player) && legal_moves(&mut copy).is_empty() {
+                min_dtz = 1;
+            }
+
+            if !zeroing {
+                dtz += dtz.signum();
+            }
+
+            if dtz < min_dtz && dtz.signum() == wdl.value().signum() {
+                min_dtz = dtz;
+            }
+        }
+
+        //No legal moves means mate
+        Some(if min_dtz == i32::MAX { -1 } else { min_dtz })
+    }
+
+    ///WDL value, resolving captures first as the tables don't know en passant, and whether a capture
+    ///(or pawn move with check_zeroing) is the best move
+    fn search(&self, game: &mut Game, check_zeroing: bool) -> Option<(Wdl, bool)> {
+        let moves = legal_moves(game);
+        let mut best = Wdl::Loss;
+        let mut move_count = 0;
+
+        for m in moves.iter() {
+            let pawn_move = m.piece() == Piece::WhitePawn as u8 || m.piece() == Piece::BlackPawn as u8;
+            if !m.is_capture() && (!check_zeroing || !pawn_move) {
+                continue;
+            }
+
+            move_count += 1;
+
+            let mut copy = *game;
+            make_move(&mut copy, m);
+            let value = self.search(&mut copy, false)?.0.opposite();
+
+            if value > best {
+                best = value;
+
+                if value == Wdl::Win {
+                    return Some((value, true));
+                }
+            }
+        }
+
+        let no_more_moves = move_count > 0 && move_count == moves.len();
+        let value = if no_more_moves {
+            best
+        } else {
+            Wdl::from_value(self.probe_table(game, false, Wdl::Draw)?.unwrap_or(0))
+        };
+
+        if best >= value {
+            return Some((best, best > Wdl::Draw || no_more_moves));
+        }
+
+        Some((value, false))
+    }
+
+    ///None when the table is missing, Some(None) when a DTZ table holds the other side to move
+    fn probe_table(&self, game: &Game, dtz: bool, wdl: Wdl) -> Option<Option<i32>> {
+        if game.all_occupancies.to_u64().count_ones() == 2 {
+            return Some(Some(0));
+        }
+
+        let white = material_name(game, Color::White);
+        let black = material_name(game, Color::Black);
+        let (entry, black_stronger) = match self.tables.get(&format!("{}v{}", white, black)) {
+            Some(entry) => (entry, false),
+            None => (self.tables.get(&format!("{}v{}", black, white))?, true)
+        };
+
+        let table = entry.table(dtz)?;
+        Some(table.probe(&entry.info, &TbBoard::new(game), black_stronger, wdl))
+    }
+}
+
+fn dtz_before_zeroing(wdl: Wdl) -> i32 {
+    match wdl {
+        Wdl::Win => 1,
+        Wdl::CursedWin => 101,
+        Wdl::BlessedLoss => -101,
+        Wdl::Loss => -1,
+        Wdl::Draw => 0
+    }
+}
+
+fn legal_moves(game: &mut Game) -> Vec<Move> {
+    let moves = generate_moves(game, MoveTypes::All);
+    moves.iter().filter(|m| is_legal(game, m)).copied().collect()
+}
+
+///Pieces of one side in the order used by table names, like "KRP"
+fn material_name(game: &Game, color: Color) -> String {
+    let offset = if color == Color::White { 0 } else { 6 };
+    let mut name = String::from("K");
+    for piece in [Piece::WhiteQueen, Piece::WhiteRook, Piece::WhiteBishop, Piece::WhiteKnight, Piece::WhitePawn] {
+        let count = game.bitboards[piece as usize + offset].to_u64().count_ones();
+        name.push_str(&PIECE_STRINGS[piece as usize].repeat(count as usize));
+    }
+    name
+}
+
+#[cfg(test)]
+mod syzygy_tests {
+    use crate::*;
+    use super::{TableInfo, INDICES};
+
+    //The 3 piece tables, made by test-data/syzygy/generate.py
+    fn test_tablebases() -> Tablebases {
+        Tablebases::load(concat!(env!("CARGO_MANIFEST_DIR"), "/test-data/syzygy")).unwrap()
+    }
+
+    #[test]
+    pub fn index_tables_match_the_table_format() {
+        let indices = &*INDICES;
+
+        let mut king_codes: Vec<usize> = (0..10).flat_map(|idx| (0..64).map(move |sq| (idx, sq)))
+            .filter(|&(idx, sq)| indices.map_kk[idx][sq] != 0 || (idx == 0 && sq == 0))
+            .map(|(idx, sq)| indices.map_kk[idx][sq])
+            .collect();
+        king_codes.sort();
+        king_codes.dedup();
+        assert_eq!(462, king_codes.len());
+        assert_eq!(461, king_codes[461]);
+
+        assert_eq!(27, *indices.map_b1h1h7.iter().max().unwrap());
+        assert_eq!(9, *indices.map_a1d1d4.iter().max().unwrap());
+        assert_eq!(47, indices.map_pawns[8]);
+        assert_eq!(46, indices.map_pawns[15]);
+        assert_eq!(10, indices.binomial[2][5]);
+        assert_eq!(1, indices.binomial[0][63]);
+    }
+
+    #[test]
+    pub fn table_names_describe_the_material() {
+        let info = TableInfo::new("KRPvKR").unwrap();
+        assert_eq!(5, info.piece_count);
+        assert!(info.has_pawns && info.has_unique_pieces && !info.symmetric);
+        assert_eq!([1, 0], info.pawn_count);
+
+        let info = TableInfo::new("KPPvKP").unwrap();
+        assert_eq!([1, 2], info.pawn_count);
+
+        let info = TableInfo::new("KRRvKR").unwrap();
+        assert!(!info.has_pawns && info.has_unique_pieces);
+
+        let info = TableInfo::new("KNNvKNN").unwrap();
+        assert!(!info.has_unique_pieces && info.symmetric);
+
+        assert_eq!(None, TableInfo::new("KQKR"));
+        assert_eq!(None, TableInfo::new("KXvK"));
+    }
+
+    #[test]
+    pub fn positions_without_tables_are_not_probed() {
+        let dir = std::env::temp_dir().join("jence_empty_syzygy");
+        std::fs::create_dir_all(&dir).unwrap();
+        let tablebases = Tablebases::load(dir.to_str().unwrap()).unwrap();
+        assert_eq!(0, tablebases.table_count());
+
+        let game = Game::new_from_fen("4k3/8/8/8/8/8/8/4K2Q w - - 0 1").unwrap();
+        assert_eq!(None, tablebases.probe_wdl(&game));
+        assert!(tablebases.root_moves(&game).is_none());
+
+        assert!(Tablebases::load("/does/not/exist").is_err());
+    }
+
+    #[test]
+    pub fn truncated_tables_are_not_probed() {
+        let dir = std::env::temp_dir().join("jence_truncated_syzygy");
+        std::fs::create_dir_all(&dir).unwrap();
+        let table = std::fs::read(concat!(env!("CARGO_MANIFEST_DIR"), "/test-data/syzygy/KQvK.rtbw")).unwrap();
+        let game = Game::new_from_fen("4k3/8/8/8/8/8/8/4K2Q w - - 0 1").unwrap();
+
+        for len in [6, 12, 40, table.len() / 2, table.len() - 1] {
+            std::fs::write(dir.join("KQvK.rtbw"), &table[..len]).unwrap();
+            let tablebases = Tablebases::load(dir.to_str().unwrap()).unwrap();
+            assert_eq!(None, tablebases.probe_wdl(&game), "{} bytes", len);
+        }
+    }
+
+    #[test]
+    pub fn wdl_matches_known_positions() {
+        let tablebases = test_tablebases();
+
+        let game = Game::new_from_fen("4k3/8/8/8/8/8/8/4K2Q w - - 0 1").unwrap();
+        assert_eq!(Some(Wdl::Win), tablebases.probe_wdl(&game));
+        let game = Game::new_from_fen("4k3/8/8/8/8/8/8/4K2Q b - - 0 1").unwrap();
+        assert_eq!(Some(Wdl::Loss), tablebases.probe_wdl(&game));
+
+        //The rook is lost when black moves first
+        let game = Game::new_from_fen("8/8/8/8/8/6R1/5k2/K7 w - - 0 1").unwrap();
+        assert_eq!(Some(Wdl::Win), tablebases.probe_wdl(&game));
+        let game = Game::new_from_fen("8/8/8/8/8/6R1/5k2/K7 b - - 0 1").unwrap();
+        assert_eq!(Some(Wdl::Draw), tablebases.probe_wdl(&game));
+
+        //Black having the stronger side flips the board
+        let game = Game::new_from_fen("4k2q/8/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        assert_eq!(Some(Wdl::Loss), tablebases.probe_wdl(&game));
+    }
+
+    ///The two kings and a white piece, or None when the squares overlap or the side not to move is in check
+    fn three_pieces(white_to_move: bool, white_king: u8, black_king: u8, piece: Piece, square: u8) -> Option<Game> {
+        if white_king == black_king || white_king == square || black_king == square {
+            return None;
+        }
+
+        let mut game = Game::new_from_fen("4k3/8/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        game.bitboards = [Bitboard::new(); 12];
+        game.bitboards[Piece::WhiteKing as usize].set_bit(white_king);
+        game.bitboards[Piece::BlackKing as usize].set_bit(black_king);
+        game.bitboards[piece as usize].set_bit(square);
+        game.white_occupancies = Bitboard::from_u64((1 << white_king) | (1 << square));
+        game.black_occupancies = Bitboard::from_u64(1 << black_king);
+        game.all_occupancies = game.white_occupancies.or(game.black_occupancies);
+        game.active_player = if white_to_move { Color::White } else { Color::Black };
+
+        if game.is_in_check(opposite_color(game.active_player)) { None } else { Some(game) }
+    }
+
+    //The bitbase is generated by build.rs, independently of the tables
+    #[test]
+    pub fn wdl_agrees_with_the_kpk_bitbase() {
+        let tablebases = test_tablebases();
+
+        //The bitbase only holds pawns on the a-d files
+        for pawn in (8..56).filter(|sq| sq % 8 < 4) {
+            for white_king in 0..64 {
+                for black_king in (0..64).step_by(5) {
+                    for white_to_move in [true, false] {
+                        let Some(game) = three_pieces(white_to_move, white_king, black_king, Piece::WhitePawn, pawn) else { continue };
+
+                        let win = probe_kpk(white_to_move, white_king, black_king, pawn);
+                        let expected = match (win, white_to_move) {
+                            (false, _) => Wdl::Draw,
+                            (true, true) => Wdl::Win,
+                            (true, false) => Wdl::Loss
+                        };
+                        assert_eq!(Some(expected), tablebases.probe_wdl(&game), "{}", game.to_fen());
+                    }
+                }
+            }
+        }
+    }
+
+    //KQvK is won in at most 10 moves and KRvK in at most 16 (Stroehlein, 1970), and without captures or
+    //pawn moves the DTZ is the distance to mate, so the longest wins take 19 and 31 plies
+    #[test]
+    pub fn longest_wins_match_the_known_results() {
+        let tablebases = test_tablebases();
+
+        for (piece, longest) in [(Piece::WhiteQueen, 19), (Piece::WhiteRook, 31)] {
+            let mut max_dtz = 0;
+
+            //Every position is a mirror of one with the white king in the a1-d1-d4 triangle
+            for white_king in [56, 57, 58, 59, 49, 50, 51, 42, 43, 35] {
+                for black_king in 0..64 {
+                    for square in 0..64 {
+                        let Some(game) = three_pieces(true, white_king, black_king, piece, square) else { continue };
+                        max_dtz = max_dtz.max(tablebases.probe_dtz(&game).unwrap());
+                    }
+                }
+            }
+
+            assert_eq!(longest, max_dtz);
+        }
+    }
+
+    #[test]
+    pub fn root_moves_keep_the_quickest_win() {
+        let tablebases = test_tablebases();
+
+        let game = Game::new_from_fen("7k/8/6K1/8/8/8/8/1Q6 w - - 0 1").unwrap();
+        let moves: Vec<String> = tablebases.root_moves(&game).unwrap().iter().map(|m| m.to_uci()).collect();
+        assert_eq!(vec!["b1b8"], moves);
+        assert_eq!(Some(1), tablebases.probe_dtz(&game));
+
+        let game = Game::new_from_fen("4k3/8/8/8/8/8/8/4K2Q b - - 0 1").unwrap();
+        assert!(tablebases.probe_dtz(&game).unwrap() < 0);
+    }
+}
+
@@ -19,7 +19,7 @@ pub enum TranspositionTableEntry {
         depth: u8,
         flag: HashFlag,
         score: i32,
-        //best: Move
+        best: Move
     }
 }
 
@@ -28,13 +28,13 @@ pub struct TranspositionTable {
 }
 
 impl TranspositionTableEntry {
-    pub fn new(hash: u64, depth: u8, flag: HashFlag, score: i32) -> Self {
+    pub fn new(hash: u64, depth: u8, flag: HashFlag, score: i32, best: Move) -> Self {
         Self::Record {
             hash: hash,
             depth: depth,
             flag: flag,
             score: score,
-            //best: best 
+            best
         }
     }
 }
@@ -44,7 +44,7 @@ impl TranspositionTable {
         Self{table: vec![TranspositionTableEntry::Empty; TT_SIZE].into_boxed_slice()}
     }
 
-    pub fn record(&mut self, hash: u64, score: i32, depth: u8, flag: HashFlag, ply: u8) {
+    pub fn record(&mut self, hash: u64, score: i32, depth: u8, flag: HashFlag, best: Move, ply: u8) {
         //Adjust mating scores before insertion
         let mut adjusted_score: i32 = score;
         if score < -MATE_BOUND {
@@ -53,7 +53,19 @@ impl TranspositionTable {
             adjusted_score += ply as i32;
         }
 
-        self.table[(hash % TT_SIZE as u64) as usize] = TranspositionTableEntry::new(hash, depth, flag, adjusted_score)
+        let index = (hash % TT_SIZE as u64) as usize;
+
+        //Keep the old best move if this search did not find one
+        let mut best_move = best;
+        if best_move == NULL_MOVE {
+            if let TranspositionTableEntry::Record { hash: old_hash, best: old_best, .. } = self.table[index] {
+                if old_hash == hash {
+                    best_move = old_best;
+                }
+            }
+        }
+
+        self.table[index] = TranspositionTableEntry::new(hash, depth, flag, adjusted_score, best_move)
     }
 
     pub fn probe(&mut self, p_hash: u64, p_depth: u8, p_alpha: i32, p_beta: i32, ply: u8) -> i32 {
@@ -61,7 +73,7 @@ impl TranspositionTable {
         let entry = &self.table[(p_hash % TT_SIZE as u64) as usize];
 
         match entry {
-            TranspositionTableEntry::Record { hash, depth, flag, score } => {
+            TranspositionTableEntry::Record { hash, depth, flag, score, .. } => {
                 if p_hash == *hash {
                     if *depth >= p_depth {
                         //Adjust mating scores before extraction
@@ -91,6 +103,23 @@ impl TranspositionTable {
         return UNKNOWN_SCORE;
     }
 
+    ///Returns the stored entry for the position regardless of depth, with mate scores adjusted to the ply
+    pub fn probe_entry(&self, p_hash: u64, ply: u8) -> Option<TranspositionTableEntry> {
+        match self.table[(p_hash % TT_SIZE as u64) as usize] {
+            TranspositionTableEntry::Record { hash, depth, flag, score, best } if hash == p_hash => {
+                let mut adjusted_score: i32 = score;
+                if adjusted_score < -MATE_BOUND {
+                    adjusted_score += ply as i32;
+                } else if adjusted_score > MATE_BOUND {
+                    adjusted_score -= ply as i32;
+                }
+
+                Some(TranspositionTableEntry::new(hash, depth, flag, adjusted_score, best))
+            },
+            _ => None
+        }
+    }
+
     pub fn clear(&mut self) {
         for i in 0..self.table.len() {
             self.table[i] = TranspositionTableEntry::Empty;
@@ -104,8 +133,22 @@ mod tt_tests {
 
     #[test]
     pub fn tt () {
-        let mut game = Game::new_from_fen("").unwrap();
+        let mut game = Game::new_from_start_pos();
         game.pretty_print();
-        search(&mut game, 4, -1, &IoWrapper::init(), &mut TranspositionTable::new(), &mut RepetitionTable::new());
+        search(&mut game, 4, -1, &IoWrapper::init(), &mut TranspositionTable::new(), &mut RepetitionTable::new(), &Evaluator::new());
+    }
+
+    #[test]
+    pub fn keeps_best_move_when_recording_without_one() {
+        let mut tt = TranspositionTable::new();
+        let best = Move::new_friendly(Square::e2, Square::e4, Piece::WhitePawn, Piece::None, false, true, false, false);
+
+        tt.record(1234, 50, 4, HashFlag::Exact, best, 0);
+        tt.record(1234, 10, 5, HashFlag::Alpha, NULL_MOVE, 0);
+
+        let entry = tt.probe_entry(1234, 0);
+        assert!(matches!(entry, Some(TranspositionTableEntry::Record { depth: 5, score: 10, best: stored, .. }) if stored == best));
+
+        assert!(tt.probe_entry(4321, 0).is_none());
     }
 }
\ No newline at end of file
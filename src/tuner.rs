@@ -0,0 +1,187 @@
+use std::{fs, thread, time::SystemTime};
+
+use super::*;
+
+///A position and the result of the game it was taken from, from white's point of view
+pub struct TuningPosition {
+    pub game: Game,
+    pub result: f64
+}
+
+///Reads a file of labeled positions, one per line. The result may be given as "1-0"/"0-1"/"1/2-1/2",
+///or as 1.0/0.5/0.0 in brackets, anywhere after the first four fen fields.
+///Lines written by datagen, "fen | score | result", are read too
+pub fn load_tuning_positions(path: &str) -> Result<Vec<TuningPosition>, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("Could not read '{}': {}", path, e))?;
+
+    let mut positions = Vec::new();
+    for (line_number, line) in text.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        match parse_tuning_position(line) {
+            Some(position) => positions.push(position),
+            None => return Err(format!("Line {}: could not parse '{}'", line_number + 1, line))
+        }
+    }
+
+    Ok(positions)
+}
+
+pub fn parse_tuning_position(line: &str) -> Option<TuningPosition> {
+    if line.contains('|') {
+        let mut parts = line.split('|');
+        let game = Game::new_from_fen(parts.next()?).ok()?;
+        let result = parts.next_back()?.trim().parse::<f64>().ok()?;
+        return Some(TuningPosition { game, result });
+    }
+
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    if fields.len() < 5 {
+        return None;
+    }
+
+    //Move counters don't matter to the evaluation, and are replaced by opcodes in epd files
+    let game = Game::new_from_fen(&fields[0..4].join(" ")).ok()?;
+
+    let rest = fields[4..].join(" ");
+    let result = if rest.contains("1/2-1/2") || rest.contains("[0.5]") { 0.5 }
+        else if rest.contains("1-0") || rest.contains("[1.0]") { 1.0 }
+        else if rest.contains("0-1") || rest.contains("[0.0]") { 0.0 }
+        else { return None };
+
+    Some(TuningPosition { game, result })
+}
+
+fn sigmoid(score: f64, k: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-k * score / 400.0))
+}
+
+///Mean squared error between the game results and the evaluations mapped to a win probability
+pub fn tuning_error(positions: &[TuningPosition], params: &EvalParams, k: f64) -> f64 {
+    let threads = thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+    let chunk_size = positions.len().div_ceil(threads).max(1);
+
+    let total: f64 = thread::scope(|scope| {
+        let handles: Vec<_> = positions.chunks(chunk_size).map(|chunk| {
+            scope.spawn(move || {
+                chunk.iter().map(|p| {
+                    let eval = evaluate(&p.game, params);
+                    let white_eval = if p.game.active_player == Color::White { eval } else { -eval };
+                    (p.result - sigmoid(white_eval as f64, k)).powi(2)
+                }).sum::<f64>()
+            })
+        }).collect();
+
+        handles.into_iter().map(|h| h.join().unwrap()).sum()
+    });
+
+    total / positions.len().max(1) as f64
+}
+
+///Finds the scaling constant that fits the current evaluation best, to the given number of decimals
+pub fn find_best_k(positions: &[TuningPosition], params: &EvalParams, decimals: u32) -> f64 {
+    let mut best_k = 1.0;
+    let mut best_error = tuning_error(positions, params, best_k);
+
+    let mut step = 1.0;
+    for _ in 0..decimals {
+        step /= 10.0;
+        let start = best_k;
+        for i in -10..=10 {
+            let k = start + i as f64 * step;
+            if k <= 0.0 {
+                continue;
+            }
+
+            let error = tuning_error(positions, params, k);
+            if error < best_error {
+                best_error = error;
+                best_k = k;
+            }
+        }
+    }
+
+    best_k
+}
+
+///Texel's local search: nudges every weight up or down by one as long as that lowers the error.
+///Runs until no weight improves or the iteration limit is reached
+pub fn tune(positions: &[TuningPosition], params: &EvalParams, k: f64, max_iterations: u32, verbose: bool) -> EvalParams {
+    let mut best = params.clone();
+    let mut best_error = tuning_error(positions, &best, k);
+    let start = SystemTime::now();
+
+    for iteration in 1..=max_iterations {
+        let mut improved = false;
+
+        for (name, mut values) in best.to_values() {
+            for i in 0..values.len() {
+                for delta in [1, -1] {
+                    values[i] += delta;
+
+                    let mut candidate = best.clone();
+                    candidate.set_values(&name, &values).unwrap();
+
+                    let error = tuning_error(positions, &candidate, k);
+                    if error < best_error {
+                        best_error = error;
+                        best = candidate;
+                        improved = true;
+                        break;
+                    }
+
+                    values[i] -= delta;
+                }
+            }
+        }
+
+        if verbose {
+            println!(" Iteration {}: error {:.8} after {}s", iteration, best_error, start.elapsed().unwrap().as_secs());
+        }
+
+        if !improved {
+            break;
+        }
+    }
+
+    best
+}
+
+#[cfg(test)]
+mod tuner_tests {
+    use crate::*;
+
+    #[test]
+    pub fn parses_result_formats() {
+        let epd = parse_tuning_position("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - c9 \"1/2-1/2\";").unwrap();
+        assert_eq!(epd.result, 0.5);
+        assert_eq!(epd.game.active_player, Color::Black);
+
+        let bracketed = parse_tuning_position("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1 [1.0]").unwrap();
+        assert_eq!(bracketed.result, 1.0);
+
+        let pgn_style = parse_tuning_position("4k3/8/8/8/8/8/4p3/4K3 w - - 0 1 0-1").unwrap();
+        assert_eq!(pgn_style.result, 0.0);
+
+        assert!(parse_tuning_position("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1").is_none());
+    }
+
+    #[test]
+    pub fn tuning_lowers_the_error() {
+        //Pawns win every game, so the tuner should value them higher
+        let positions: Vec<TuningPosition> = [
+            "4k3/8/8/8/8/8/3PP3/4K3 w - - 0 1 [1.0]",
+            "4k3/8/8/8/8/8/2PPP3/4K3 b - - 0 1 [1.0]",
+            "4k3/3pp3/8/8/8/8/8/4K3 w - - 0 1 [0.0]",
+            "4k3/2ppp3/8/8/8/8/8/4K3 b - - 0 1 [0.0]",
+        ].iter().map(|l| parse_tuning_position(l).unwrap()).collect();
+
+        let params = EvalParams::default();
+        let before = tuning_error(&positions, &params, 1.0);
+        let tuned = tune(&positions, &params, 1.0, 1, false);
+
+        assert!(tuning_error(&positions, &tuned, 1.0) < before);
+    }
+}
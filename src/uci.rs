@@ -0,0 +1,374 @@
+use super::*;
+
+///Limits given to a "go" command. Times are in milliseconds
+#[derive(Debug, PartialEq, Default)]
+pub struct GoParams {
+    pub wtime: Option<i64>,
+    pub btime: Option<i64>,
+    pub winc: Option<i64>,
+    pub binc: Option<i64>,
+    pub moves_to_go: Option<i64>,
+    pub move_time: Option<i64>,
+    pub depth: Option<i8>,
+    pub infinite: bool,
+    ///Play a random move instead of searching
+    pub random: bool,
+    ///Arguments we don't support, which are ignored rather than dropping the search
+    pub ignored: Vec<String>,
+}
+
+impl GoParams {
+    ///Time to spend on the move for the side to move, -1 for no limit
+    pub fn search_time(&self, color: Color) -> i64 {
+        if let Some(move_time) = self.move_time {
+            return move_time;
+        }
+
+        let (time, inc) = if color == Color::White { (self.wtime, self.winc) } else { (self.btime, self.binc) };
+        let Some(time) = time else { return -1 };
+        let inc = inc.unwrap_or(0);
+        let moves_to_go = self.moves_to_go.unwrap_or(30).max(1);
+
+        if time > 2000 {
+            time / moves_to_go + inc - 100
+        }
+        else if inc != 0 {
+            inc - 500
+        }
+        else {
+            time / moves_to_go
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    Quit,
+    Help,
+    Display,
+    ///None is the start position
+    Position { fen: Option<String>, moves: Vec<String> },
+    Perft { depth: u8, detail: bool },
+    ///"perft!", a simple perft for each depth up to this
+    PerftEach(u8),
+    ///None is the built in suite
+    Psuite { path: Option<String>, long: bool },
+    Uci,
+    NewGame,
+    IsReady,
+    SetOption { name: String, value: String },
+    Go(GoParams),
+    Eval { json: bool },
+    SaveEval(String),
+    Tune { positions: String, output: String, iterations: u32 },
+    Datagen { output: String, config: DatagenConfig },
+    PgnFens { pgn: String, output: String, skip_plies: usize },
+    TestSuite { path: String, limit: SuiteLimit },
+    BookMoves,
+    MakeBook { pgn: String, output: String, config: BookConfig },
+    Sbench,
+    Move(Vec<String>),
+}
+
+///Parses a line of input. Errors describe what was wrong with it
+pub fn parse_command(input: &str) -> Result<Command, String> {
+    let mut split = input.split_whitespace();
+    let name = split.next().ok_or("Empty command")?;
+
+    match name.to_ascii_lowercase().as_str() {
+        "exit" | "x" | "quit" => Ok(Command::Quit),
+        "help" => Ok(Command::Help),
+        "d" => Ok(Command::Display),
+        "position" => parse_position_command(split),
+        "perft" => {
+            let (first, second) = (split.next(), split.next());
+            match (first, second) {
+                (Some("simple"), Some(depth)) | (Some(depth), Some("simple")) => Ok(Command::Perft { depth: parse_value("perft", depth)?, detail: false }),
+                (Some(depth), None) => Ok(Command::Perft { depth: parse_value("perft", depth)?, detail: true }),
+                (None, _) => Err("Please provide depth".to_string()),
+                (Some(_), Some(arg)) => Err(format!("Illegal 'perft' argument: '{}'", arg))
+            }
+        },
+        "perft!" => Ok(Command::PerftEach(parse_value("perft!", split.next().ok_or("Please provide depth")?)?)),
+        "psuite" => {
+            let (mut path, mut long) = (None, false);
+            for arg in split {
+                match arg {
+                    "long" => long = true,
+                    _ if path.is_none() => path = Some(arg.to_string()),
+                    _ => return Err(format!("Illegal 'psuite' argument: '{}'", arg))
+                }
+            }
+            Ok(Command::Psuite { path, long })
+        },
+        "uci" => Ok(Command::Uci),
+        "ucinewgame" | "cleartt" => Ok(Command::NewGame),
+        "isready" => Ok(Command::IsReady),
+        "setoption" => {
+            let args = split.collect::<Vec<&str>>().join(" ");
+            match args.strip_prefix("name ").and_then(|rest| rest.split_once(" value ")) {
+                Some((name, value)) => Ok(Command::SetOption { name: name.trim().to_string(), value: value.trim().to_string() }),
+                None => Err(format!("Illegal 'setoption' command: '{}'", args))
+            }
+        },
+        "go" => parse_go_command(split),
+        "eval" => match split.next() {
+            None => Ok(Command::Eval { json: false }),
+            Some("json") => Ok(Command::Eval { json: true }),
+            Some(arg) => Err(format!("Illegal 'eval' argument: '{}'", arg))
+        },
+        "saveeval" => Ok(Command::SaveEval(split.next().ok_or("Please provide a path")?.to_string())),
+        "tune" => {
+            let (positions, output) = match (split.next(), split.next()) {
+                (Some(p), Some(o)) => (p.to_string(), o.to_string()),
+                _ => return Err("Please provide a positions file and an output file".to_string())
+            };
+            let iterations = match split.next() {
+                Some(iterations) => parse_value("tune", iterations)?,
+                None => u32::MAX
+            };
+            Ok(Command::Tune { positions, output, iterations })
+        },
+        "datagen" => parse_datagen_command(split),
+        "pgnfens" => {
+            let (pgn, output) = match (split.next(), split.next()) {
+                (Some(p), Some(o)) => (p.to_string(), o.to_string()),
+                _ => return Err("Please provide a PGN file and an output file".to_string())
+            };
+            let skip_plies = match split.next() {
+                Some(skip_plies) => parse_value("pgnfens", skip_plies)?,
+                None => 8
+            };
+            Ok(Command::PgnFens { pgn, output, skip_plies })
+        },
+        "testsuite" => {
+            let path = split.next().ok_or("Please provide an EPD file")?.to_string();
+            let limit = match (split.next(), split.next()) {
+                (Some("movetime"), Some(time)) => SuiteLimit::MoveTime(parse_value("testsuite", time)?),
+                (Some("depth"), Some(depth)) => SuiteLimit::Depth(parse_value::<i8>("testsuite", depth)?.max(1)),
+                _ => return Err("Please provide a limit: 'movetime [ms]' or 'depth [n]'".to_string())
+            };
+            Ok(Command::TestSuite { path, limit })
+        },
+        "bookmoves" => match split.next() {
+            None => Ok(Command::BookMoves),
+            Some(arg) => Err(format!("Illegal 'bookmoves' argument: '{}'", arg))
+        },
+        "makebook" => parse_makebook_command(split),
+        "sbench" => Ok(Command::Sbench),
+        "move" => Ok(Command::Move(split.map(str::to_string).collect())),
+        _ => Err(format!("Unknown command '{}'", name))
+    }
+}
+
+fn parse_value<T: std::str::FromStr>(command: &str, value: &str) -> Result<T, String> {
+    value.parse::<T>().map_err(|_| format!("Illegal value '{}' in '{}'", value, command))
+}
+
+//"position startpos [moves ...]" or "position fen [fen] [moves ...]"
+fn parse_position_command<'a>(mut split: impl Iterator<Item = &'a str>) -> Result<Command, String> {
+    let fen = match split.next() {
+        Some("startpos") => None,
+        Some("fen") => Some(split.by_ref().take_while(|token| *token != "moves").collect::<Vec<&str>>().join(" ")),
+        Some(arg) => return Err(format!("Unknown position '{}'", arg)),
+        None => return Err("Please provide a position".to_string())
+    };
+
+    //The fen consumes the "moves" keyword, startpos doesn't
+    let mut moves: Vec<String> = split.map(str::to_string).collect();
+    if fen.is_none() && !moves.is_empty() {
+        if moves[0] != "moves" {
+            return Err(format!("Illegal 'position' argument: '{}'", moves[0]));
+        }
+        moves.remove(0);
+    }
+
+    Ok(Command::Position { fen, moves })
+}
+
+fn parse_go_command<'a>(mut split: impl Iterator<Item = &'a str>) -> Result<Command, String> {
+    let mut params = GoParams::default();
+
+    while let Some(arg) = split.next() {
+        let mut value = || split.next().ok_or(format!("Missing value for '{}'", arg));
+        match arg {
+            "wtime" =>     params.wtime = Some(parse_value("go", value()?)?),
+            "btime" =>     params.btime = Some(parse_value("go", value()?)?),
+            "winc" =>      params.winc = Some(parse_value("go", value()?)?),
+            "binc" =>      params.binc = Some(parse_value("go", value()?)?),
+            "movestogo" => params.moves_to_go = Some(parse_value("go", value()?)?),
+            "movetime" =>  params.move_time = Some(parse_value("go", value()?)?),
+            //Fixed depth
+            "depth" => {
+                let depth: i8 = parse_value("go", value()?)?;
+                if depth < 1 {
+                    return Err(format!("Illegal depth {}", depth));
+                }
+                params.depth = Some(depth)
+            },
+            "infinite" => params.infinite = true,
+            //Random mover
+            "random" => params.random = true,
+            _ => params.ignored.push(arg.to_string())
+        }
+    }
+
+    Ok(Command::Go(params))
+}
+
+fn parse_datagen_command<'a>(mut split: impl Iterator<Item = &'a str>) -> Result<Command, String> {
+    let output = split.next().ok_or("Please provide an output file")?.to_string();
+
+    let mut config = DatagenConfig::default();
+    let mut depth_given = false;
+    while let Some(arg) = split.next() {
+        if arg == "pgn" {
+            config.pgn = Some(split.next().ok_or("Please provide a PGN file")?.to_string());
+            continue;
+        }
+        let value = match split.next().map(|v| v.parse::<u64>()) {
+            Some(Ok(value)) => value,
+            _ => return Err(format!("Missing or illegal value for '{}'", arg))
+        };
+        match arg {
+            "games" =>   config.games = value as usize,
+            "threads" => config.threads = (value as usize).max(1),
+            "depth" =>   { config.depth = value.clamp(1, i8::MAX as u64) as i8; depth_given = true },
+            "nodes" =>   config.nodes = value,
+            "random" =>  config.random_plies = value as usize,
+            "seed" =>    config.seed = value,
+            _ => return Err(format!("Illegal 'datagen' argument: '{}'", arg))
+        }
+    }
+
+    //A node limit replaces the default depth
+    if config.nodes != 0 && !depth_given {
+        config.depth = -1;
+    }
+
+    Ok(Command::Datagen { output, config })
+}
+
+//"makebook [pgn] [output] [args]"
+fn parse_makebook_command<'a>(mut split: impl Iterator<Item = &'a str>) -> Result<Command, String> {
+    let (pgn, output) = match (split.next(), split.next()) {
+        (Some(p), Some(o)) => (p.to_string(), o.to_string()),
+        _ => return Err("Please provide a PGN file and an output file".to_string())
+    };
+
+    let mut config = BookConfig::default();
+    while let Some(arg) = split.next() {
+        let value = split.next().ok_or(format!("Missing value for '{}'", arg))?;
+        match arg {
+            "plies" =>    config.max_plies = parse_value("makebook", value)?,
+            "mingames" => config.min_games = parse_value("makebook", value)?,
+            "minscore" => config.min_score = parse_value::<u32>("makebook", value)?.min(100),
+            _ => return Err(format!("Illegal 'makebook' argument: '{}'", arg))
+        }
+    }
+
+    Ok(Command::MakeBook { pgn, output, config })
+}
+
+#[cfg(test)]
+mod uci_tests {
+    use rand::{Rng, SeedableRng, rngs::StdRng, seq::SliceRandom};
+
+    use crate::*;
+
+    #[test]
+    pub fn commands_are_parsed() {
+        assert_eq!(parse_command("position startpos moves e2e4 e7e5").unwrap(), Command::Position { fen: None, moves: vec!["e2e4".to_string(), "e7e5".to_string()] });
+        assert_eq!(parse_command("position fen 8/8/8/8/8/8/8/K6k w - - 0 1 moves a1a2").unwrap(), Command::Position { fen: Some("8/8/8/8/8/8/8/K6k w - - 0 1".to_string()), moves: vec!["a1a2".to_string()] });
+        assert_eq!(parse_command("perft 4").unwrap(), Command::Perft { depth: 4, detail: true });
+        assert_eq!(parse_command("perft simple 4").unwrap(), Command::Perft { depth: 4, detail: false });
+        assert_eq!(parse_command("psuite long").unwrap(), Command::Psuite { path: None, long: true });
+        assert_eq!(parse_command("psuite perftsuite.epd").unwrap(), Command::Psuite { path: Some("perftsuite.epd".to_string()), long: false });
+        assert_eq!(parse_command("bookmoves").unwrap(), Command::BookMoves);
+        assert_eq!(parse_command("makebook games.pgn book.bin mingames 5 plies 16").unwrap(),
+            Command::MakeBook { pgn: "games.pgn".to_string(), output: "book.bin".to_string(), config: BookConfig { max_plies: 16, min_games: 5, min_score: 0 } });
+        assert_eq!(parse_command("setoption name SyzygyPath value /tb/a:/tb/b").unwrap(), Command::SetOption { name: "SyzygyPath".to_string(), value: "/tb/a:/tb/b".to_string() });
+
+        let go = match parse_command("go wtime 60000 btime 50000 winc 1000 binc 1000 ponder").unwrap() {
+            Command::Go(params) => params,
+            _ => panic!("Expected a go command")
+        };
+        assert_eq!(go.wtime, Some(60000));
+        assert_eq!(go.binc, Some(1000));
+        assert_eq!(go.ignored, vec!["ponder".to_string()]);
+        assert_eq!(go.search_time(Color::White), 60000 / 30 + 1000 - 100);
+        assert_eq!(go.search_time(Color::Black), 50000 / 30 + 1000 - 100);
+    }
+
+    #[test]
+    pub fn malformed_commands_are_errors() {
+        for input in ["", "   ", "foo", "position", "position somewhere", "position startpos e2e4", "perft", "perft x", "perft 3 4",
+                      "perft! -1", "psuite a.epd b.epd", "go depth", "go depth 0", "go wtime soon", "setoption name Hash", "eval xml", "tune positions.txt",
+                      "datagen", "datagen out.txt games", "datagen out.txt speed 3", "datagen out.txt pgn", "pgnfens games.pgn",
+                      "pgnfens games.pgn out.txt -3",
+                      "testsuite", "testsuite wac.epd", "testsuite wac.epd nodes 100", "testsuite wac.epd depth x", "saveeval", "bookmoves all",
+                      "makebook games.pgn", "makebook games.pgn book.bin plies", "makebook games.pgn book.bin minscore x", "makebook games.pgn book.bin depth 3"] {
+            assert!(parse_command(input).is_err(), "'{}' should not parse", input);
+        }
+    }
+
+    #[test]
+    pub fn errors_keep_the_position() {
+        let io_receiver = IoWrapper::silent();
+        let mut engine = Engine::new();
+        run_command(parse_command("position startpos moves e2e4").unwrap(), &mut engine, &io_receiver).unwrap();
+        let hash = engine.game.zobrist_hash;
+
+        assert!(run_command(parse_command("position startpos moves e2e4 e2e4").unwrap(), &mut engine, &io_receiver).is_err());
+        assert!(run_command(parse_command("position fen 8/8/8/8 w - - 0 1").unwrap(), &mut engine, &io_receiver).is_err());
+        assert!(run_command(parse_command("move e7e5 e7e5").unwrap(), &mut engine, &io_receiver).is_err());
+        assert!(run_command(parse_command("setoption name UseNNUE value maybe").unwrap(), &mut engine, &io_receiver).is_err());
+        assert!(run_command(parse_command("setoption name BookFile value missing.bin").unwrap(), &mut engine, &io_receiver).is_err());
+        assert!(run_command(parse_command("bookmoves").unwrap(), &mut engine, &io_receiver).is_err());
+        assert_eq!(engine.game.zobrist_hash, hash);
+
+        run_command(parse_command("move e7e5").unwrap(), &mut engine, &io_receiver).unwrap();
+        assert_ne!(engine.game.zobrist_hash, hash);
+    }
+
+    #[test]
+    pub fn random_command_lines_do_not_crash() {
+        const TOKENS: [&str; 42] = [
+            "position", "startpos", "fen", "moves", "go", "depth", "wtime", "btime", "winc", "binc", "movestogo", "movetime",
+            "infinite", "random", "perft", "perft!", "simple", "setoption", "name", "value", "UseNNUE", "EvalFile", "true",
+            "OwnBook", "bookmoves",
+            "move", "eval", "json", "d", "isready", "ucinewgame", "uci", "e2e4", "e7e5", "g1f3", "e1g1", "8/8/8/8/8/8/8/K6k",
+            "w", "-", "0", "-1", "300"
+        ];
+
+        let io_receiver = IoWrapper::silent();
+        let mut engine = Engine::new();
+        let mut rng = StdRng::seed_from_u64(44);
+        for _ in 0..2000 {
+            let length = rng.gen_range(0..10);
+            let mut tokens: Vec<String> = (0..length).map(|_| TOKENS.choose(&mut rng).unwrap().to_string()).collect();
+            //Some garbage, including non ASCII characters
+            if rng.gen_bool(0.2) {
+                tokens.push((0..rng.gen_range(1..6)).map(|_| rng.gen_range(' '..='\u{3ff}')).collect());
+            }
+            let line = tokens.join(" ");
+
+            let command = match parse_command(&line) {
+                Ok(command) => command,
+                Err(_) => continue
+            };
+            //Keep the run short and free of side effects
+            let command = match command {
+                Command::Quit | Command::Psuite { .. } | Command::Sbench | Command::Tune { .. } | Command::Datagen { .. } | Command::PgnFens { .. } | Command::TestSuite { .. } | Command::SaveEval(_) => continue,
+                Command::Go(mut params) => {
+                    params.depth = Some(params.depth.unwrap_or(2).min(2));
+                    Command::Go(params)
+                },
+                Command::Perft { depth, detail } => Command::Perft { depth: depth.min(2), detail },
+                Command::PerftEach(depth) => Command::PerftEach(depth.min(2)),
+                command => command
+            };
+            let _ = run_command(command, &mut engine, &io_receiver);
+        }
+    }
+}
@@ -11,6 +11,12 @@ impl IoWrapper {
         Self { receiver: init_input_thread( )}
     }
 
+    ///Never receives any input, so searches run until their depth or time limit
+    pub fn silent() -> Self {
+        let (_, receiver) = mpsc::channel::<String>();
+        Self { receiver }
+    }
+
     pub fn try_read_line(&self) -> Option<String> {
         match self.receiver.try_recv() {
             Ok(line) => Some(line.trim().to_string()),
@@ -40,11 +46,14 @@ pub fn opposite_color(color: Color) -> Color {
     if color == Color::White { Color::Black } else { Color::White }
 }
 
-pub fn square_from_string(string: &str) -> Square {
+pub fn square_from_string(string: &str) -> Option<Square> {
     let chars = string.as_bytes();
-    let x = chars[0] - 97;
-    let y = 8 - (chars[1] as char).to_digit(10).unwrap() as usize;
-    SQUARES[8 * y + x as usize]
+    if chars.len() != 2 || !(b'a'..=b'h').contains(&chars[0]) || !(b'1'..=b'8').contains(&chars[1]) {
+        return None;
+    }
+    let x = chars[0] - b'a';
+    let y = b'8' - chars[1];
+    Some(SQUARES[8 * y as usize + x as usize])
 }
 
 pub fn char_to_piece(char: char) -> Option<Piece> {
@@ -110,6 +119,8 @@ pub const LOOKUP_RANK: [usize; 64] =
 	0, 0, 0, 0, 0, 0, 0, 0
 ];
 
+pub const LIGHT_SQUARES: u64 = 0xAA55AA55AA55AA55;
+
 pub const PIECE_STRINGS: [&str; 13] = ["P", "N", "B", "R", "Q", "K", "p", "n", "b", "r", "q", "k", "None"];
 
 pub const CASTLING_RIGHTS: [u8; 64] = [
@@ -251,11 +262,13 @@ pub struct SearchResult {
     pub score: i32,
     pub depth: u8,
     pub reached_max_ply: bool,
-    pub tt_hits: u32
+    pub tt_hits: u32,
+    pub pawn_table_probes: u64,
+    pub pawn_table_hits: u64
 }
 
 impl SearchResult {
     pub fn new(cmove: Move, nodes: u64, score: i32, depth: u8, reached_max_ply: bool, tt_hits: u32) -> Self {
-        Self { best_move: cmove, nodes_visited: nodes, score: score, depth: depth, reached_max_ply: reached_max_ply, tt_hits: tt_hits }
+        Self { best_move: cmove, nodes_visited: nodes, score: score, depth: depth, reached_max_ply: reached_max_ply, tt_hits: tt_hits, pawn_table_probes: 0, pawn_table_hits: 0 }
     }
 }
\ No newline at end of file
@@ -0,0 +1,433 @@
+#!/usr/bin/env python3
+#Generates the 3 piece tables used by the tests, in the Syzygy WDL and DTZ format.
+#The positions are solved by retrograde analysis, and every value is stored as its own Huffman symbol
+#(no symbol pairing), which the format allows. Run from anywhere: python3 generate.py
+import heapq
+import os
+from array import array
+from collections import deque
+
+WDL_MAGIC = bytes([0x71, 0xE8, 0x23, 0x5D])
+DTZ_MAGIC = bytes([0xD7, 0x66, 0x0C, 0xA5])
+FLAG_STM = 1
+FLAG_WIN_PLIES = 4
+FLAG_LOSS_PLIES = 8
+FLAG_SINGLE_VALUE = 128
+
+#Piece codes of the tables: white 1-6 (P N B R Q K), black 9-14
+WHITE_CODES = {'P': 1, 'N': 2, 'B': 3, 'R': 4, 'Q': 5, 'K': 6}
+BLACK_KING = 14
+
+BLOCK_SIZE_LOG = 8
+SPAN_LOG = 7
+
+#Squares are a1 = 0 to h8 = 63, as in the tables
+def rank(sq): return sq >> 3
+def file(sq): return sq & 7
+def distance(a, b): return max(abs(rank(a) - rank(b)), abs(file(a) - file(b)))
+def off_a1h8(sq): return rank(sq) - file(sq)
+
+KING = [[s for s in range(64) if distance(sq, s) == 1] for sq in range(64)]
+
+def rays(sq, directions):
+    result = []
+    for dr, df in directions:
+        ray = []
+        r, f = rank(sq) + dr, file(sq) + df
+        while 0 <= r < 8 and 0 <= f < 8:
+            ray.append(r * 8 + f)
+            r, f = r + dr, f + df
+        result.append(ray)
+    return result
+
+ROOK_DIRECTIONS = [(1, 0), (-1, 0), (0, 1), (0, -1)]
+BISHOP_DIRECTIONS = [(1, 1), (1, -1), (-1, 1), (-1, -1)]
+RAYS = {
+    'R': [rays(sq, ROOK_DIRECTIONS) for sq in range(64)],
+    'B': [rays(sq, BISHOP_DIRECTIONS) for sq in range(64)],
+    'Q': [rays(sq, ROOK_DIRECTIONS + BISHOP_DIRECTIONS) for sq in range(64)],
+}
+PAWN_ATTACKS = [[sq + 8 + df for df in (-1, 1) if 0 <= file(sq) + df < 8 and sq < 56] for sq in range(64)]
+KNIGHT = [[s for s in range(64) if sorted((abs(rank(sq) - rank(s)), abs(file(sq) - file(s)))) == [1, 2]] for sq in range(64)]
+
+def attacks(piece, frm, to, blocker):
+    """Whether a white piece attacks a square, with the white king as the only other piece in the way"""
+    if piece == 'P':
+        return to in PAWN_ATTACKS[frm]
+    if piece == 'N':
+        return to in KNIGHT[frm]
+    for ray in RAYS[piece][frm]:
+        for sq in ray:
+            if sq == to:
+                return True
+            if sq == blocker:
+                break
+    return False
+
+def key(stm, wk, bk, x):
+    return ((stm * 64 + wk) * 64 + bk) * 64 + x
+
+SIZE = 2 * 64 * 64 * 64
+
+def legal(stm, wk, bk, x, piece):
+    if len({wk, bk, x}) < 3 or distance(wk, bk) <= 1:
+        return False
+    if piece == 'P' and not 8 <= x < 56:
+        return False
+    #The side not to move can't be in check, and only black can be
+    return stm == 1 or not attacks(piece, x, bk, wk)
+
+def moves(stm, wk, bk, x, piece, solved):
+    """(child key, zeroing) for moves staying in the table, and the WDL of the other moves, seen by the side moving next"""
+    children = []
+    outside = []
+    if stm == 0:
+        for s in KING[wk]:
+            if s != x and distance(s, bk) > 1:
+                children.append((key(1, s, bk, x), False))
+
+        if piece == 'P':
+            push = x + 8
+            if push not in (wk, bk):
+                if push >= 56:
+                    for promotion in 'QRBN':
+                        outside.append(solved[promotion][key(1, wk, bk, push)])
+                else:
+                    children.append((key(1, wk, bk, push), True))
+                    if x < 16 and push + 8 not in (wk, bk):
+                        children.append((key(1, wk, bk, push + 8), True))
+        elif piece == 'N':
+            for t in KNIGHT[x]:
+                if t not in (wk, bk):
+                    children.append((key(1, wk, bk, t), False))
+        else:
+            for ray in RAYS[piece][x]:
+                for t in ray:
+                    if t in (wk, bk):
+                        break
+                    children.append((key(1, wk, bk, t), False))
+    else:
+        for s in KING[bk]:
+            if distance(s, wk) <= 1:
+                continue
+            if s == x:
+                #Capturing the last piece draws
+                outside.append(0)
+            elif not attacks(piece, x, s, wk):
+                children.append((key(0, wk, s, x), False))
+
+    return children, outside
+
+def solve(piece, solved):
+    """WDL of every position from the side to move (-2, 0 or 2, None when illegal), and the DTZ of the decisive ones"""
+    wdl = [None] * SIZE
+    dtz = array('H', bytes(2 * SIZE))
+
+    #Edges of the game graph, grouped by child for the retrograde analysis
+    parents = [[] for _ in range(SIZE)]
+    child_count = array('H', bytes(2 * SIZE))
+    escape = bytearray(SIZE)
+    mated = []
+    queue = deque()
+
+    for k in range(SIZE):
+        x, bk, wk, stm = k & 63, (k >> 6) & 63, (k >> 12) & 63, k >> 18
+        if not legal(stm, wk, bk, x, piece):
+            continue
+        wdl[k] = 0
+
+        children, outside = moves(stm, wk, bk, x, piece, solved)
+        for child, zeroing in children:
+            parents[child].append((k, zeroing))
+        child_count[k] = len(children)
+
+        if stm == 0:
+            if -2 in outside:
+                #A promotion wins, and zeroes the counter
+                wdl[k] = 2
+                dtz[k] = 1
+                queue.append(k)
+        else:
+            escape[k] = bool(outside)
+            if not children and not outside and attacks(piece, x, bk, wk):
+                wdl[k] = -2
+                dtz[k] = 1
+                mated.append(k)
+                queue.append(k)
+
+    #WDL: black loses when all moves go to white wins, white wins when a move goes to a black loss
+    while queue:
+        k = queue.popleft()
+        for parent, _ in parents[k]:
+            if wdl[parent] != 0:
+                continue
+            if k >> 18 == 1:
+                wdl[parent] = 2
+                queue.append(parent)
+            else:
+                child_count[parent] -= 1
+                if child_count[parent] == 0 and not escape[parent]:
+                    wdl[parent] = -2
+                    queue.append(parent)
+
+    #Mates and winning pawn moves are one ply from zeroing
+    for k in mated:
+        for parent, _ in parents[k]:
+            dtz[parent] = 1
+    for k in range(0, SIZE // 2):
+        if wdl[k] == 2 and dtz[k] != 1 and piece == 'P':
+            x, bk, wk = k & 63, (k >> 6) & 63, (k >> 12) & 63
+            children, _ = moves(0, wk, bk, x, piece, solved)
+            if any(zeroing and wdl[child] == -2 for child, zeroing in children):
+                dtz[k] = 1
+
+    #DTZ: the winner takes the shortest way to zeroing and the loser the longest, so positions are finalized in
+    #increasing order. A losing position is final when all its moves are, and a win at its first losing child
+    remaining = array('H', bytes(2 * SIZE))
+    for k in range(SIZE // 2, SIZE):
+        if wdl[k] == -2:
+            x, bk, wk = k & 63, (k >> 6) & 63, (k >> 12) & 63
+            remaining[k] = len(moves(1, wk, bk, x, piece, solved)[0])
+
+    heap = [(dtz[k], k) for k in range(SIZE) if dtz[k] == 1]
+    heapq.heapify(heap)
+    final = bytearray(SIZE)
+    while heap:
+        value, k = heapq.heappop(heap)
+        if final[k]:
+            continue
+        final[k] = 1
+        for parent, zeroing in parents[k]:
+            if final[parent]:
+                continue
+            if k >> 18 == 1:
+                if wdl[k] == -2 and wdl[parent] == 2 and not zeroing and (dtz[parent] == 0 or value + 1 < dtz[parent]):
+                    dtz[parent] = value + 1
+                    heapq.heappush(heap, (value + 1, parent))
+            elif wdl[parent] == -2:
+                remaining[parent] -= 1
+                if remaining[parent] == 0:
+                    dtz[parent] = value + 1
+                    heapq.heappush(heap, (value + 1, parent))
+
+    assert all(dtz[k] > 0 for k in range(SIZE) if wdl[k] and wdl[k] != 0)
+    return wdl, dtz
+
+#Index tables, see src/syzygy.rs
+MAP_B1H1H7 = [0] * 64
+code = 0
+for sq in range(64):
+    if off_a1h8(sq) < 0:
+        MAP_B1H1H7[sq] = code
+        code += 1
+
+MAP_A1D1D4 = [0] * 64
+code = 0
+diagonal = []
+for sq in (r * 8 + f for r in range(4) for f in range(4)):
+    if off_a1h8(sq) < 0:
+        MAP_A1D1D4[sq] = code
+        code += 1
+    elif off_a1h8(sq) == 0:
+        diagonal.append(sq)
+for sq in diagonal:
+    MAP_A1D1D4[sq] = code
+    code += 1
+
+def encode_unique_pieces(squares):
+    adjust1 = int(squares[1] > squares[0])
+    adjust2 = int(squares[2] > squares[0]) + int(squares[2] > squares[1])
+    if off_a1h8(squares[0]) != 0:
+        return (MAP_A1D1D4[squares[0]] * 63 + squares[1] - adjust1) * 62 + squares[2] - adjust2
+    if off_a1h8(squares[1]) != 0:
+        return (6 * 63 + rank(squares[0]) * 28 + MAP_B1H1H7[squares[1]]) * 62 + squares[2] - adjust2
+    if off_a1h8(squares[2]) != 0:
+        return 6 * 63 * 62 + 4 * 28 * 62 + rank(squares[0]) * 7 * 28 + (rank(squares[1]) - adjust1) * 28 + MAP_B1H1H7[squares[2]]
+    return 6 * 63 * 62 + 4 * 28 * 62 + 4 * 7 * 28 + rank(squares[0]) * 7 * 6 + (rank(squares[1]) - adjust1) * 6 + rank(squares[2]) - adjust2
+
+def piece_index(squares):
+    """Index of three unique pieces without pawns, in table order"""
+    if file(squares[0]) > 3:
+        squares = [sq ^ 7 for sq in squares]
+    if rank(squares[0]) > 3:
+        squares = [sq ^ 56 for sq in squares]
+    for i, sq in enumerate(squares):
+        if off_a1h8(sq) != 0:
+            if off_a1h8(sq) > 0:
+                squares = squares[:i] + [((s >> 3) | (s << 3)) & 63 for s in squares[i:]]
+            break
+    return encode_unique_pieces(squares)
+
+def pawn_index(pawn, squares):
+    """Tablebase file and index of a pawn followed by two pieces"""
+    if file(pawn) > 3:
+        pawn ^= 7
+        squares = [sq ^ 7 for sq in squares]
+    idx = rank(pawn) - 1
+    placed = [pawn]
+    group_idx = 6
+    for sq in squares:
+        idx += (sq - sum(1 for s in placed if sq > s)) * group_idx
+        group_idx *= 64 - len(placed)
+        placed.append(sq)
+    return file(pawn), idx
+
+def huffman_lengths(frequencies):
+    heap = [(count, i, [value]) for i, (value, count) in enumerate(sorted(frequencies.items()))]
+    heapq.heapify(heap)
+    lengths = {value: 0 for value in frequencies}
+    order = len(heap)
+    while len(heap) > 1:
+        c1, _, v1 = heapq.heappop(heap)
+        c2, _, v2 = heapq.heappop(heap)
+        for value in v1 + v2:
+            lengths[value] += 1
+        heapq.heappush(heap, (c1 + c2, order, v1 + v2))
+        order += 1
+    return lengths
+
+def compress(values, flags):
+    """Header, sparse index, block lengths and blocks of one table of values"""
+    frequencies = {}
+    for value in values:
+        frequencies[value] = frequencies.get(value, 0) + 1
+    if len(frequencies) == 1:
+        return bytes([flags | FLAG_SINGLE_VALUE, values[0]]), b'', b'', b''
+
+    #Canonical code: longer codes have lower values, and symbols are numbered from the longest codes up
+    lengths = huffman_lengths(frequencies)
+    min_len, max_len = min(lengths.values()), max(lengths.values())
+    by_length = {length: sorted(v for v in lengths if lengths[v] == length) for length in range(min_len, max_len + 1)}
+
+    symbols = []
+    lowest_sym = {}
+    for length in range(max_len, min_len - 1, -1):
+        lowest_sym[length] = len(symbols)
+        symbols += by_length[length]
+
+    codes = {}
+    base = 0
+    for length in range(max_len, min_len - 1, -1):
+        for i, value in enumerate(by_length[length]):
+            codes[value] = (base + i, length)
+        if length > min_len:
+            base = (base + len(by_length[length])) // 2
+    assert base + len(by_length[min_len]) == 1 << min_len
+
+    header = bytearray([flags, BLOCK_SIZE_LOG, SPAN_LOG, 0])
+    blocks = []
+    block = []
+    bits = 0
+    for value in values:
+        length = codes[value][1]
+        if bits + length > 8 << BLOCK_SIZE_LOG:
+            blocks.append(block)
+            block = []
+            bits = 0
+        block.append(value)
+        bits += length
+    blocks.append(block)
+
+    header += len(blocks).to_bytes(4, 'little')
+    header += bytes([max_len, min_len])
+    for length in range(min_len, max_len + 1):
+        header += lowest_sym[length].to_bytes(2, 'little')
+    header += len(symbols).to_bytes(2, 'little')
+    for value in symbols:
+        #Leaves have 0xFFF as their right child and the value as their left one
+        header += bytes([value & 0xFF, (value >> 8) | 0xF0, 0xFF])
+    header += bytes(len(symbols) & 1)
+
+    data = bytearray()
+    block_lengths = bytearray()
+    starts = []
+    for block in blocks:
+        starts.append(sum(len(b) for b in blocks[:len(starts)]))
+        block_lengths += (len(block) - 1).to_bytes(2, 'little')
+        stream = 0
+        stream_bits = 0
+        for value in block:
+            code, length = codes[value]
+            stream = (stream << length) | code
+            stream_bits += length
+        stream <<= (8 << BLOCK_SIZE_LOG) - stream_bits
+        data += stream.to_bytes(1 << BLOCK_SIZE_LOG, 'big')
+
+    #Every span'th value is found from its block and offset, counted from the middle of the span
+    span = 1 << SPAN_LOG
+    sparse = bytearray()
+    block_idx = 0
+    for k in range((len(values) + span - 1) // span):
+        first = k * span
+        while block_idx + 1 < len(starts) and starts[block_idx + 1] <= first:
+            block_idx += 1
+        sparse += block_idx.to_bytes(4, 'little')
+        sparse += (first - starts[block_idx] + span // 2).to_bytes(2, 'little')
+
+    return bytes(header), bytes(sparse), bytes(block_lengths), bytes(data)
+
+def write_table(path, magic, has_pawns, pieces, parts):
+    """parts holds the compressed tables per file of the leading pawn and side to move"""
+    out = bytearray(magic)
+    out.append((has_pawns << 1) | (len(parts[0]) > 1))
+    for _ in parts:
+        out.append(0)
+        out += bytes(p | (p << 4) for p in pieces)
+    out += bytes(len(out) & 1)
+    for file_parts in parts:
+        for header, _, _, _ in file_parts:
+            out += header
+    if magic == DTZ_MAGIC:
+        out += bytes(len(out) & 1)
+    for index in (1, 2):
+        for file_parts in parts:
+            for part in file_parts:
+                out += part[index]
+    for file_parts in parts:
+        for _, _, _, data in file_parts:
+            out += bytes(-len(out) % 64)
+            out += data
+    with open(path, 'wb') as f:
+        f.write(out)
+
+def set_value(table, idx, value, position):
+    if table[idx] is not None and table[idx] != value:
+        raise Exception(f'{position} disagrees with a symmetric position')
+    table[idx] = value
+
+def generate(piece, solved, directory):
+    wdl, dtz = solve(piece, solved)
+    name = f'K{piece}vK'
+    files = 4 if piece == 'P' else 1
+    size = 6 * 63 * 62 if piece == 'P' else 31332
+    wdl_tables = [[[None] * size for _ in range(2)] for _ in range(files)]
+    dtz_tables = [[None] * size for _ in range(files)]
+
+    for k in range(SIZE):
+        if wdl[k] is None:
+            continue
+        x, bk, wk, stm = k & 63, (k >> 6) & 63, (k >> 12) & 63, k >> 18
+        if piece == 'P':
+            tb_file, idx = pawn_index(x, [wk, bk])
+        else:
+            tb_file, idx = 0, piece_index([wk, x, bk])
+
+        set_value(wdl_tables[tb_file][stm], idx, wdl[k] + 2, (stm, wk, bk, x))
+        #The DTZ tables hold white to move, in plies
+        if stm == 0 and wdl[k] == 2:
+            set_value(dtz_tables[tb_file], idx, dtz[k] - 1, (stm, wk, bk, x))
+
+    flags = FLAG_WIN_PLIES | FLAG_LOSS_PLIES
+    wdl_parts = [[compress([2 if v is None else v for v in side], 0) for side in tables] for tables in wdl_tables]
+    dtz_parts = [[compress([0 if v is None else v for v in table], flags)] for table in dtz_tables]
+    pieces = [WHITE_CODES[piece], WHITE_CODES['K'], BLACK_KING] if piece == 'P' else [WHITE_CODES['K'], WHITE_CODES[piece], BLACK_KING]
+    write_table(os.path.join(directory, name + '.rtbw'), WDL_MAGIC, piece == 'P', pieces, wdl_parts)
+    write_table(os.path.join(directory, name + '.rtbz'), DTZ_MAGIC, piece == 'P', pieces, dtz_parts)
+    return wdl
+
+if __name__ == '__main__':
+    directory = os.path.dirname(os.path.abspath(__file__))
+    solved = {}
+    for piece in 'QRBN':
+        solved[piece] = generate(piece, solved, directory)
+    generate('P', solved, directory)